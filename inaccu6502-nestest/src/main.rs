@@ -0,0 +1,132 @@
+//! Runs kevtris's `nestest.nes` in its automated mode (starting at `$C000`,
+//! no PPU needed) and compares the CPU state before every instruction
+//! against the reference `nestest.log`.
+//!
+//! Neither file is in the repo. Put them next to this crate's `Cargo.toml`,
+//! or pass their paths on the command line:
+//!
+//! ```text
+//! cargo run -p inaccu6502-nestest -- path/to/nestest.nes path/to/nestest.log
+//! ```
+
+use inaccu6502::{Cpu, Memory};
+
+const DEFAULT_ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.nes");
+const DEFAULT_LOG_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.log");
+const INES_HEADER_SIZE: usize = 16;
+/// The log starts counting at 7, because that's how long the reset sequence
+/// took.
+const STARTING_CYCLES: u64 = 7;
+
+/// Just enough of an NES for nestest: work RAM and an NROM cartridge.
+struct Nestputer {
+    ram: [u8; 2048],
+    prg: Vec<u8>,
+}
+
+impl Memory for Nestputer {
+    fn read_byte(&mut self, _cpu: &mut Cpu, address: u16) -> u8 {
+        if address < 0x2000 {
+            self.ram[(address & 0x7FF) as usize]
+        } else if address < 0x8000 {
+            // PPU, APU, and I/O. nestest's automated mode only reads these
+            // while testing addressing modes, and the log says they read as
+            // $FF.
+            0xFF
+        } else {
+            self.prg[(address as usize - 0x8000) % self.prg.len()]
+        }
+    }
+    fn write_byte(&mut self, _cpu: &mut Cpu, address: u16, data: u8) {
+        if address < 0x2000 {
+            self.ram[(address & 0x7FF) as usize] = data;
+        }
+    }
+}
+
+/// Turn a line of `nestest.log` into the same format as `show_state`. Returns
+/// `None` if the line isn't an instruction.
+fn parse_reference_line(line: &str) -> Option<String> {
+    let pc = line.get(0..4)?;
+    let mut fields = vec![pc.to_string()];
+    for token in line.get(48..)?.split_whitespace() {
+        if ["A:", "X:", "Y:", "P:", "SP:", "CYC:"]
+            .iter()
+            .any(|prefix| token.starts_with(prefix))
+        {
+            fields.push(token.to_string());
+        }
+    }
+    Some(fields.join(" "))
+}
+
+/// Unofficial opcodes are marked with a `*` right before the mnemonic. We
+/// don't do those.
+fn is_unofficial(line: &str) -> bool {
+    line.get(15..16) == Some("*")
+}
+
+fn show_state(cpu: &Cpu, cycles: u64) -> String {
+    format!(
+        "{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{cycles}",
+        cpu.get_pc(),
+        cpu.get_a(),
+        cpu.get_x(),
+        cpu.get_y(),
+        cpu.get_p(),
+        cpu.get_s(),
+    )
+}
+
+fn main() {
+    env_logger::init();
+    let mut arguments = std::env::args().skip(1);
+    let rom_path = arguments.next().unwrap_or(DEFAULT_ROM_PATH.to_string());
+    let log_path = arguments.next().unwrap_or(DEFAULT_LOG_PATH.to_string());
+    let rom = std::fs::read(&rom_path)
+        .unwrap_or_else(|x| panic!("Couldn't read {rom_path:?}: {x} (see the top of main.rs)"));
+    let reference = std::fs::read_to_string(&log_path)
+        .unwrap_or_else(|x| panic!("Couldn't read {log_path:?}: {x} (see the top of main.rs)"));
+    let prg_size = rom[4] as usize * 16384;
+    let mut nestputer = Nestputer {
+        ram: [0; 2048],
+        prg: rom[INES_HEADER_SIZE..INES_HEADER_SIZE + prg_size].to_vec(),
+    };
+    let mut cpu = Cpu::new();
+    cpu.set_pc(0xC000); // automated mode!
+    cpu.set_a(0);
+    cpu.set_x(0);
+    cpu.set_y(0);
+    cpu.set_s(0xFD);
+    cpu.set_p(0x24);
+    let mut cycles = STARTING_CYCLES;
+    let mut instructions = 0;
+    for (line_number, line) in reference.lines().enumerate() {
+        if is_unofficial(line) {
+            println!("Reached the unofficial opcodes at line {}.", line_number + 1);
+            break;
+        }
+        let Some(expected) = parse_reference_line(line) else {
+            continue;
+        };
+        let actual = show_state(&cpu, cycles);
+        if actual != expected {
+            println!("Mismatch at line {} of {log_path}!", line_number + 1);
+            println!("Reference: {line}");
+            println!("Expected:  {expected}");
+            println!("Actual:    {actual}");
+            std::process::exit(1);
+        }
+        log::trace!("{actual}");
+        cycles += cpu.step(&mut nestputer) as u64;
+        instructions += 1;
+    }
+    // nestest leaves an error code here. Zero means everything passed. (The
+    // unofficial opcodes put theirs in $03, but we never get that far.)
+    let result = nestputer.ram[0x02];
+    if result != 0 {
+        println!("nestest reported error code ${result:02X}");
+        std::process::exit(1);
+    }
+    println!("Matched the reference log for {instructions} instructions. Tests passed!");
+}
@@ -23,36 +23,52 @@ impl Memory for RAMputer {
     }
 }
 
+/// How many instructions to run between checks for a trap. (The test
+/// jumps to itself forever when something fails, and at the very end.)
+const STEPS_PER_CHECK: usize = 100_000;
+
+/// Run until the PC gets to `target`. Returns false if the CPU gets stuck
+/// somewhere else first.
+fn run_to(cpu: &mut Cpu, ramputer: &mut RAMputer, target: u16) -> bool {
+    while !cpu.run_until_pc(ramputer, target, STEPS_PER_CHECK) {
+        let old_pc = cpu.get_pc();
+        cpu.step(ramputer);
+        if cpu.get_pc() == old_pc {
+            if cpu.get_p() & inaccu6502::STATUS_D != 0 {
+                log::warn!("Failed a test, but it appears to be BCD-based, so we're skipping it.");
+                cpu.set_pc(old_pc + 2);
+            } else {
+                return false;
+            }
+        }
+    }
+    true
+}
+
 fn main() {
     env_logger::init();
     let mut ramputer = RAMputer::new();
     ramputer.ram[..BINARY.len()].copy_from_slice(BINARY);
     let mut cpu = Cpu::new();
     cpu.reset(&mut ramputer);
+    cpu.set_trace_hook(Box::new(|cpu, _| log::trace!("{cpu:?}")));
     cpu.set_pc(0x0400); // start the test!
-    loop {
-        let old_pc = cpu.get_pc();
-        // TODO: remove this
-        if old_pc == 0x09C5 {
-            println!("Skipping the BRK test. (We don't have interrupt handling yet.)");
-            cpu.set_pc(0x0A11);
-        } else if old_pc == 0x343A {
-            println!("Skipping an RTI test. (We don't have interrupt handling yet.)");
-            cpu.set_pc(0x345D);
-        }
-        log::trace!("{cpu:?}");
-        cpu.step(&mut ramputer);
-        let new_pc = cpu.get_pc();
-        if old_pc == new_pc {
-            if cpu.get_p() & inaccu6502::STATUS_D != 0 {
-                log::warn!("Failed a test, but it appears to be BCD-based, so we're skipping it.");
-                cpu.set_pc(new_pc + 2);
-            } else {
-                break;
-            }
+
+    // TODO: remove this
+    let skips = [
+        (0x09C5, 0x0A11, "the BRK test"),
+        (0x343A, 0x345D, "an RTI test"),
+    ];
+    let mut passed = true;
+    for (from, to, what) in skips {
+        if !run_to(&mut cpu, &mut ramputer, from) {
+            passed = false;
+            break;
         }
+        println!("Skipping {what}. (We don't have interrupt handling yet.)");
+        cpu.set_pc(to);
     }
-    if cpu.get_pc() == 0x3469 {
+    if passed && run_to(&mut cpu, &mut ramputer, 0x3469) {
         println!(
             "CPU entered infinite loop at ${:04X}. Tests passed!",
             cpu.get_pc()
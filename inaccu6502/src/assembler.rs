@@ -0,0 +1,154 @@
+//! Putting little 6502 programs together in Rust, so test programs (and
+//! test ROMs) don't have to be hand-assembled hex:
+//!
+//! ```
+//! use inaccu6502::Program;
+//!
+//! // A9 05 85 10
+//! let program = Program::new(0x8000).lda_imm(0x05).sta_zp(0x10).build();
+//! ```
+//!
+//! There are no labels. For branches and jumps, grab the address you want to
+//! come back to with `here` before you get there.
+
+use crate::disassembler::{MNEMONICS, MODES};
+use crate::AddressingMode;
+
+/// A program under construction. Every instruction method adds one
+/// instruction to the end and hands the program back.
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// Where the first byte goes, so branches know where they are.
+    origin: u16,
+    bytes: Vec<u8>,
+}
+
+impl Program {
+    /// Start a program that will live at `origin`.
+    pub fn new(origin: u16) -> Program {
+        Program {
+            origin,
+            bytes: vec![],
+        }
+    }
+    /// Where the next instruction will go.
+    pub fn here(&self) -> u16 {
+        self.origin.wrapping_add(self.bytes.len() as u16)
+    }
+    /// Add any instruction at all. `operand` is ignored if there isn't one,
+    /// and for branches, it's where to go (not the offset). Panics if
+    /// there's no such instruction, or if a branch can't reach.
+    pub fn instruction(mut self, mnemonic: &str, mode: AddressingMode, operand: u16) -> Program {
+        let opcode = (0..=255u8)
+            .find(|x| MNEMONICS[*x as usize] == mnemonic && MODES[*x as usize] == mode)
+            .unwrap_or_else(|| panic!("There's no {mnemonic} with {mode:?} addressing"));
+        let next = self.here().wrapping_add(1 + mode.get_operand_length());
+        self.bytes.push(opcode);
+        match mode {
+            AddressingMode::Relative => {
+                let offset = operand.wrapping_sub(next) as i16;
+                let offset = i8::try_from(offset).unwrap_or_else(|_| {
+                    panic!("{mnemonic} at {next:04X} can't reach {operand:04X}")
+                });
+                self.bytes.push(offset as u8);
+            }
+            _ => {
+                let length = mode.get_operand_length() as usize;
+                self.bytes
+                    .extend_from_slice(&operand.to_le_bytes()[..length]);
+            }
+        }
+        self
+    }
+    /// Add some bytes that aren't instructions (data, or an opcode we don't
+    /// know).
+    pub fn data(mut self, bytes: &[u8]) -> Program {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+    pub fn build(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Make a method for every instruction in each addressing mode. They take no
+/// operand, a byte, a word, or (for branches) where to branch to.
+macro_rules! instructions {
+    ($($mode:ident: $kind:ident [$($name:ident $mnemonic:literal),* $(,)?])*) => {
+        impl Program {
+            $($(instructions!(@method $kind $mode $name $mnemonic);)*)*
+        }
+    };
+    (@method none $mode:ident $name:ident $mnemonic:literal) => {
+        pub fn $name(self) -> Program {
+            self.instruction($mnemonic, AddressingMode::$mode, 0)
+        }
+    };
+    (@method byte $mode:ident $name:ident $mnemonic:literal) => {
+        pub fn $name(self, operand: u8) -> Program {
+            self.instruction($mnemonic, AddressingMode::$mode, operand as u16)
+        }
+    };
+    (@method word $mode:ident $name:ident $mnemonic:literal) => {
+        pub fn $name(self, operand: u16) -> Program {
+            self.instruction($mnemonic, AddressingMode::$mode, operand)
+        }
+    };
+}
+
+instructions! {
+    Implied: none [
+        brk "BRK", clc "CLC", cld "CLD", cli "CLI", clv "CLV", dex "DEX", dey "DEY",
+        inx "INX", iny "INY", nop "NOP", pha "PHA", php "PHP", pla "PLA", plp "PLP",
+        rti "RTI", rts "RTS", sec "SEC", sed "SED", sei "SEI", tax "TAX", tay "TAY",
+        tsx "TSX", txa "TXA", txs "TXS", tya "TYA",
+    ]
+    Accumulator: none [asl_a "ASL", lsr_a "LSR", rol_a "ROL", ror_a "ROR"]
+    Immediate: byte [
+        adc_imm "ADC", and_imm "AND", cmp_imm "CMP", cpx_imm "CPX", cpy_imm "CPY",
+        eor_imm "EOR", lda_imm "LDA", ldx_imm "LDX", ldy_imm "LDY", ora_imm "ORA",
+        sbc_imm "SBC",
+    ]
+    ZeroPage: byte [
+        adc_zp "ADC", and_zp "AND", asl_zp "ASL", bit_zp "BIT", cmp_zp "CMP", cpx_zp "CPX",
+        cpy_zp "CPY", dec_zp "DEC", eor_zp "EOR", inc_zp "INC", lda_zp "LDA", ldx_zp "LDX",
+        ldy_zp "LDY", lsr_zp "LSR", ora_zp "ORA", rol_zp "ROL", ror_zp "ROR", sbc_zp "SBC",
+        sta_zp "STA", stx_zp "STX", sty_zp "STY",
+    ]
+    ZeroPageX: byte [
+        adc_zpx "ADC", and_zpx "AND", asl_zpx "ASL", cmp_zpx "CMP", dec_zpx "DEC",
+        eor_zpx "EOR", inc_zpx "INC", lda_zpx "LDA", ldy_zpx "LDY", lsr_zpx "LSR",
+        ora_zpx "ORA", rol_zpx "ROL", ror_zpx "ROR", sbc_zpx "SBC", sta_zpx "STA",
+        sty_zpx "STY",
+    ]
+    ZeroPageY: byte [ldx_zpy "LDX", stx_zpy "STX"]
+    Absolute: word [
+        adc_abs "ADC", and_abs "AND", asl_abs "ASL", bit_abs "BIT", cmp_abs "CMP",
+        cpx_abs "CPX", cpy_abs "CPY", dec_abs "DEC", eor_abs "EOR", inc_abs "INC",
+        jmp "JMP", jsr "JSR", lda_abs "LDA", ldx_abs "LDX", ldy_abs "LDY", lsr_abs "LSR",
+        ora_abs "ORA", rol_abs "ROL", ror_abs "ROR", sbc_abs "SBC", sta_abs "STA",
+        stx_abs "STX", sty_abs "STY",
+    ]
+    AbsoluteX: word [
+        adc_abx "ADC", and_abx "AND", asl_abx "ASL", cmp_abx "CMP", dec_abx "DEC",
+        eor_abx "EOR", inc_abx "INC", lda_abx "LDA", ldy_abx "LDY", lsr_abx "LSR",
+        ora_abx "ORA", rol_abx "ROL", ror_abx "ROR", sbc_abx "SBC", sta_abx "STA",
+    ]
+    AbsoluteY: word [
+        adc_aby "ADC", and_aby "AND", cmp_aby "CMP", eor_aby "EOR", lda_aby "LDA",
+        ldx_aby "LDX", ora_aby "ORA", sbc_aby "SBC", sta_aby "STA",
+    ]
+    Indirect: word [jmp_ind "JMP"]
+    ZeroPageXIndexedIndirect: byte [
+        adc_izx "ADC", and_izx "AND", cmp_izx "CMP", eor_izx "EOR", lda_izx "LDA",
+        ora_izx "ORA", sbc_izx "SBC", sta_izx "STA",
+    ]
+    ZeroPageIndirectYIndexed: byte [
+        adc_izy "ADC", and_izy "AND", cmp_izy "CMP", eor_izy "EOR", lda_izy "LDA",
+        ora_izy "ORA", sbc_izy "SBC", sta_izy "STA",
+    ]
+    Relative: word [
+        bcc "BCC", bcs "BCS", beq "BEQ", bmi "BMI", bne "BNE", bpl "BPL", bvc "BVC",
+        bvs "BVS",
+    ]
+}
@@ -14,6 +14,39 @@ const RESET_VECTOR: u16 = 0xFFFC;
 const IRQ_VECTOR: u16 = 0xFFFE;
 const BYTE_SIGN_BIT: u8 = 0x80;
 const BYTE_CARRIED_BIT: u16 = 0b1_0000_0000;
+/// How many cycles an interrupt (NMI or IRQ, not BRK) takes to get going.
+const INTERRUPT_CYCLES: u32 = 7;
+
+/// How many cycles the unofficial immediate opcodes (ANC, ALR, ARR, and AXS)
+/// take, if we're doing them. (Short, so the table below still lines up.)
+const U: u8 = if cfg!(feature = "undocumented-opcodes") {
+    2
+} else {
+    0
+};
+
+/// How many cycles each opcode takes, not counting page crossings or taken
+/// branches. Zero means we don't know that opcode (yet).
+#[rustfmt::skip]
+const OPCODE_CYCLES: [u8; 256] = [
+    //  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+        7, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, U, 0, 4, 6, 0, // 0x
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 1x
+        6, 6, 0, 0, 3, 3, 5, 0, 4, 2, 2, U, 4, 4, 6, 0, // 2x
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 3x
+        6, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, U, 3, 4, 6, 0, // 4x
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 5x
+        6, 6, 0, 0, 0, 3, 5, 0, 4, 2, 2, U, 5, 4, 6, 0, // 6x
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 7x
+        0, 6, 0, 0, 3, 3, 3, 0, 2, 0, 2, 0, 4, 4, 4, 0, // 8x
+        2, 6, 0, 0, 4, 4, 4, 0, 2, 5, 2, 0, 0, 5, 0, 0, // 9x
+        2, 6, 2, 0, 3, 3, 3, 0, 2, 2, 2, 0, 4, 4, 4, 0, // Ax
+        2, 5, 0, 0, 4, 4, 4, 0, 2, 4, 2, 0, 4, 4, 4, 0, // Bx
+        2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, U, 4, 4, 6, 0, // Cx
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // Dx
+        2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0, // Ex
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // Fx
+];
 
 pub struct Cpu {
     /// The accumulator. Where math operations can happen.
@@ -32,7 +65,28 @@ pub struct Cpu {
     /// an "active low" signal)
     nmi_signal: bool,
     nmi_signal_last_step: bool,
+    /// Set by the indexed addressing modes when indexing carried into the
+    /// high byte of the address. Instructions that only *read* from such an
+    /// address take an extra cycle.
+    page_crossed: bool,
+    /// Cycles the current instruction took on top of `OPCODE_CYCLES`.
+    extra_cycles: u32,
+    /// Cycles the CPU has to sit out because something else (OAM DMA) took
+    /// over the bus. They get tacked onto the current step.
+    stall_cycles: u32,
+    /// How many cycles have gone by since the last reset.
+    cycle_count: u64,
+    /// How many instructions have run since the last reset. (Interrupts
+    /// don't count.)
+    instruction_count: u64,
+    /// Called after every opcode fetch. See `set_trace_hook`.
+    trace_hook: Option<TraceHook>,
 }
+
+/// Something that wants to hear about every instruction. Gets the CPU (as of
+/// right after the opcode was fetched) and the opcode.
+pub type TraceHook = Box<dyn FnMut(&Cpu, u8) + Send>;
+
 impl Debug for Cpu {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
         write!(
@@ -132,22 +186,38 @@ where
 
 impl Cpu {
     pub fn new() -> Cpu {
+        // What these are at power-on isn't specified, but on most 6502s they
+        // come up as zeroes. (`reset` does the rest.)
         return Cpu {
-            a: 255,
-            x: 255,
-            y: 255,
-            s: 255,
-            p: 255,
-            pc: 255,
+            a: 0,
+            x: 0,
+            y: 0,
+            s: 0,
+            p: STATUS_1,
+            pc: 0,
             nmi_signal: false,
             nmi_signal_last_step: false,
+            page_crossed: false,
+            extra_cycles: 0,
+            stall_cycles: 0,
+            cycle_count: 0,
+            instruction_count: 0,
+            trace_hook: None,
         };
     }
 
+    /// Do what the 6502 does when its reset line is pulled. It goes through
+    /// the motions of an interrupt, except that the writes to the stack turn
+    /// into reads, so S goes down by 3 but nothing gets pushed. (That's how S
+    /// ends up being $FD after power-on.) Interrupts get disabled, and the
+    /// other flags stay as they were. The cycle and instruction counts start
+    /// over from zero.
     pub fn reset<M: Memory>(&mut self, memory: &mut M) {
-        let a = memory.read_byte(self, RESET_VECTOR);
-        let b = memory.read_byte(self, RESET_VECTOR + 1);
-        self.pc = u16::from_le_bytes([a, b]);
+        self.cycle_count = 0;
+        self.instruction_count = 0;
+        self.s = self.s.wrapping_sub(3);
+        self.p = set_bit(self.p, STATUS_I);
+        self.pc = memory.read_word(self, RESET_VECTOR);
     }
 
     fn read_pc_and_post_inc<M: Memory>(&mut self, memory: &mut M) -> u8 {
@@ -191,6 +261,7 @@ impl Cpu {
         memory: &mut M,
     ) {
         let am = AM::new(self, memory);
+        self.charge_for_page_crossing();
         let value = am.get_value(self, memory);
         Target::new(self, memory).put_value(self, memory, value);
         self.assign_status_nz_for_result(value);
@@ -205,19 +276,25 @@ impl Cpu {
     }
     fn or_accumulator<AM: ReadAddressingMode<M>, M: Memory>(&mut self, memory: &mut M) {
         let am = AM::new(self, memory);
+        self.charge_for_page_crossing();
         self.a |= am.get_value(self, memory);
         self.assign_status_nz_for_result(self.a);
     }
     fn and_accumulator<AM: ReadAddressingMode<M>, M: Memory>(&mut self, memory: &mut M) {
         let am = AM::new(self, memory);
+        self.charge_for_page_crossing();
         self.a &= am.get_value(self, memory);
         self.assign_status_nz_for_result(self.a);
     }
     fn xor_accumulator<AM: ReadAddressingMode<M>, M: Memory>(&mut self, memory: &mut M) {
         let am = AM::new(self, memory);
+        self.charge_for_page_crossing();
         self.a ^= am.get_value(self, memory);
         self.assign_status_nz_for_result(self.a);
     }
+    /// BIT: Z is whether `A & value` is zero (*not* whether they're equal),
+    /// and N and V are copied straight from bits 7 and 6 of `value`. Games
+    /// use this to poll hardware flags without touching A.
     fn bit_test<AM: ReadAddressingMode<M>, M: Memory>(&mut self, memory: &mut M) {
         let am = AM::new(self, memory);
         let value = am.get_value(self, memory);
@@ -232,6 +309,7 @@ impl Cpu {
         subtraction: bool,
     ) {
         let am = AM::new(self, memory);
+        self.charge_for_page_crossing();
         let r = R::new(self, memory);
         let thing1 = r.get_value(self, memory);
         let thing2 = if subtraction {
@@ -248,6 +326,9 @@ impl Cpu {
         } else {
             0
         };
+        // The NES's 2A03 doesn't have decimal mode, so neither do we (yet).
+        // If it ever shows up, it only applies to ADC and SBC: a compare
+        // (`discard_result`) must stay binary no matter what D says.
         let result = thing1 as u16 + thing2 as u16 + thing3;
         //  0  1  0
         // 69 72 C4
@@ -269,6 +350,15 @@ impl Cpu {
             r.put_value(self, memory, result);
         }
     }
+    /// CMP, CPX, and CPY: subtract without storing the result, just to see
+    /// what the flags say. Carry means "register >= value", as unsigned
+    /// numbers. This is always done in binary, even in decimal mode.
+    fn compare<R: WriteAddressingMode<M>, AM: ReadAddressingMode<M>, M: Memory>(
+        &mut self,
+        memory: &mut M,
+    ) {
+        self.perform_alu_operation::<R, AM, M>(memory, false, true, true)
+    }
     fn arithmetic_shift_left<AM: WriteAddressingMode<M>, M: Memory>(&mut self, memory: &mut M) {
         let am = AM::new(self, memory);
         let value = am.get_value(self, memory);
@@ -310,6 +400,52 @@ impl Cpu {
         self.p = assign_bit(self.p, STATUS_C, carry_out);
     }
 
+    /// ANC (unofficial): AND, and then copy bit 7 of the result into carry,
+    /// like an ASL or ROL would have.
+    #[cfg(feature = "undocumented-opcodes")]
+    fn and_with_carry<M: Memory>(&mut self, memory: &mut M) {
+        self.and_accumulator::<Immediate, _>(memory);
+        self.p = assign_bit(self.p, STATUS_C, is_bit_set(self.a, BYTE_SIGN_BIT));
+    }
+    /// ALR (unofficial): AND, then LSR A. Nothing weird about it.
+    #[cfg(feature = "undocumented-opcodes")]
+    fn and_shift_right<M: Memory>(&mut self, memory: &mut M) {
+        self.and_accumulator::<Immediate, _>(memory);
+        self.logical_shift_right::<RegisterA, _>(memory);
+    }
+    /// ARR (unofficial): AND, then ROR A, except that C and V come out of
+    /// the adder instead of the shifter. C is bit 6 of the result, and V is
+    /// bit 6 XOR bit 5. (On a 6502 with decimal mode, it gets weirder still,
+    /// but the NES doesn't have one.)
+    #[cfg(feature = "undocumented-opcodes")]
+    fn and_rotate_right<M: Memory>(&mut self, memory: &mut M) {
+        self.and_accumulator::<Immediate, _>(memory);
+        self.rotate_right::<RegisterA, _>(memory);
+        let bit_6 = is_bit_set(self.a, 0b0100_0000);
+        let bit_5 = is_bit_set(self.a, 0b0010_0000);
+        self.p = assign_bit(self.p, STATUS_C, bit_6);
+        self.p = assign_bit(self.p, STATUS_V, bit_6 != bit_5);
+    }
+    /// AXS (unofficial, also called SBX): X = (A AND X) - value. The flags
+    /// come out like CMP's, not SBC's: the carry going in doesn't count, and
+    /// V doesn't change.
+    #[cfg(feature = "undocumented-opcodes")]
+    fn and_x_subtract<M: Memory>(&mut self, memory: &mut M) {
+        let value = Immediate::new(self, memory).get_value(self, memory);
+        let and = self.a & self.x;
+        self.p = assign_bit(self.p, STATUS_C, and >= value);
+        self.x = self.assign_status_nz_for_result(and.wrapping_sub(value));
+    }
+
+    /// Instructions that only read take one more cycle if their indexed
+    /// address crossed a page. (Writes and read-modify-writes always take
+    /// that cycle, so it's already in their `OPCODE_CYCLES`.)
+    fn charge_for_page_crossing(&mut self) {
+        if self.page_crossed {
+            self.extra_cycles += 1;
+        }
+    }
+
     /// Set the N and Z bits in the status register according to the given
     /// result. (Return that same result that was passed in, for convenience.)
     fn assign_status_nz_for_result(&mut self, result: u8) -> u8 {
@@ -338,6 +474,12 @@ impl Cpu {
         // offset 255 -> address - 1
         let potential_destination = self.pc.wrapping_add(offset as u16);
         if should_branch {
+            // One extra cycle for taking the branch, and another one if we
+            // landed on a different page.
+            self.extra_cycles += 1;
+            if potential_destination & 0xFF00 != self.pc & 0xFF00 {
+                self.extra_cycles += 1;
+            }
             self.pc = potential_destination;
         }
     }
@@ -347,7 +489,18 @@ impl Cpu {
     }
 
     pub fn set_irq_signal(&mut self, active: bool) {
-        todo!("IRQ signal");
+        crate::unimplemented_feature!((), "IRQ signal");
+    }
+
+    /// Make the CPU sit out some cycles, because something else is using the
+    /// bus. (OAM DMA, for example.)
+    pub fn stall(&mut self, cycles: u32) {
+        self.stall_cycles += cycles;
+    }
+
+    /// Whether the current instruction started on an odd cycle.
+    pub fn is_on_odd_cycle(&self) -> bool {
+        !self.cycle_count.is_multiple_of(2)
     }
 
     fn do_interrupt<M: Memory>(
@@ -363,24 +516,65 @@ impl Cpu {
         // Save the status bit for later restoration (but with the B bit clear)
         self.push_byte(memory, assign_bit(self.p, STATUS_B, is_actually_a_brk));
         // Find out what address to jump to
-        self.pc = u16::from_le_bytes([
-            memory.read_byte(self, vector_address),
-            memory.read_byte(self, vector_address + 1),
-        ]);
+        self.pc = memory.read_word(self, vector_address);
         // Disable interrupts
         self.p = set_bit(self.p, STATUS_I);
     }
 
-    pub fn step<M: Memory>(&mut self, memory: &mut M) {
-        if !self.nmi_signal_last_step && self.nmi_signal {
+    /// Whether the next `step` is going to start handling an NMI, instead of
+    /// running an instruction.
+    pub fn is_nmi_pending(&self) -> bool {
+        !self.nmi_signal_last_step && self.nmi_signal
+    }
+
+    /// Execute one instruction (or start handling one interrupt). Returns how
+    /// many cycles that took.
+    pub fn step<M: Memory>(&mut self, memory: &mut M) -> u32 {
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+        let cycles = if self.is_nmi_pending() {
             self.nmi_signal_last_step = self.nmi_signal;
             self.do_interrupt(memory, NMI_VECTOR, false);
-            return;
+            INTERRUPT_CYCLES
+        } else {
+            self.nmi_signal_last_step = self.nmi_signal;
+            let opcode = self.execute_instruction(memory);
+            self.instruction_count += 1;
+            OPCODE_CYCLES[opcode as usize] as u32 + self.extra_cycles
+        };
+        let cycles = cycles + std::mem::take(&mut self.stall_cycles);
+        self.cycle_count += cycles as u64;
+        cycles
+    }
+
+    /// Keep stepping until the PC is `target` (that is, until the
+    /// instruction at `target` is about to run), but not more than
+    /// `max_steps` times. Returns whether we got there. Handling an
+    /// interrupt counts as a step, just like it does for `step`.
+    pub fn run_until_pc<M: Memory>(
+        &mut self,
+        memory: &mut M,
+        target: u16,
+        max_steps: usize,
+    ) -> bool {
+        for _ in 0..max_steps {
+            if self.pc == target {
+                return true;
+            }
+            self.step(memory);
         }
-        self.nmi_signal_last_step = self.nmi_signal;
+        self.pc == target
+    }
+
+    /// Fetch and execute one instruction. Returns the opcode.
+    fn execute_instruction<M: Memory>(&mut self, memory: &mut M) -> u8 {
         //eprintln!("PC is {:X}", self.pc);
         let opcode = self.read_pc_and_post_inc(memory);
         //eprintln!("Opcode is {:02X}", opcode);
+        if let Some(mut hook) = self.trace_hook.take() {
+            hook(self, opcode);
+            self.trace_hook = Some(hook);
+        }
         match opcode {
             // BRK xx
             // BReaK the computer
@@ -411,6 +605,10 @@ impl Cpu {
             // ASL A
             // Arithmetic Shift Left (accumulator)
             0x0A => self.arithmetic_shift_left::<RegisterA, _>(memory),
+            // ANC #imm (unofficial, and so is $2B)
+            // AND, with Carry from bit 7
+            #[cfg(feature = "undocumented-opcodes")]
+            0x0B | 0x2B => self.and_with_carry(memory),
             // ORA abs
             // OR with Accumulator (absolute)
             0x0D => self.or_accumulator::<Absolute, _>(memory),
@@ -535,6 +733,10 @@ impl Cpu {
             // LSR A
             // Logical Shift Right (accumulator)
             0x4A => self.logical_shift_right::<RegisterA, _>(memory),
+            // ALR #imm (unofficial)
+            // AND, then Logical shift Right
+            #[cfg(feature = "undocumented-opcodes")]
+            0x4B => self.and_shift_right(memory),
             // JMP abs
             // JuMP
             0x4C => self.pc = Absolute::new(self, memory).get_address(),
@@ -604,12 +806,23 @@ impl Cpu {
             // ROR A
             // ROtate Right (accumulator)
             0x6A => self.rotate_right::<RegisterA, _>(memory),
+            // ARR #imm (unofficial)
+            // AND, then Rotate Right (with strange flags)
+            #[cfg(feature = "undocumented-opcodes")]
+            0x6B => self.and_rotate_right(memory),
             // JMP (abs)
             // JuMP (absolute indirect)
             0x6C => {
                 let address_of_address = Absolute::new(self, memory).get_address();
+                // The famous bug: the high byte is fetched without carrying
+                // into the high byte of the pointer, so JMP ($10FF) takes
+                // its high byte from $1000, *not* $1100. Don't "fix" this!
+                let [pointer_low, pointer_high] = address_of_address.to_le_bytes();
                 let destination_low = memory.read_byte(self, address_of_address);
-                let destination_high = memory.read_byte(self, address_of_address.wrapping_add(1));
+                let destination_high = memory.read_byte(
+                    self,
+                    u16::from_le_bytes([pointer_low.wrapping_add(1), pointer_high]),
+                );
                 self.pc = u16::from_le_bytes([destination_low, destination_high]);
             }
             // ADC abs
@@ -691,7 +904,7 @@ impl Cpu {
             // STA zp,X
             // STore Accumulator (zero page x-indexed)
             0x95 => self.store::<RegisterA, ZeroPageXIndexed, _>(memory),
-            // STX zp,X
+            // STX zp,Y
             // STore X (zero page Y-indexed)
             0x96 => self.store::<RegisterX, ZeroPageYIndexed, _>(memory),
             // TYA
@@ -777,20 +990,16 @@ impl Cpu {
             0xBE => self.load::<RegisterX, AbsoluteYIndexed, _>(memory),
             // CPY #imm
             // ComPare Y (immediate)
-            0xC0 => {
-                self.perform_alu_operation::<RegisterY, Immediate, _>(memory, false, true, true)
-            }
+            0xC0 => self.compare::<RegisterY, Immediate, _>(memory),
             // CMP (zp,X)
             // CoMPare accumulator (zero page X-indexed indirect)
-            0xC1 => self.perform_alu_operation::<RegisterA, ZeroPageXIndexedIndirect, _>(
-                memory, false, true, true,
-            ),
+            0xC1 => self.compare::<RegisterA, ZeroPageXIndexedIndirect, _>(memory),
             // CPY zp
             // ComPare Y (zero page)
-            0xC4 => self.perform_alu_operation::<RegisterY, ZeroPage, _>(memory, false, true, true),
+            0xC4 => self.compare::<RegisterY, ZeroPage, _>(memory),
             // CMP zp
             // CoMPare accumulator (zero page)
-            0xC5 => self.perform_alu_operation::<RegisterA, ZeroPage, _>(memory, false, true, true),
+            0xC5 => self.compare::<RegisterA, ZeroPage, _>(memory),
             // DEC zp
             // DECrement (zero page)
             0xC6 => self.decrement::<ZeroPage, _>(memory),
@@ -799,18 +1008,20 @@ impl Cpu {
             0xC8 => self.increment::<RegisterY, _>(memory),
             // CMP #imm
             // CoMPare accumulator (immediate)
-            0xC9 => {
-                self.perform_alu_operation::<RegisterA, Immediate, _>(memory, false, true, true)
-            }
+            0xC9 => self.compare::<RegisterA, Immediate, _>(memory),
             // DEC X or DEX
             // DECrement X
             0xCA => self.decrement::<RegisterX, _>(memory),
+            // AXS #imm (unofficial)
+            // A AND X, minus immediate, into X
+            #[cfg(feature = "undocumented-opcodes")]
+            0xCB => self.and_x_subtract(memory),
             // CPY abs
             // ComPare Y (absolute)
-            0xCC => self.perform_alu_operation::<RegisterY, Absolute, _>(memory, false, true, true),
+            0xCC => self.compare::<RegisterY, Absolute, _>(memory),
             // CMP abs
             // CoMPare accumulator (absolute)
-            0xCD => self.perform_alu_operation::<RegisterA, Absolute, _>(memory, false, true, true),
+            0xCD => self.compare::<RegisterA, Absolute, _>(memory),
             // DEC abs
             // DECrement (absolute)
             0xCE => self.decrement::<Absolute, _>(memory),
@@ -819,13 +1030,10 @@ impl Cpu {
             0xD0 => self.handle_branch_operation(memory, !is_bit_set(self.p, STATUS_Z)),
             // CMP (zp),Y
             // CoMPare accumulator (zero page indirect Y-indexed)
-            0xD1 => self.perform_alu_operation::<RegisterA, ZeroPageIndirectYIndexed, _>(
-                memory, false, true, true,
-            ),
+            0xD1 => self.compare::<RegisterA, ZeroPageIndirectYIndexed, _>(memory),
             // CMP zp,X
             // CoMPare accumulator (zero page x-indexed)
-            0xD5 => self
-                .perform_alu_operation::<RegisterA, ZeroPageXIndexed, _>(memory, false, true, true),
+            0xD5 => self.compare::<RegisterA, ZeroPageXIndexed, _>(memory),
             // DEC zp,X
             // DECrement (zero page X-indexed)
             0xD6 => self.decrement::<ZeroPageXIndexed, _>(memory),
@@ -834,20 +1042,16 @@ impl Cpu {
             0xD8 => self.p = clear_bit(self.p, STATUS_D),
             // CMP abs,Y
             // CoMPare accumulator (absolute Y-indexed)
-            0xD9 => self
-                .perform_alu_operation::<RegisterA, AbsoluteYIndexed, _>(memory, false, true, true),
+            0xD9 => self.compare::<RegisterA, AbsoluteYIndexed, _>(memory),
             // CMP abs,X
             // CoMPare accumulator (absolute X-indexed)
-            0xDD => self
-                .perform_alu_operation::<RegisterA, AbsoluteXIndexed, _>(memory, false, true, true),
+            0xDD => self.compare::<RegisterA, AbsoluteXIndexed, _>(memory),
             // DEC abs,X
             // DECrement (absolute X-indexed)
             0xDE => self.decrement::<AbsoluteXIndexed, _>(memory),
             // CPX #imm
             // ComPare X (immediate)
-            0xE0 => {
-                self.perform_alu_operation::<RegisterX, Immediate, _>(memory, false, true, true)
-            }
+            0xE0 => self.compare::<RegisterX, Immediate, _>(memory),
             // SBC (zp,X)
             // SuBtract with Carry (zero page X-indexed indirect)
             0xE1 => self.perform_alu_operation::<RegisterA, ZeroPageXIndexedIndirect, _>(
@@ -855,7 +1059,7 @@ impl Cpu {
             ),
             // CPX zp
             // ComPare X (zero page)
-            0xE4 => self.perform_alu_operation::<RegisterX, ZeroPage, _>(memory, false, true, true),
+            0xE4 => self.compare::<RegisterX, ZeroPage, _>(memory),
             // SBC zp
             // SuBtract with Carry (zero page)
             0xE5 => self.perform_alu_operation::<RegisterA, ZeroPage, _>(memory, true, false, true),
@@ -875,7 +1079,7 @@ impl Cpu {
             0xEA => (),
             // CPX abs
             // ComPare X (absolute)
-            0xEC => self.perform_alu_operation::<RegisterX, Absolute, _>(memory, false, true, true),
+            0xEC => self.compare::<RegisterX, Absolute, _>(memory),
             // SBC abs
             // SuBtract with Carry (absolute)
             0xED => self.perform_alu_operation::<RegisterA, Absolute, _>(memory, true, false, true),
@@ -923,6 +1127,7 @@ impl Cpu {
         //   Some(x) => x,
         //   None => panic!("something else!"),
         // };
+        opcode
     }
     // Ways to inspect the state of the CPU, for debugging and visualization
     // purposes.
@@ -944,6 +1149,26 @@ impl Cpu {
     pub fn get_p(&self) -> u8 {
         self.p
     }
+    /// How many cycles have gone by since the last reset, counting
+    /// interrupts and stalls.
+    pub fn get_cycle_count(&self) -> u64 {
+        self.cycle_count
+    }
+    /// How many instructions have run since the last reset, not counting
+    /// interrupts.
+    pub fn get_instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+    /// Call `hook` every time an opcode is fetched, before the instruction
+    /// does anything. Note that by then the PC has already moved past the
+    /// opcode, so the instruction starts at `get_pc() - 1`. Only one hook at a
+    /// time; setting a new one replaces the old one.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+    pub fn remove_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
     // The real 6502 has this feature. They regret adding it. I don't. I think
     // it's rad!
     pub fn set_overflow(&mut self) {
@@ -976,3 +1201,224 @@ impl Cpu {
         self.p = nu
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    /// Where `load` puts the program, and points the reset vector.
+    const ORIGIN: u16 = 0x8000;
+
+    /// Plain 64KiB of RAM.
+    struct Ram([u8; 0x10000]);
+
+    impl Memory for Ram {
+        fn read_byte(&mut self, _cpu: &mut Cpu, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+        fn write_byte(&mut self, _cpu: &mut Cpu, address: u16, data: u8) {
+            self.0[address as usize] = data;
+        }
+    }
+
+    /// A freshly reset CPU, about to run `program`.
+    fn load(program: Program) -> (Cpu, Ram) {
+        let program = program.build();
+        let mut ram = Ram([0; 0x10000]);
+        ram.0[ORIGIN as usize..ORIGIN as usize + program.len()].copy_from_slice(&program);
+        ram.0[RESET_VECTOR as usize..][..2].copy_from_slice(&ORIGIN.to_le_bytes());
+        let mut cpu = Cpu::new();
+        cpu.reset(&mut ram);
+        (cpu, ram)
+    }
+
+    /// Run `program` from reset, for `steps` steps.
+    fn run(program: Program, steps: usize) -> (Cpu, Ram) {
+        let (mut cpu, mut ram) = load(program);
+        for _ in 0..steps {
+            cpu.step(&mut ram);
+        }
+        (cpu, ram)
+    }
+
+    #[test]
+    fn jmp_indirect_wraps_within_the_page() {
+        let (mut cpu, mut ram) = load(Program::new(ORIGIN).jmp_ind(0x10FF));
+        ram.0[0x10FF] = 0x34;
+        ram.0[0x1000] = 0x12;
+        ram.0[0x1100] = 0x56;
+        cpu.step(&mut ram);
+        assert_eq!(cpu.get_pc(), 0x1234);
+    }
+    #[test]
+    fn reset_takes_3_off_s_and_sets_i() {
+        let (mut cpu, mut ram) = load(Program::new(ORIGIN).cli().sec());
+        // From power-on, that's $00 - 3.
+        assert_eq!(cpu.get_s(), 0xFD);
+        assert_ne!(cpu.get_p() & STATUS_I, 0);
+        cpu.step(&mut ram);
+        cpu.step(&mut ram);
+        assert_eq!(cpu.get_p() & STATUS_I, 0);
+        cpu.reset(&mut ram);
+        assert_eq!(cpu.get_s(), 0xFA);
+        assert_ne!(cpu.get_p() & STATUS_I, 0);
+        // The other flags stay as they were.
+        assert_ne!(cpu.get_p() & STATUS_C, 0);
+        assert_eq!(cpu.get_pc(), ORIGIN);
+    }
+    #[test]
+    fn compare_ignores_decimal_mode() {
+        for a in (0..=255u8).step_by(15) {
+            for value in (0..=255u8).step_by(17) {
+                let program = Program::new(ORIGIN).sed().lda_imm(a).cmp_imm(value);
+                let (cpu, _) = run(program, 3);
+                let difference = a.wrapping_sub(value);
+                let expected = assign_bit(0, STATUS_C, a >= value)
+                    | assign_bit(0, STATUS_Z, a == value)
+                    | (difference & STATUS_N);
+                assert_eq!(
+                    cpu.get_p() & (STATUS_N | STATUS_Z | STATUS_C),
+                    expected,
+                    "CMP #${value:02X} with A = ${a:02X}"
+                );
+            }
+        }
+    }
+    #[test]
+    fn bit_sets_z_from_and_and_copies_n_and_v() {
+        for (value, expected) in [
+            // Not equal, but nothing in common, so Z is set.
+            (0xF0, STATUS_Z | STATUS_N | STATUS_V),
+            (0x4F, STATUS_V),
+            (0x8F, STATUS_N),
+        ] {
+            let (mut cpu, mut ram) = load(Program::new(ORIGIN).lda_imm(0x0F).bit_zp(0x10));
+            ram.0[0x10] = value;
+            cpu.step(&mut ram);
+            cpu.step(&mut ram);
+            assert_eq!(
+                cpu.get_p() & (STATUS_N | STATUS_V | STATUS_Z),
+                expected,
+                "BIT ${value:02X} with A = $0F"
+            );
+            // And A doesn't change.
+            assert_eq!(cpu.get_a(), 0x0F);
+        }
+    }
+    #[test]
+    fn run_until_pc_stops_right_at_the_target() {
+        // Count X up to 3, and then keep counting past the label.
+        let program = Program::new(ORIGIN)
+            .ldx_imm(0)
+            .inx()
+            .cpx_imm(3)
+            .bne(ORIGIN + 2);
+        let label = program.here();
+        let program = program.inx().jmp(label);
+        let (mut cpu, mut ram) = load(program);
+        assert!(cpu.run_until_pc(&mut ram, label, 100));
+        // The INX at the label hasn't run yet.
+        assert_eq!(cpu.get_x(), 3);
+        // Already there, so it doesn't step at all.
+        assert!(cpu.run_until_pc(&mut ram, label, 100));
+        assert_eq!(cpu.get_x(), 3);
+        // Nowhere the program goes: it gives up after `max_steps`.
+        let instructions = cpu.get_instruction_count();
+        assert!(!cpu.run_until_pc(&mut ram, 0x1234, 10));
+        assert_eq!(cpu.get_instruction_count(), instructions + 10);
+    }
+    #[test]
+    fn counts_instructions_and_cycles_until_reset() {
+        let program = Program::new(ORIGIN).ldx_imm(5).dex().bne(ORIGIN + 2).nop();
+        let (mut cpu, mut ram) = load(program);
+        assert!(cpu.run_until_pc(&mut ram, ORIGIN + 5, 100));
+        // LDX, then five DEXes and five BNEs, the first four of them taken.
+        assert_eq!(cpu.get_instruction_count(), 11);
+        assert_eq!(cpu.get_cycle_count(), 2 + 5 * 2 + 4 * 3 + 2);
+        cpu.reset(&mut ram);
+        assert_eq!(cpu.get_instruction_count(), 0);
+        assert_eq!(cpu.get_cycle_count(), 0);
+    }
+
+    /// Set V (with an ADC that overflows), then A, X, and C, and then run
+    /// the unofficial immediate-mode `opcode` on `value`.
+    #[cfg(feature = "undocumented-opcodes")]
+    fn run_unofficial(opcode: u8, a: u8, x: u8, carry: bool, value: u8) -> Cpu {
+        let program = Program::new(ORIGIN)
+            .lda_imm(0x7F)
+            .adc_imm(0x01)
+            .lda_imm(a)
+            .ldx_imm(x);
+        let program = if carry { program.sec() } else { program.clc() };
+        let (cpu, _) = run(program.data(&[opcode, value]), 6);
+        cpu
+    }
+    #[cfg(feature = "undocumented-opcodes")]
+    const NVZC: u8 = STATUS_N | STATUS_V | STATUS_Z | STATUS_C;
+    #[cfg(feature = "undocumented-opcodes")]
+    #[test]
+    fn anc_copies_n_into_c() {
+        for opcode in [0x0B, 0x2B] {
+            let cpu = run_unofficial(opcode, 0xF0, 0, false, 0x8F);
+            assert_eq!(cpu.get_a(), 0x80);
+            assert_eq!(cpu.get_p() & NVZC, STATUS_N | STATUS_V | STATUS_C);
+            let cpu = run_unofficial(opcode, 0x0F, 0, true, 0x70);
+            assert_eq!(cpu.get_a(), 0x00);
+            assert_eq!(cpu.get_p() & NVZC, STATUS_V | STATUS_Z);
+        }
+    }
+    #[cfg(feature = "undocumented-opcodes")]
+    #[test]
+    fn alr_ands_and_then_shifts_right() {
+        for (a, value, result, flags) in [
+            (0xFF, 0x03, 0x01, STATUS_C),
+            (0x81, 0x80, 0x40, 0),
+            (0xFF, 0x01, 0x00, STATUS_Z | STATUS_C),
+        ] {
+            // The carry going in doesn't get shifted in.
+            let cpu = run_unofficial(0x4B, a, 0, true, value);
+            assert_eq!(cpu.get_a(), result, "ALR #${value:02X} with A = ${a:02X}");
+            assert_eq!(cpu.get_p() & NVZC, STATUS_V | flags);
+        }
+    }
+    #[cfg(feature = "undocumented-opcodes")]
+    #[test]
+    fn arr_takes_c_and_v_from_bits_6_and_5() {
+        for (value, carry, result, flags) in [
+            (0xFF, true, 0xFF, STATUS_N | STATUS_C),
+            (0x80, false, 0x40, STATUS_C | STATUS_V),
+            (0x40, false, 0x20, STATUS_V),
+            // Bit 0 falls off the end, but it's not where C comes from.
+            (0x01, false, 0x00, STATUS_Z),
+            (0x00, true, 0x80, STATUS_N),
+        ] {
+            let cpu = run_unofficial(0x6B, 0xFF, 0, carry, value);
+            assert_eq!(cpu.get_a(), result, "ARR #${value:02X} with C = {carry}");
+            assert_eq!(
+                cpu.get_p() & NVZC,
+                flags,
+                "ARR #${value:02X} with C = {carry}"
+            );
+        }
+    }
+    #[cfg(feature = "undocumented-opcodes")]
+    #[test]
+    fn axs_subtracts_like_a_compare() {
+        for (a, x, carry, value, result, flags) in [
+            (0xF0, 0x3C, false, 0x10, 0x20, STATUS_C),
+            (0xFF, 0x05, true, 0x06, 0xFF, STATUS_N),
+            (0x0F, 0x0F, false, 0x0F, 0x00, STATUS_Z | STATUS_C),
+        ] {
+            let cpu = run_unofficial(0xCB, a, x, carry, value);
+            assert_eq!(
+                cpu.get_x(),
+                result,
+                "AXS #${value:02X} with A = ${a:02X}, X = ${x:02X}"
+            );
+            // V stays set, and A doesn't change.
+            assert_eq!(cpu.get_p() & NVZC, STATUS_V | flags);
+            assert_eq!(cpu.get_a(), a);
+        }
+    }
+}
@@ -96,12 +96,10 @@ addressible_mode!(
     cpu_var_name: cpu,
     memory_var_name: memory,
     new_function_body: {
-        let address_of_address = (cpu.read_pc_and_post_inc(memory).wrapping_add(cpu.x)) as u16;
-        let address_low = memory.read_byte(cpu, address_of_address as u16);
-        // note: wrap BEFORE conversion to u16. 0x00FF wraps to 0x0000 when
-        // doing X indexing.
-        let address_high = memory.read_byte(cpu, address_of_address.wrapping_add(1) as u16);
-        let address = u16::from_le_bytes([address_low, address_high]);
+        let address_of_address = cpu.read_pc_and_post_inc(memory).wrapping_add(cpu.x);
+        // note: the pointer wraps within the zero page. 0x00FF wraps to
+        // 0x0000 when fetching the high byte.
+        let address = memory.read_word_zp_wrap(cpu, address_of_address);
         return Self(address);
     }
 );
@@ -111,10 +109,10 @@ addressible_mode!(
     memory_var_name: memory,
     new_function_body: {
         let address_of_address = cpu.read_pc_and_post_inc(memory);
-        let base_low = memory.read_byte(cpu, address_of_address as u16);
-        let base_high = memory.read_byte(cpu, address_of_address as u16 + 1);
-        let base = u16::from_le_bytes([base_low, base_high]);
-        return Self(base.wrapping_add(cpu.y as u16));
+        let base = memory.read_word_zp_wrap(cpu, address_of_address);
+        let result = base.wrapping_add(cpu.y as u16);
+        cpu.page_crossed = result & 0xFF00 != base & 0xFF00;
+        return Self(result);
     }
 );
 addressible_mode!(
@@ -136,7 +134,9 @@ addressible_mode!(
         let a = cpu.read_pc_and_post_inc(memory);
         let b = cpu.read_pc_and_post_inc(memory);
         let address = u16::from_le_bytes([a, b]);
-        return Self(address.wrapping_add(cpu.x as u16));
+        let result = address.wrapping_add(cpu.x as u16);
+        cpu.page_crossed = result & 0xFF00 != address & 0xFF00;
+        return Self(result);
     }
 );
 addressible_mode!(
@@ -147,7 +147,9 @@ addressible_mode!(
         let a = cpu.read_pc_and_post_inc(memory);
         let b = cpu.read_pc_and_post_inc(memory);
         let address = u16::from_le_bytes([a, b]);
-        return Self(address.wrapping_add(cpu.y as u16));
+        let result = address.wrapping_add(cpu.y as u16);
+        cpu.page_crossed = result & 0xFF00 != address & 0xFF00;
+        return Self(result);
     }
 );
 
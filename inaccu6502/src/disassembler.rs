@@ -0,0 +1,309 @@
+//! Turning machine code back into assembly, for debuggers and trace logs.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::Cpu;
+
+/// How an instruction finds its operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    /// No operand at all. (Also what unknown opcodes get.)
+    Implied,
+    /// `A`
+    Accumulator,
+    /// `#$12`
+    Immediate,
+    /// `$12`
+    ZeroPage,
+    /// `$12,X`
+    ZeroPageX,
+    /// `$12,Y`
+    ZeroPageY,
+    /// `$1234`
+    Absolute,
+    /// `$1234,X`
+    AbsoluteX,
+    /// `$1234,Y`
+    AbsoluteY,
+    /// `($1234)`, only for `JMP`.
+    Indirect,
+    /// `($12,X)`
+    ZeroPageXIndexedIndirect,
+    /// `($12),Y`
+    ZeroPageIndirectYIndexed,
+    /// A branch's signed offset, which we show as where it goes.
+    Relative,
+}
+
+impl AddressingMode {
+    /// How many bytes of operand come after the opcode.
+    pub fn get_operand_length(self) -> u16 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 0,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+// Short names, so the table below fits on the screen.
+const IMP: AddressingMode = AddressingMode::Implied;
+const ACC: AddressingMode = AddressingMode::Accumulator;
+const IMM: AddressingMode = AddressingMode::Immediate;
+const ZPG: AddressingMode = AddressingMode::ZeroPage;
+const ZPX: AddressingMode = AddressingMode::ZeroPageX;
+const ZPY: AddressingMode = AddressingMode::ZeroPageY;
+const ABS: AddressingMode = AddressingMode::Absolute;
+const ABX: AddressingMode = AddressingMode::AbsoluteX;
+const ABY: AddressingMode = AddressingMode::AbsoluteY;
+const IND: AddressingMode = AddressingMode::Indirect;
+const IZX: AddressingMode = AddressingMode::ZeroPageXIndexedIndirect;
+const IZY: AddressingMode = AddressingMode::ZeroPageIndirectYIndexed;
+const REL: AddressingMode = AddressingMode::Relative;
+
+// The unofficial opcodes the CPU knows, if it knows them. (See the
+// `undocumented-opcodes` feature.) Otherwise, they're as unknown as the rest.
+const fn unofficial(mnemonic: &'static str) -> &'static str {
+    if cfg!(feature = "undocumented-opcodes") {
+        mnemonic
+    } else {
+        "???"
+    }
+}
+const ANC: &str = unofficial("ANC");
+const ALR: &str = unofficial("ALR");
+const ARR: &str = unofficial("ARR");
+const AXS: &str = unofficial("AXS");
+const UIM: AddressingMode = if cfg!(feature = "undocumented-opcodes") {
+    IMM
+} else {
+    IMP
+};
+
+/// The mnemonic for every opcode. `???` means we don't know that opcode
+/// (same as a zero in `OPCODE_CYCLES`).
+#[rustfmt::skip]
+pub(crate) const MNEMONICS: [&str; 256] = [
+    //  0      1      2      3      4      5      6      7      8      9      A      B      C      D      E      F
+        "BRK", "ORA", "???", "???", "???", "ORA", "ASL", "???", "PHP", "ORA", "ASL", ANC,   "???", "ORA", "ASL", "???", // 0x
+        "BPL", "ORA", "???", "???", "???", "ORA", "ASL", "???", "CLC", "ORA", "???", "???", "???", "ORA", "ASL", "???", // 1x
+        "JSR", "AND", "???", "???", "BIT", "AND", "ROL", "???", "PLP", "AND", "ROL", ANC,   "BIT", "AND", "ROL", "???", // 2x
+        "BMI", "AND", "???", "???", "???", "AND", "ROL", "???", "SEC", "AND", "???", "???", "???", "AND", "ROL", "???", // 3x
+        "RTI", "EOR", "???", "???", "???", "EOR", "LSR", "???", "PHA", "EOR", "LSR", ALR,   "JMP", "EOR", "LSR", "???", // 4x
+        "BVC", "EOR", "???", "???", "???", "EOR", "LSR", "???", "CLI", "EOR", "???", "???", "???", "EOR", "LSR", "???", // 5x
+        "RTS", "ADC", "???", "???", "???", "ADC", "ROR", "???", "PLA", "ADC", "ROR", ARR,   "JMP", "ADC", "ROR", "???", // 6x
+        "BVS", "ADC", "???", "???", "???", "ADC", "ROR", "???", "SEI", "ADC", "???", "???", "???", "ADC", "ROR", "???", // 7x
+        "???", "STA", "???", "???", "STY", "STA", "STX", "???", "DEY", "???", "TXA", "???", "STY", "STA", "STX", "???", // 8x
+        "BCC", "STA", "???", "???", "STY", "STA", "STX", "???", "TYA", "STA", "TXS", "???", "???", "STA", "???", "???", // 9x
+        "LDY", "LDA", "LDX", "???", "LDY", "LDA", "LDX", "???", "TAY", "LDA", "TAX", "???", "LDY", "LDA", "LDX", "???", // Ax
+        "BCS", "LDA", "???", "???", "LDY", "LDA", "LDX", "???", "CLV", "LDA", "TSX", "???", "LDY", "LDA", "LDX", "???", // Bx
+        "CPY", "CMP", "???", "???", "CPY", "CMP", "DEC", "???", "INY", "CMP", "DEX", AXS,   "CPY", "CMP", "DEC", "???", // Cx
+        "BNE", "CMP", "???", "???", "???", "CMP", "DEC", "???", "CLD", "CMP", "???", "???", "???", "CMP", "DEC", "???", // Dx
+        "CPX", "SBC", "???", "???", "CPX", "SBC", "INC", "???", "INX", "SBC", "NOP", "???", "CPX", "SBC", "INC", "???", // Ex
+        "BEQ", "SBC", "???", "???", "???", "SBC", "INC", "???", "SED", "SBC", "???", "???", "???", "SBC", "INC", "???", // Fx
+];
+/// The addressing mode for every opcode.
+#[rustfmt::skip]
+pub(crate) const MODES: [AddressingMode; 256] = [
+    //  0    1    2    3    4    5    6    7    8    9    A    B    C    D    E    F
+        IMP, IZX, IMP, IMP, IMP, ZPG, ZPG, IMP, IMP, IMM, ACC, UIM, IMP, ABS, ABS, IMP, // 0x
+        REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP, // 1x
+        ABS, IZX, IMP, IMP, ZPG, ZPG, ZPG, IMP, IMP, IMM, ACC, UIM, ABS, ABS, ABS, IMP, // 2x
+        REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP, // 3x
+        IMP, IZX, IMP, IMP, IMP, ZPG, ZPG, IMP, IMP, IMM, ACC, UIM, ABS, ABS, ABS, IMP, // 4x
+        REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP, // 5x
+        IMP, IZX, IMP, IMP, IMP, ZPG, ZPG, IMP, IMP, IMM, ACC, UIM, IND, ABS, ABS, IMP, // 6x
+        REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP, // 7x
+        IMP, IZX, IMP, IMP, ZPG, ZPG, ZPG, IMP, IMP, IMP, IMP, IMP, ABS, ABS, ABS, IMP, // 8x
+        REL, IZY, IMP, IMP, ZPX, ZPX, ZPY, IMP, IMP, ABY, IMP, IMP, IMP, ABX, IMP, IMP, // 9x
+        IMM, IZX, IMM, IMP, ZPG, ZPG, ZPG, IMP, IMP, IMM, IMP, IMP, ABS, ABS, ABS, IMP, // Ax
+        REL, IZY, IMP, IMP, ZPX, ZPX, ZPY, IMP, IMP, ABY, IMP, IMP, ABX, ABX, ABY, IMP, // Bx
+        IMM, IZX, IMP, IMP, ZPG, ZPG, ZPG, IMP, IMP, IMM, IMP, UIM, ABS, ABS, ABS, IMP, // Cx
+        REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP, // Dx
+        IMM, IZX, IMP, IMP, ZPG, ZPG, ZPG, IMP, IMP, IMM, IMP, IMP, ABS, ABS, ABS, IMP, // Ex
+        REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP, // Fx
+];
+
+/// One instruction, fresh out of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    /// Where the opcode is.
+    pub address: u16,
+    pub opcode: u8,
+    /// The operand bytes, if there are any. (Unused ones are zero.)
+    pub operand: [u8; 2],
+}
+
+impl Instruction {
+    /// Decode the instruction at `address`. `peek` reads memory, and had
+    /// better not have any side effects!
+    pub fn decode(address: u16, peek: impl Fn(u16) -> u8) -> Instruction {
+        let opcode = peek(address);
+        let mut operand = [0; 2];
+        for (n, byte) in operand
+            .iter_mut()
+            .take(MODES[opcode as usize].get_operand_length() as usize)
+            .enumerate()
+        {
+            *byte = peek(address.wrapping_add(1 + n as u16));
+        }
+        Instruction {
+            address,
+            opcode,
+            operand,
+        }
+    }
+    pub fn get_mnemonic(&self) -> &'static str {
+        MNEMONICS[self.opcode as usize]
+    }
+    /// Whether this is an opcode we know. If it isn't, it's probably data.
+    pub fn is_known(&self) -> bool {
+        self.get_mnemonic() != "???"
+    }
+    pub fn get_addressing_mode(&self) -> AddressingMode {
+        MODES[self.opcode as usize]
+    }
+    /// How many bytes long the whole instruction is, opcode and all.
+    pub fn get_length(&self) -> u16 {
+        1 + self.get_addressing_mode().get_operand_length()
+    }
+    /// The opcode and operand bytes.
+    pub fn get_bytes(&self) -> Vec<u8> {
+        let mut result = vec![self.opcode];
+        result.extend_from_slice(&self.operand[..self.get_length() as usize - 1]);
+        result
+    }
+    fn get_operand_word(&self) -> u16 {
+        u16::from_le_bytes(self.operand)
+    }
+    /// Like `Display`, but also work out (without running anything) where
+    /// the operand actually is, given the CPU's current registers, and what's
+    /// there right now. This is the same style as `nestest.log`:
+    ///
+    /// ```text
+    /// LDA $12,X @ 15 = A9
+    /// LDA ($80,X) @ 82 = 0200 = 5A
+    /// LDA ($89),Y = 0300 @ 0302 = 89
+    /// ```
+    ///
+    /// The values are only right for the *next* instruction, since the ones
+    /// after it might change X, Y, or memory before they run.
+    pub fn show_with_operands(&self, cpu: &Cpu, peek: impl Fn(u16) -> u8) -> String {
+        let zero_page = self.operand[0];
+        let read_zero_page_word = |address: u8| {
+            u16::from_le_bytes([peek(address as u16), peek(address.wrapping_add(1) as u16)])
+        };
+        let annotation = match self.get_addressing_mode() {
+            AddressingMode::ZeroPage => format!(" = {:02X}", peek(zero_page as u16)),
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+                let index = if self.get_addressing_mode() == AddressingMode::ZeroPageX {
+                    cpu.get_x()
+                } else {
+                    cpu.get_y()
+                };
+                let address = zero_page.wrapping_add(index);
+                format!(" @ {address:02X} = {:02X}", peek(address as u16))
+            }
+            // Jumps don't read from their operand, they go there.
+            AddressingMode::Absolute if matches!(self.get_mnemonic(), "JMP" | "JSR") => {
+                String::new()
+            }
+            AddressingMode::Absolute => format!(" = {:02X}", peek(self.get_operand_word())),
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+                let index = if self.get_addressing_mode() == AddressingMode::AbsoluteX {
+                    cpu.get_x()
+                } else {
+                    cpu.get_y()
+                };
+                let address = self.get_operand_word().wrapping_add(index as u16);
+                format!(" @ {address:04X} = {:02X}", peek(address))
+            }
+            AddressingMode::Indirect => {
+                // The famous bug: the high byte of the pointer never comes
+                // from the next page.
+                let pointer = self.get_operand_word();
+                let high_address = (pointer & 0xFF00) | (pointer as u8).wrapping_add(1) as u16;
+                let target = u16::from_le_bytes([peek(pointer), peek(high_address)]);
+                format!(" = {target:04X}")
+            }
+            AddressingMode::ZeroPageXIndexedIndirect => {
+                let pointer = zero_page.wrapping_add(cpu.get_x());
+                let address = read_zero_page_word(pointer);
+                format!(" @ {pointer:02X} = {address:04X} = {:02X}", peek(address))
+            }
+            AddressingMode::ZeroPageIndirectYIndexed => {
+                let base = read_zero_page_word(zero_page);
+                let address = base.wrapping_add(cpu.get_y() as u16);
+                format!(" = {base:04X} @ {address:04X} = {:02X}", peek(address))
+            }
+            _ => String::new(),
+        };
+        format!("{self}{annotation}")
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let mnemonic = self.get_mnemonic();
+        let byte = self.operand[0];
+        let word = self.get_operand_word();
+        match self.get_addressing_mode() {
+            AddressingMode::Implied => write!(f, "{mnemonic}"),
+            AddressingMode::Accumulator => write!(f, "{mnemonic} A"),
+            AddressingMode::Immediate => write!(f, "{mnemonic} #${byte:02X}"),
+            AddressingMode::ZeroPage => write!(f, "{mnemonic} ${byte:02X}"),
+            AddressingMode::ZeroPageX => write!(f, "{mnemonic} ${byte:02X},X"),
+            AddressingMode::ZeroPageY => write!(f, "{mnemonic} ${byte:02X},Y"),
+            AddressingMode::Absolute => write!(f, "{mnemonic} ${word:04X}"),
+            AddressingMode::AbsoluteX => write!(f, "{mnemonic} ${word:04X},X"),
+            AddressingMode::AbsoluteY => write!(f, "{mnemonic} ${word:04X},Y"),
+            AddressingMode::Indirect => write!(f, "{mnemonic} (${word:04X})"),
+            AddressingMode::ZeroPageXIndexedIndirect => write!(f, "{mnemonic} (${byte:02X},X)"),
+            AddressingMode::ZeroPageIndirectYIndexed => write!(f, "{mnemonic} (${byte:02X}),Y"),
+            AddressingMode::Relative => {
+                let target = self.address.wrapping_add(2).wrapping_add(byte as i8 as u16);
+                write!(f, "{mnemonic} ${target:04X}")
+            }
+        }
+    }
+}
+
+/// Disassemble everything from `start` to `end` (inclusive), one line per
+/// instruction, in the same format as a trace log (minus the registers).
+/// There's no telling code from data without running it, so this just
+/// decodes straight through, and shows anything that isn't an opcode we
+/// know (or an instruction that would run past `end`) as `.byte`. Data that
+/// happens to look like code will still come out as code.
+pub fn disassemble(start: u16, end: u16, peek: impl Fn(u16) -> u8) -> String {
+    let mut result = String::new();
+    let mut address = start as u32;
+    while address <= end as u32 {
+        let instruction = Instruction::decode(address as u16, &peek);
+        let length = instruction.get_length() as u32;
+        let line = if instruction.is_known() && address + length - 1 <= end as u32 {
+            let bytes: Vec<String> = instruction
+                .get_bytes()
+                .iter()
+                .map(|x| format!("{x:02X}"))
+                .collect();
+            address += length;
+            format!(
+                "{:04X}  {:8}  {instruction}",
+                instruction.address,
+                bytes.join(" ")
+            )
+        } else {
+            address += 1;
+            format!(
+                "{:04X}  {:02X}        .byte ${:02X}",
+                instruction.address, instruction.opcode, instruction.opcode
+            )
+        };
+        result.push_str(&line);
+        result.push('\n');
+    }
+    result
+}
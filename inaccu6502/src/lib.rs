@@ -1,4 +1,26 @@
+/// For things we haven't gotten around to yet. Normally, this is `todo!()`.
+/// With the `lenient` feature, it logs a warning and evaluates to `$default`
+/// instead, so a game that wanders into one keeps going (wrongly). Handy when
+/// you're testing something else entirely. The feature that counts is the
+/// one in whichever crate uses this, so give that crate a `lenient` feature
+/// too.
+#[macro_export]
+macro_rules! unimplemented_feature {
+    ($default:expr, $what:literal) => {
+        if cfg!(feature = "lenient") {
+            ::log::warn!(concat!("Not implemented yet: ", $what));
+            $default
+        } else {
+            todo!($what)
+        }
+    };
+}
+
 mod memory;
 pub use memory::Memory;
 mod cpu;
 pub use cpu::*;
+mod disassembler;
+pub use disassembler::{disassemble, AddressingMode, Instruction};
+mod assembler;
+pub use assembler::Program;
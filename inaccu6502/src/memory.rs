@@ -3,4 +3,68 @@ use crate::Cpu;
 pub trait Memory {
     fn read_byte(&mut self, cpu: &mut Cpu, address: u16) -> u8;
     fn write_byte(&mut self, cpu: &mut Cpu, address: u16, data: u8);
+    /// Read a little-endian word from `address` and `address + 1`. (If
+    /// `address` is $FFFF, the high byte comes from $0000.)
+    fn read_word(&mut self, cpu: &mut Cpu, address: u16) -> u16 {
+        let low = self.read_byte(cpu, address);
+        let high = self.read_byte(cpu, address.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+    /// Read a little-endian word from the zero page, the way the 6502's
+    /// indirect addressing modes do: the high byte of a pointer at $FF comes
+    /// from $00, *not* from $0100.
+    fn read_word_zp_wrap(&mut self, cpu: &mut Cpu, address: u8) -> u16 {
+        let low = self.read_byte(cpu, address as u16);
+        let high = self.read_byte(cpu, address.wrapping_add(1) as u16);
+        u16::from_le_bytes([low, high])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plain 64KiB of RAM.
+    struct Ram([u8; 0x10000]);
+
+    impl Memory for Ram {
+        fn read_byte(&mut self, _cpu: &mut Cpu, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+        fn write_byte(&mut self, _cpu: &mut Cpu, address: u16, data: u8) {
+            self.0[address as usize] = data;
+        }
+    }
+
+    /// Every byte is the low byte of its own address, except `$0100`, so
+    /// that a pointer on the zero page that wraps the wrong way stands out.
+    fn ram() -> Ram {
+        let mut result = Ram([0; 0x10000]);
+        for (address, byte) in result.0.iter_mut().enumerate() {
+            *byte = address as u8;
+        }
+        result.0[0x0100] = 0xAA;
+        result
+    }
+
+    #[test]
+    fn read_word_is_little_endian() {
+        let mut cpu = Cpu::new();
+        let mut ram = ram();
+        ram.0[0x1234] = 0xCD;
+        ram.0[0x1235] = 0xAB;
+        assert_eq!(ram.read_word(&mut cpu, 0x1234), 0xABCD);
+        // Across a page is fine, unlike the zero page.
+        assert_eq!(ram.read_word(&mut cpu, 0x00FF), 0xAAFF);
+        // And the very top wraps around to the bottom.
+        assert_eq!(ram.read_word(&mut cpu, 0xFFFF), 0x00FF);
+    }
+
+    #[test]
+    fn read_word_zp_wrap_stays_on_the_zero_page() {
+        let mut cpu = Cpu::new();
+        let mut ram = ram();
+        assert_eq!(ram.read_word_zp_wrap(&mut cpu, 0x10), 0x1110);
+        assert_eq!(ram.read_word_zp_wrap(&mut cpu, 0xFF), 0x00FF);
+    }
 }
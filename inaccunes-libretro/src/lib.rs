@@ -0,0 +1,345 @@
+//! inaccunes as a libretro core, for RetroArch and friends. Build it with
+//! `cargo build --release -p inaccunes-libretro` and point your frontend at
+//! the resulting `libinaccunes_libretro.so` (or `.dll`, or `.dylib`).
+//!
+//! Only the parts of the API we actually need are declared here, by hand,
+//! straight out of `libretro.h`. Everything is `repr(C)` and must stay
+//! exactly the shape the header says it is!
+
+use std::{
+    ffi::{c_char, c_uint, c_void, CStr},
+    sync::Mutex,
+};
+
+use inaccunes::{
+    cartridge::Cartridge, region::Region, system::System, NES_HEIGHT, NES_PIXEL_COUNT, NES_WIDTH,
+};
+use log::*;
+
+const RETRO_API_VERSION: c_uint = 1;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+const RETRO_REGION_PAL: c_uint = 1;
+
+/// libretro's joypad button IDs, in the order of our own controller bits (A,
+/// B, Select, Start, Up, Down, Left, Right). libretro's A and B are the
+/// right and bottom face buttons, same as on a Super Nintendo pad.
+const JOYPAD_IDS: [c_uint; 8] = [8, 0, 2, 3, 4, 5, 6, 7];
+
+/// The audio sample rate we tell the frontend about. There's no APU yet, so
+/// all it ever gets is silence, but frontends that sync to audio need
+/// *something* to sync to.
+const SAMPLE_RATE: f64 = 44100.0;
+
+type EnvironmentFn = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshFn =
+    unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = unsafe extern "C" fn();
+type InputStateFn =
+    unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    geometry: RetroGameGeometry,
+    timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+/// All the callbacks the frontend has handed us so far.
+#[derive(Default)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+/// A loaded game, and somewhere to render it.
+struct Core {
+    system: System,
+    pixels: Vec<u32>,
+    /// Fractional audio frames left over from previous video frames, so the
+    /// frontend gets exactly `SAMPLE_RATE` of them per second on average.
+    audio_frames_owed: f64,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_info` we're allowed to write to.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: c"inaccunes".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: c"nes".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_av_info` we're allowed to write to.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    let region = CORE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|core| core.system.get_region())
+        .unwrap_or_default();
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: NES_WIDTH as c_uint,
+            base_height: NES_HEIGHT as c_uint,
+            max_width: NES_WIDTH as c_uint,
+            max_height: NES_HEIGHT as c_uint,
+            aspect_ratio: 4.0 / 3.0,
+        },
+        timing: RetroSystemTiming {
+            fps: region.frames_per_second(),
+            sample_rate: SAMPLE_RATE,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {
+    // We always use the batch one.
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {
+    // It's a joypad. It's always a joypad.
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.system.reset();
+    }
+}
+
+/// Run exactly one frame.
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = CALLBACKS.lock().unwrap();
+    let mut core = CORE.lock().unwrap();
+    let Some(core) = core.as_mut() else {
+        return;
+    };
+    if let (Some(input_poll), Some(input_state)) = (callbacks.input_poll, callbacks.input_state) {
+        unsafe { input_poll() };
+        for port in 0..core.system.get_controllers().len() {
+            let mut buttons = 0;
+            for (bit, id) in JOYPAD_IDS.iter().enumerate() {
+                if unsafe { input_state(port as c_uint, RETRO_DEVICE_JOYPAD, 0, *id) } != 0 {
+                    buttons |= 1 << bit;
+                }
+            }
+            core.system.set_controller_state(port, buttons);
+        }
+    }
+    core.system.render_into(&mut core.pixels);
+    if let Some(video_refresh) = callbacks.video_refresh {
+        // Our pixels are 0x00RRGGBB, which is exactly XRGB8888.
+        unsafe {
+            video_refresh(
+                core.pixels.as_ptr() as *const c_void,
+                NES_WIDTH as c_uint,
+                NES_HEIGHT as c_uint,
+                NES_WIDTH * std::mem::size_of::<u32>(),
+            )
+        };
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        core.audio_frames_owed += SAMPLE_RATE / core.system.get_region().frames_per_second();
+        let frames = core.audio_frames_owed as usize;
+        core.audio_frames_owed -= frames as f64;
+        let silence = vec![0i16; frames * 2];
+        unsafe { audio_sample_batch(silence.as_ptr(), frames) };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize(_data: *mut c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unserialize(_data: *const c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game` must point to a valid `retro_game_info`, whose `data` points to
+/// `size` bytes of ROM.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+    if game.data.is_null() {
+        error!("The frontend didn't give us the ROM's data");
+        return false;
+    }
+    if let Some(environment) = CALLBACKS.lock().unwrap().environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !environment(
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut format as *mut c_uint as *mut c_void,
+        ) {
+            error!("The frontend doesn't support XRGB8888");
+            return false;
+        }
+    }
+    let rom = std::slice::from_raw_parts(game.data as *const u8, game.size);
+    let rom_path = if game.path.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(game.path).to_string_lossy().into_owned()
+    };
+    let cartridge = match Cartridge::from_reader(rom) {
+        Ok(x) => x,
+        Err(x) => {
+            error!("Couldn't load the game: {x}");
+            return false;
+        }
+    };
+    let region = Region::detect(&rom_path, &cartridge);
+    let mut system = System::new(cartridge);
+    system.set_region(region);
+    *CORE.lock().unwrap() = Some(Core {
+        system,
+        pixels: vec![0; NES_PIXEL_COUNT],
+        audio_frames_owed: 0.0,
+    });
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    match CORE.lock().unwrap().as_ref().map(|core| core.system.get_region()) {
+        Some(Region::Pal) => RETRO_REGION_PAL,
+        _ => RETRO_REGION_NTSC,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
@@ -0,0 +1,68 @@
+//! A thin browser frontend for inaccunes. Build it with:
+//!
+//! ```text
+//! wasm-pack build --target web inaccunes-wasm
+//! ```
+//!
+//! and then serve up `www/` and `pkg/` from the same place.
+
+use inaccunes::{cartridge::Cartridge, system::System, NES_PIXEL_COUNT};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct Emulator {
+    system: System,
+    pixels: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Make a new `Emulator` out of the bytes of an iNES file, and power it
+    /// on. If the ROM won't load, this throws, with a message saying why.
+    #[wasm_bindgen(constructor)]
+    pub fn load_rom(bytes: &[u8]) -> Result<Emulator, JsValue> {
+        let cartridge =
+            Cartridge::from_reader(bytes).map_err(|x| JsValue::from_str(&x.to_string()))?;
+        Ok(Emulator {
+            system: System::new(cartridge),
+            pixels: vec![0; NES_PIXEL_COUNT],
+        })
+    }
+    /// Press or release a button. `player` is 0 to 3, but 2 and 3 only get
+    /// read with a Four Score. `button` is a bit number, in the same order
+    /// as the controller's shift register: A, B, Select, Start, Up, Down,
+    /// Left, Right.
+    pub fn set_button(&mut self, player: usize, button: u8, pressed: bool) {
+        if player >= self.system.get_controllers().len() {
+            return;
+        }
+        let mask = 1u8.checked_shl(button as u32).unwrap_or(0);
+        let buttons = self.system.get_controller_state(player);
+        let buttons = if pressed {
+            buttons | mask
+        } else {
+            buttons & !mask
+        };
+        self.system.set_controller_state(player, buttons);
+    }
+    /// Plug in (or unplug) a Four Score, for four players.
+    pub fn set_four_score(&mut self, is_plugged_in: bool) {
+        self.system.set_four_score_plugged_in(is_plugged_in);
+    }
+    /// Run one frame, and return it as RGBA bytes, ready for an `ImageData`.
+    pub fn render_frame(&mut self) -> Vec<u8> {
+        self.system.render_into(&mut self.pixels);
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                let [_, r, g, b] = pixel.to_be_bytes();
+                [r, g, b, 0xFF]
+            })
+            .collect()
+    }
+    /// Whatever audio was generated since the last call. There's no APU yet,
+    /// so there's never anything here, but the frontend can already ask.
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        vec![]
+    }
+}
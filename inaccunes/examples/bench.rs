@@ -0,0 +1,100 @@
+//! How fast does the core go, with no SDL in the way? Run it like this:
+//!
+//! ```text
+//! cargo run --release -p inaccunes --no-default-features --example bench -- [game.nes] [frames]
+//! ```
+//!
+//! Without a ROM, it uses a tiny built-in one that turns rendering on and
+//! then loops forever, which is mostly a benchmark of the PPU.
+
+use std::time::Instant;
+
+use inaccu6502::Program;
+use inaccunes::{cartridge::Cartridge, region::Region, system::System};
+
+const DEFAULT_FRAMES: usize = 3000;
+/// Frames to run before we start the clock, so the game is past its
+/// (usually much lighter) startup.
+const WARM_UP_FRAMES: usize = 60;
+/// The average 6502 instruction takes about this many cycles. Good enough
+/// to turn cycles into a rough instruction count.
+const CYCLES_PER_INSTRUCTION: f64 = 3.0;
+
+/// An NROM cartridge with 16KiB of PRG and 8KiB of CHR. The program waits
+/// out the PPU's warm-up (which ignores writes to PPUCTRL and PPUMASK), turns
+/// on the background, the sprites, and NMIs, and then scribbles on RAM
+/// forever.
+fn built_in_rom() -> Vec<u8> {
+    let mut rom = b"NES\x1a\x01\x01\x00\x00".to_vec();
+    rom.resize(16 + 0x4000 + 0x2000, 0);
+    let prg = &mut rom[16..16 + 0x4000];
+    let program = Program::new(0x8000).sei().bit_abs(0x2002);
+    // Wait for V-blank twice.
+    let wait_1 = program.here();
+    let program = program.bit_abs(0x2002).bpl(wait_1);
+    let wait_2 = program.here();
+    let program = program
+        .bit_abs(0x2002)
+        .bpl(wait_2)
+        .lda_imm(0x1E)
+        .sta_abs(0x2001)
+        .lda_imm(0x80)
+        .sta_abs(0x2000);
+    let forever = program.here();
+    let program = program.inx().sta_abx(0x0300).jmp(forever);
+    let rti = program.here();
+    let program = program.rti().build();
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI and IRQ go to the RTI, reset goes to the start.
+    let [rti_low, rti_high] = rti.to_le_bytes();
+    prg[0x3FFA..].copy_from_slice(&[rti_low, rti_high, 0x00, 0x80, rti_low, rti_high]);
+    // Some stripes in CHR, so there's something to draw.
+    for (index, byte) in rom[16 + 0x4000..].iter_mut().enumerate() {
+        *byte = index as u8;
+    }
+    rom
+}
+
+fn main() {
+    let mut arguments = std::env::args().skip(1);
+    let (cartridge, name) = match arguments.next() {
+        Some(path) => (
+            Cartridge::new(&path).unwrap_or_else(|x| panic!("Could not load {path}: {x}")),
+            path,
+        ),
+        None => (
+            Cartridge::from_reader(&built_in_rom()[..]).unwrap(),
+            "the built-in ROM".to_string(),
+        ),
+    };
+    let frames = arguments
+        .next()
+        .map(|x| x.parse().expect("That's not a number of frames"))
+        .unwrap_or(DEFAULT_FRAMES);
+    let region = Region::detect(&name, &cartridge);
+    let mut system = System::new(cartridge);
+    system.set_region(region);
+    let mut pixels = vec![0; inaccunes::NES_PIXEL_COUNT];
+    for _ in 0..WARM_UP_FRAMES {
+        system.render_into(&mut pixels);
+    }
+    let start_cycles = system.get_cpu().get_cycle_count();
+    let start = Instant::now();
+    for _ in 0..frames {
+        system.render_into(&mut pixels);
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    let cycles = system.get_cpu().get_cycle_count() - start_cycles;
+    let frames_per_second = frames as f64 / seconds;
+    println!("Ran {frames} frames of {name} ({region:?}) in {seconds:.3}s");
+    println!(
+        "{:.3}ms per frame, {frames_per_second:.1} frames per second ({:.1}x real time)",
+        seconds * 1000.0 / frames as f64,
+        frames_per_second / region.frames_per_second(),
+    );
+    println!(
+        "{:.2} million CPU cycles per second, so roughly {:.2} million instructions per second",
+        cycles as f64 / seconds / 1e6,
+        cycles as f64 / CYCLES_PER_INSTRUCTION / seconds / 1e6,
+    );
+}
@@ -1,36 +1,206 @@
 use log::*;
-use std::{fs::File, io::Read};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::File,
+    io::{ErrorKind, Read},
+};
+
+#[cfg(feature = "cartridge-database")]
+mod database;
+pub mod patch;
 pub struct Cartridge {
     pub mirroring_type: MirroringType,
     pub prg_data: Vec<u8>,
     pub chr_data: Vec<u8>,
+    /// Whether byte 9 of the header claims this is a PAL game. Hardly any
+    /// dumps set this, but some do.
+    pub header_says_pal: bool,
+    /// What byte 7 of the header says this was made for.
+    pub console_type: ConsoleType,
+    /// CRC32s of the PRG ROM, the CHR ROM, and both together, as loaded
+    /// (after any patch). See `rom_crc32`.
+    prg_crc32: u32,
+    chr_crc32: u32,
+    rom_crc32: u32,
+    /// Every row of every tile in `chr_data`, already decoded into 2-bit
+    /// color indices, so that `get_tile` doesn't have to do bit math for
+    /// every single pixel. See `tile_row_index` for how it's laid out.
+    decoded_tile_rows: Vec<[u8; 8]>,
 }
 
 const PRG_CHUNK_SIZE: usize = 16 * 1024; // 16 kibibytes per PRG chunk
 const CHR_CHUNK_SIZE: usize = 8 * 1024; // 8 kibibytes per CHR chunk
+/// NROM has no bank switching, so this is all the CPU can see at once.
+const NROM_MAX_PRG_SIZE: usize = 32 * 1024;
+/// ...and this is all the PPU can.
+const NROM_MAX_CHR_SIZE: usize = 8 * 1024;
+
+/// What's wrong with an NROM cartridge that has `prg_size` bytes of PRG ROM
+/// and `chr_size` bytes of CHR ROM, if anything. Anything bigger than NROM
+/// has room for is almost certainly some other mapper with the wrong number
+/// in the header, and it's going to be broken.
+fn nrom_size_warnings(prg_size: usize, chr_size: usize) -> Vec<String> {
+    let mut result = Vec::new();
+    if prg_size > NROM_MAX_PRG_SIZE {
+        result.push(format!(
+            "This says it's NROM, but it has {}KiB of PRG ROM, and NROM only has room for \
+             32KiB. Only the first 32KiB will show up, so expect it to be broken. (Is the \
+             mapper number in the header wrong?)",
+            prg_size / 1024
+        ));
+    }
+    if chr_size > NROM_MAX_CHR_SIZE {
+        result.push(format!(
+            "This says it's NROM, but it has {}KiB of CHR ROM, and NROM only has room for \
+             8KiB. Only the first 8KiB will show up, so expect it to look wrong. (Is the \
+             mapper number in the header wrong?)",
+            chr_size / 1024
+        ));
+    }
+    result
+}
 
 const HEADER_FLAG_MIRRORING: u8 = 0x01;
 const HEADER_FLAG_SAVE_RAM: u8 = 0x02;
 const HEADER_FLAG_HAS_TRAINER: u8 = 0x04;
 const HEADER_FLAG_FOUR_SCREEN_VRAM: u8 = 0x08;
+const HEADER_FLAG7_VS_SYSTEM: u8 = 0x01;
+const HEADER_FLAG7_PLAYCHOICE: u8 = 0x02;
+const HEADER_FLAG9_PAL: u8 = 0x01;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MirroringType {
+    /// $2000 = $2400, $2800 = $2C00 (for vertical scrolling)
     Horizontal,
+    /// $2000 = $2800, $2400 = $2C00 (for horizontal scrolling)
     Vertical,
+    /// All four nametables are the first 1KiB of nametable RAM
+    SingleScreenLower,
+    /// All four nametables are the second 1KiB of nametable RAM
+    SingleScreenUpper,
+    /// The cartridge has 2KiB of extra RAM, so every nametable is real
     FourScreen,
 }
 
+impl MirroringType {
+    /// Turn an address within $2000-$2FFF into an offset within the
+    /// (up to 4KiB of) physical nametable RAM.
+    pub fn map_nametable_address(self, address: u16) -> usize {
+        let address = address as usize;
+        match self {
+            MirroringType::Horizontal => (address & 0x3FF) | ((address & 0x800) >> 1),
+            MirroringType::Vertical => address & 0x7FF,
+            MirroringType::SingleScreenLower => address & 0x3FF,
+            MirroringType::SingleScreenUpper => 0x400 | (address & 0x3FF),
+            MirroringType::FourScreen => address & 0xFFF,
+        }
+    }
+}
+
+/// Which machine a ROM was dumped from. Arcade games are mostly NES games
+/// on the inside, so we try to run them like one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    /// A VS. UniSystem (or DualSystem) arcade game. These came with one of
+    /// several RGB PPUs with scrambled palettes, and read DIP switches and
+    /// coins through `$4016`/`$4017`. We have none of that, so the colors
+    /// might be off, and every DIP switch is off.
+    VsSystem,
+    /// A PlayChoice-10 arcade game. The game itself is an ordinary NES game;
+    /// the dump just has 8KiB of INST-ROM (the instructions screen) and some
+    /// PROM after the CHR ROM, which we don't read.
+    PlayChoice10,
+}
+
+/// Why a cartridge wouldn't load.
+#[derive(Debug)]
+pub enum CartridgeError {
+    /// It doesn't start with an iNES header.
+    NotInes,
+    /// It uses a mapper we haven't written yet.
+    UnsupportedMapper(u16),
+    /// It has a trainer (512 bytes that get loaded at `$7000`), which we
+    /// don't handle.
+    UnsupportedTrainer,
+    /// It has battery-backed save RAM, which we don't do yet.
+    UnsupportedSaveRam,
+    /// It has no CHR ROM, which means it has CHR RAM instead, which we don't
+    /// do yet either.
+    UnsupportedChrRam,
+    /// The file ends before all of the PRG ROM the header promised.
+    TruncatedPrg,
+    /// The file ends before all of the CHR ROM the header promised.
+    TruncatedChr,
+    /// The patch wouldn't apply. See `patch::apply_patch`.
+    Patch(anyhow::Error),
+    /// Reading the file went wrong.
+    Io(std::io::Error),
+}
+
+impl Display for CartridgeError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            CartridgeError::NotInes => write!(fmt, "It's not an iNES file!"),
+            CartridgeError::UnsupportedMapper(x) => write!(fmt, "Unknown mapper type: {x}"),
+            CartridgeError::UnsupportedTrainer => {
+                write!(
+                    fmt,
+                    "This archaic ROM has a trainer in it, we don't handle that"
+                )
+            }
+            CartridgeError::UnsupportedSaveRam => write!(fmt, "We don't do save RAM yet"),
+            CartridgeError::UnsupportedChrRam => write!(fmt, "We don't do CHR RAM yet"),
+            CartridgeError::TruncatedPrg => write!(fmt, "The file ends in the middle of PRG ROM"),
+            CartridgeError::TruncatedChr => write!(fmt, "The file ends in the middle of CHR ROM"),
+            CartridgeError::Patch(x) => write!(fmt, "Couldn't apply the patch: {x:#}"),
+            CartridgeError::Io(x) => write!(fmt, "Couldn't read the file: {x}"),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CartridgeError::Patch(x) => Some(x.as_ref()),
+            CartridgeError::Io(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(x: std::io::Error) -> CartridgeError {
+        CartridgeError::Io(x)
+    }
+}
+
+/// Fill `buffer` from `f`, turning running out of file into `truncated`.
+fn read_or<R: Read>(
+    f: &mut R,
+    buffer: &mut [u8],
+    truncated: CartridgeError,
+) -> Result<(), CartridgeError> {
+    f.read_exact(buffer).map_err(|x| match x.kind() {
+        ErrorKind::UnexpectedEof => truncated,
+        _ => CartridgeError::Io(x),
+    })
+}
+
 impl Cartridge {
-    // TODO: make this return a Result of some kind
-    pub fn new(path: &str) -> Self {
+    pub fn new(path: &str) -> Result<Cartridge, CartridgeError> {
         info!("Attempting to open path: '{path}'");
-        let mut f = File::open(path).expect("failed to open that file");
+        let f = File::open(path)?;
+        Cartridge::from_reader(f)
+    }
+    /// Load an iNES file from anywhere you can read bytes from. (Say, a
+    /// `&[u8]` that a web browser handed you.)
+    pub fn from_reader<R: Read>(mut f: R) -> Result<Cartridge, CartridgeError> {
         let mut header = [0u8; 16];
-        f.read_exact(&mut header)
-            .expect("failed to read 16-byte header");
+        // Too short to even have a header? Not an iNES file.
+        read_or(&mut f, &mut header, CartridgeError::NotInes)?;
         if &header[0..4] != b"NES\x1A" {
-            panic!("It's not an iNES file!");
+            return Err(CartridgeError::NotInes);
         }
         let prg_size = header[4] as usize * PRG_CHUNK_SIZE;
         let chr_size = header[5] as usize * CHR_CHUNK_SIZE;
@@ -42,62 +212,463 @@ impl Cartridge {
         } else {
             MirroringType::Horizontal
         };
+        let header_says_pal = header[9] & HEADER_FLAG9_PAL != 0;
+        let console_type = if header[7] & HEADER_FLAG7_VS_SYSTEM != 0 {
+            ConsoleType::VsSystem
+        } else if header[7] & HEADER_FLAG7_PLAYCHOICE != 0 {
+            ConsoleType::PlayChoice10
+        } else {
+            ConsoleType::Nes
+        };
         let has_save_ram = flags & HEADER_FLAG_SAVE_RAM != 0;
-        if has_save_ram {
-            todo!("implement save ram >:(")
-        }
         let has_trainer = flags & HEADER_FLAG_HAS_TRAINER != 0;
         if has_trainer {
-            panic!("this archaic ROM has a trainer in it, we don't handle that, FLEE!")
+            return Err(CartridgeError::UnsupportedTrainer);
         }
         let mapper_type = flags >> 4;
+        let mut prg_data = vec![0; prg_size];
+        let mut chr_data = vec![0; chr_size];
+        read_or(&mut f, &mut prg_data, CartridgeError::TruncatedPrg)?;
+        read_or(&mut f, &mut chr_data, CartridgeError::TruncatedChr)?;
+        // Whatever comes after CHR ROM (PlayChoice-10 INST-ROM, or junk)
+        // stays in the file, and out of the CRC.
+        let prg_crc32 = crc32(&[&prg_data]);
+        let chr_crc32 = crc32(&[&chr_data]);
+        let rom_crc32 = crc32(&[&prg_data, &chr_data]);
+        #[cfg(feature = "cartridge-database")]
+        let (mapper_type, mirroring_type, has_save_ram) = {
+            match database::lookup(rom_crc32) {
+                Some(fixes) => {
+                    info!("Cartridge {rom_crc32:08X} is in the database: {fixes:?}");
+                    (
+                        fixes.mapper_type.unwrap_or(mapper_type),
+                        fixes.mirroring_type.unwrap_or(mirroring_type),
+                        fixes.has_save_ram.unwrap_or(has_save_ram),
+                    )
+                }
+                None => (mapper_type, mirroring_type, has_save_ram),
+            }
+        };
+        if has_save_ram {
+            // TODO: implement save ram >:(
+            return Err(CartridgeError::UnsupportedSaveRam);
+        }
+        if chr_size == 0 {
+            return Err(CartridgeError::UnsupportedChrRam);
+        }
         match mapper_type {
             0 => {
-                // NROM, we're okay
-            }
-            x => {
-                panic!("Unknown mapper type: {}", x)
+                // NROM, we're okay... as long as it's a size NROM can be.
+                for warning in nrom_size_warnings(prg_size, chr_size) {
+                    warn!("{warning}");
+                }
             }
+            // TODO: when UxROM (2) and CNROM (3) show up, remember that
+            // they have bus conflicts: the ROM drives the bus during a
+            // bank register write too, so the bank that gets picked is the
+            // written value ANDed with the ROM byte at that address. Some
+            // games count on it.
+            x => return Err(CartridgeError::UnsupportedMapper(x as u16)),
         }
-        info!("ROM info: {prg_size} bytes PRG, {chr_size} bytes CHR, mapper type: {mapper_type}, mirroring type: {mirroring_type:?}");
-        let mut prg_data = vec![0; prg_size];
-        let mut chr_data = vec![0; chr_size];
-        f.read_exact(&mut prg_data)
-            .expect("failed to read PRG data");
-        f.read_exact(&mut chr_data)
-            .expect("failed to read CHR data");
-        return Cartridge {
+        match console_type {
+            ConsoleType::Nes => (),
+            ConsoleType::VsSystem => warn!(
+                "This is a VS. System arcade game. We run it like a NES game, but we don't have \
+                 its PPU's palette or its DIP switches, so it might look wrong, or sit there \
+                 waiting for a coin."
+            ),
+            ConsoleType::PlayChoice10 => info!(
+                "This is a PlayChoice-10 arcade game. We run it like a NES game, without the \
+                 instructions screen."
+            ),
+        }
+        info!("ROM info: {prg_size} bytes PRG, {chr_size} bytes CHR, mapper type: {mapper_type}, mirroring type: {mirroring_type:?}, CRC32: {rom_crc32:08X}");
+        let mut result = Cartridge {
             mirroring_type,
             prg_data,
             chr_data,
+            header_says_pal,
+            console_type,
+            prg_crc32,
+            chr_crc32,
+            rom_crc32,
+            decoded_tile_rows: vec![],
         };
+        result.decode_all_tile_rows();
+        Ok(result)
+    }
+    pub fn prg_crc32(&self) -> u32 {
+        self.prg_crc32
+    }
+    /// (Always of CHR ROM: we don't load cartridges with CHR RAM.)
+    pub fn chr_crc32(&self) -> u32 {
+        self.chr_crc32
+    }
+    /// The CRC32 of PRG and CHR together, without the header, which is what
+    /// No-Intro and most ROM databases go by. Good for telling games apart:
+    /// the same game with a fixed-up header has the same CRC.
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+    /// Load an iNES file, with an IPS or BPS patch applied to it first (see
+    /// `patch`).
+    pub fn from_reader_with_patch<R: Read, P: Read>(
+        mut rom: R,
+        mut patch: P,
+    ) -> Result<Cartridge, CartridgeError> {
+        let mut rom_bytes = vec![];
+        rom.read_to_end(&mut rom_bytes)?;
+        let mut patch_bytes = vec![];
+        patch.read_to_end(&mut patch_bytes)?;
+        let patched =
+            patch::apply_patch(&rom_bytes, &patch_bytes).map_err(CartridgeError::Patch)?;
+        Cartridge::from_reader(&patched[..])
     }
 
+    /// Read from the cartridge's part of the CPU's address space
+    /// (`$4020-$FFFF`). NROM puts PRG ROM at `$8000-$FFFF`: 32KiB maps
+    /// straight in, and 16KiB shows up twice, so the vectors at the top are
+    /// the same as the ones at `$BFFA`. There's nothing below `$8000` (we
+    /// don't do save RAM yet), so that's open bus, which we call 0.
+    // TODO: when there's PRG RAM at $6000-$7FFF, it needs an enable flag
+    // and a write-protect flag too. MMC1 and MMC3 both have register bits
+    // for these, and games clear them before resetting so that the save
+    // doesn't get scribbled on. Disabled means open bus here, and writes
+    // that go nowhere. (We only do NROM so far, which has no such bits.)
+    pub fn perform_prg_read(&self, address: u16) -> u8 {
+        if address < 0x8000 || self.prg_data.is_empty() {
+            return 0;
+        }
+        let offset = (address - 0x8000) as usize;
+        self.prg_data[offset % self.prg_data.len()]
+    }
     pub fn perform_chr_read(&self, address: u16) -> u8 {
         self.chr_data[(address as usize) % self.chr_data.len()]
     }
 
     pub(crate) fn perform_chr_write(&mut self, address: u16, data: u8) {
-        if false {
-            let length = self.chr_data.len();
-            self.chr_data[(address as usize) % length] = data;
+        warn!("We have CHR ROM, but the game wrote {data:02X} to {address:04X}!");
+    }
+    /// Write to CHR, for when there's CHR RAM, and keep `decoded_tile_rows`
+    /// up to date. We don't load cartridges with CHR RAM yet, so for now
+    /// only the tests write through this.
+    #[cfg(test)]
+    fn write_chr_ram(&mut self, address: u16, data: u8) {
+        let length = self.chr_data.len();
+        self.chr_data[(address as usize) % length] = data;
+        // Whichever plane we wrote, that row needs decoding again.
+        let row_address = (address as usize % length) & !0b1000;
+        self.decoded_tile_rows[tile_row_index(row_address)] = self.decode_tile_row(row_address);
+    }
+    /// Get the 2-bit color index (0-3) of one pixel of the tile whose data
+    /// starts at `tile_address`.
+    ///
+    /// A tile is 16 bytes: two 8x8 "bitplanes", one byte per row. Bit 0 of
+    /// the color comes from the low plane at `tile_address + y`, bit 1 from
+    /// the high plane at `tile_address + y + 8`. Within each byte, bit 7 is
+    /// the leftmost pixel (`x = 0`) and bit 0 is the rightmost (`x = 7`).
+    ///
+    /// For example, a tile whose first row is `$41` in the low plane and
+    /// `$C0` in the high plane has colors `2, 3, 0, 0, 0, 0, 0, 1` in that
+    /// row.
+    ///
+    /// `y` is added to the address as-is, so `y` = 16-23 reads the rows of
+    /// the *next* tile. (8x16 sprites rely on this.)
+    pub fn get_tile(&self, tile_address: u16, x: usize, y: usize) -> u8 {
+        let row_address = (tile_address as usize + y) % self.chr_data.len();
+        if row_address & 0b1000 == 0 {
+            self.decoded_tile_rows[tile_row_index(row_address)][x]
         } else {
-            warn!("We have CHR ROM, but the game wrote {data:02X} to {address:04X}!");
-        }
-    }
-    pub fn get_tile(
-        &self,
-        tile_address: u16,
-        x_within_sprite: usize,
-        y_within_sprite: usize,
-    ) -> u8 {
-        let x_within_sprite = 7 - x_within_sprite;
-        let low_byte = self.perform_chr_read(tile_address + y_within_sprite as u16);
-        let high_byte = self.perform_chr_read(tile_address + y_within_sprite as u16 + 8);
-        let mask = 1 << x_within_sprite;
-        let low_masked = (low_byte & mask) >> x_within_sprite;
-        let high_masked = (high_byte & mask) >> x_within_sprite << 1;
-        let sprite_color = low_masked | high_masked;
-        sprite_color
+            // Someone's asking for a "row" that starts in the upper plane.
+            // Nobody does this on purpose, but we can still answer.
+            self.decode_tile_row(row_address)[x]
+        }
+    }
+    /// Decode one row of one tile, starting at the given address of its lower
+    /// plane byte.
+    fn decode_tile_row(&self, row_address: usize) -> [u8; 8] {
+        let low_byte = self.perform_chr_read(row_address as u16);
+        let high_byte = self.perform_chr_read(row_address as u16 + 8);
+        let mut result = [0; 8];
+        for (x, pixel) in result.iter_mut().enumerate() {
+            // leftmost pixel is the most significant bit
+            let bit = 7 - x;
+            let mask = 1 << bit;
+            let low_masked = (low_byte & mask) >> bit;
+            let high_masked = (high_byte & mask) >> bit << 1;
+            *pixel = low_masked | high_masked;
+        }
+        result
+    }
+    fn decode_all_tile_rows(&mut self) {
+        self.decoded_tile_rows = (0..self.chr_data.len())
+            .filter(|row_address| row_address & 0b1000 == 0)
+            .map(|row_address| self.decode_tile_row(row_address))
+            .collect();
+    }
+}
+
+/// Where in `decoded_tile_rows` to find the row whose lower plane is at the
+/// given CHR address. Tiles are 16 bytes, and only the first 8 (the lower
+/// plane) start a row, so: eight rows per tile, tile after tile.
+fn tile_row_index(row_address: usize) -> usize {
+    (row_address >> 4) * 8 + (row_address & 0b111)
+}
+
+/// The usual CRC32 (the one zip files use), one bit at a time. We only do
+/// this once per cartridge (or patch), so it doesn't need to be fast.
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for byte in chunks.iter().flat_map(|x| x.iter()) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An iNES file with `prg_banks` 16KiB banks of PRG, `chr_banks` 8KiB
+    /// banks of CHR, and header flags 6 and 7. The PRG and CHR bytes are
+    /// noise, but the same noise every time.
+    fn ines_file(prg_banks: u8, chr_banks: u8, flags_6: u8, flags_7: u8) -> Vec<u8> {
+        let mut result = vec![
+            b'N', b'E', b'S', 0x1A, prg_banks, chr_banks, flags_6, flags_7,
+        ];
+        result.resize(16, 0);
+        let data_size = prg_banks as usize * PRG_CHUNK_SIZE + chr_banks as usize * CHR_CHUNK_SIZE;
+        result.extend((0..data_size as u32).map(|x| (x.wrapping_mul(2_654_435_761) >> 24) as u8));
+        result
+    }
+
+    /// Decode one pixel straight from CHR, the slow way, to check
+    /// `get_tile` against.
+    fn decode_pixel(chr_data: &[u8], tile_address: u16, x: usize, y: usize) -> u8 {
+        let row_address = tile_address as usize + y;
+        let low_byte = chr_data[row_address % chr_data.len()];
+        let high_byte = chr_data[(row_address + 8) % chr_data.len()];
+        ((low_byte >> (7 - x)) & 1) | (((high_byte >> (7 - x)) & 1) << 1)
+    }
+
+    #[test]
+    fn cached_tile_rows_match_decoding_every_time() {
+        let cartridge = Cartridge::from_reader(&ines_file(1, 1, 0, 0)[..]).unwrap();
+        for tile_address in (0..CHR_CHUNK_SIZE as u16).step_by(16) {
+            // 16 rows, so the rows of the next tile get checked too.
+            for y in 0..16 {
+                for x in 0..8 {
+                    assert_eq!(
+                        cartridge.get_tile(tile_address, x, y),
+                        decode_pixel(&cartridge.chr_data, tile_address, x, y),
+                        "tile {tile_address:04X}, x {x}, y {y}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chr_ram_writes_decode_the_row_again() {
+        let mut cartridge = Cartridge::from_reader(&ines_file(1, 1, 0, 0)[..]).unwrap();
+        // One byte in the low plane and one in the high plane.
+        for address in [0x0123, 0x0A3D] {
+            cartridge.write_chr_ram(address, !cartridge.chr_data[address as usize]);
+        }
+        for tile_address in [0x0120, 0x0A30] {
+            for y in 0..8 {
+                for x in 0..8 {
+                    assert_eq!(
+                        cartridge.get_tile(tile_address, x, y),
+                        decode_pixel(&cartridge.chr_data, tile_address, x, y),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tile_planes_and_bit_order() {
+        let mut file = ines_file(1, 1, 0, 0);
+        let tile = [
+            // The low plane...
+            0x41, 0x80, 0, 0, 0, 0, 0, 0x01, //
+            // ...and the high plane.
+            0xC0, 0x01, 0, 0, 0, 0, 0, 0xFF,
+        ];
+        file[16 + PRG_CHUNK_SIZE..][..16].copy_from_slice(&tile);
+        let cartridge = Cartridge::from_reader(&file[..]).unwrap();
+        let row = |y| {
+            (0..8)
+                .map(|x| cartridge.get_tile(0, x, y))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(row(0), [2, 3, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(row(1), [1, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(row(2), [0; 8]);
+        assert_eq!(row(7), [2, 2, 2, 2, 2, 2, 2, 3]);
+    }
+
+    #[test]
+    fn no_chr_rom_is_an_error() {
+        let result = Cartridge::from_reader(&ines_file(1, 0, 0, 0)[..]);
+        assert!(matches!(result, Err(CartridgeError::UnsupportedChrRam)));
+    }
+
+    /// How much the cache saves. Run with `--ignored --nocapture`, in
+    /// release mode if you want numbers that mean anything.
+    #[test]
+    #[ignore]
+    fn time_cached_and_uncached_tile_rows() {
+        let cartridge = Cartridge::from_reader(&ines_file(1, 1, 0, 0)[..]).unwrap();
+        let time = |what: &str, get_pixel: &dyn Fn(u16, usize, usize) -> u8| {
+            let start = std::time::Instant::now();
+            let mut total = 0u32;
+            for _ in 0..100 {
+                for tile_address in (0..CHR_CHUNK_SIZE as u16).step_by(16) {
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            total += get_pixel(tile_address, x, y) as u32;
+                        }
+                    }
+                }
+            }
+            println!("{what}: {:?} (total {total})", start.elapsed());
+        };
+        time("cached", &|tile_address, x, y| {
+            cartridge.get_tile(tile_address, x, y)
+        });
+        time("uncached", &|tile_address, x, y| {
+            cartridge.decode_tile_row(tile_address as usize + y)[x]
+        });
+    }
+    #[test]
+    fn prg_mapping() {
+        // 16KiB shows up twice, vectors and all.
+        let cartridge = Cartridge::from_reader(&ines_file(1, 1, 0, 0)[..]).unwrap();
+        for offset in [0x0000, 0x1234, 0x3FFA, 0x3FFC, 0x3FFE, 0x3FFF] {
+            let expected = cartridge.prg_data[offset as usize];
+            assert_eq!(cartridge.perform_prg_read(0x8000 + offset), expected);
+            assert_eq!(cartridge.perform_prg_read(0xC000 + offset), expected);
+        }
+        // 32KiB goes straight in.
+        let cartridge = Cartridge::from_reader(&ines_file(2, 1, 0, 0)[..]).unwrap();
+        let prg = &cartridge.prg_data;
+        assert_eq!(cartridge.perform_prg_read(0xC000), prg[0x4000]);
+        assert_eq!(cartridge.perform_prg_read(0xFFFC), prg[0x7FFC]);
+        // And below $8000 is open bus.
+        assert_eq!(cartridge.perform_prg_read(0x6000), 0);
+    }
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF43926);
+        // However it's cut up.
+        assert_eq!(crc32(&[b"1234", b"", b"56789"]), 0xCBF43926);
+        assert_eq!(crc32(&[]), 0);
+    }
+    #[test]
+    fn playchoice_10_loads_like_a_nes_game() {
+        let mut file = ines_file(2, 1, 0, 0x02);
+        let (prg, chr) = (file[16..][..0x8000].to_vec(), file[16 + 0x8000..].to_vec());
+        // INST-ROM and PROM, which should both get left behind.
+        file.extend([0xAA; 8 * 1024 + 32]);
+        let cartridge = Cartridge::from_reader(&file[..]).unwrap();
+        assert_eq!(cartridge.console_type, ConsoleType::PlayChoice10);
+        assert_eq!(cartridge.prg_data, prg);
+        assert_eq!(cartridge.chr_data, chr);
+    }
+    #[test]
+    fn oversized_nrom_loads_with_a_warning() {
+        // 48KiB of PRG ROM is more than NROM can see, but it still loads.
+        let cartridge = Cartridge::from_reader(&ines_file(3, 1, 0, 0)[..]).unwrap();
+        assert_eq!(cartridge.prg_data.len(), 48 * 1024);
+        let warnings = nrom_size_warnings(48 * 1024, 8 * 1024);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("48KiB of PRG ROM"));
+        let warnings = nrom_size_warnings(32 * 1024, 16 * 1024);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("16KiB of CHR ROM"));
+        assert!(nrom_size_warnings(32 * 1024, 8 * 1024).is_empty());
+        assert!(nrom_size_warnings(16 * 1024, 8 * 1024).is_empty());
+    }
+    /// A reader that breaks right away.
+    struct BrokenReader;
+
+    impl Read for BrokenReader {
+        fn read(&mut self, _buffer: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("unplugged"))
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let load = |file: &[u8]| Cartridge::from_reader(file).err();
+        assert!(matches!(load(b"NES"), Some(CartridgeError::NotInes)));
+        assert!(matches!(
+            load(b"SNES and then some more bytes"),
+            Some(CartridgeError::NotInes)
+        ));
+        assert!(matches!(
+            load(&ines_file(1, 1, 0x40, 0)),
+            Some(CartridgeError::UnsupportedMapper(4))
+        ));
+        assert!(matches!(
+            load(&ines_file(1, 1, 0x04, 0)),
+            Some(CartridgeError::UnsupportedTrainer)
+        ));
+        assert!(matches!(
+            load(&ines_file(1, 1, 0x02, 0)),
+            Some(CartridgeError::UnsupportedSaveRam)
+        ));
+        let file = ines_file(1, 1, 0, 0);
+        assert!(matches!(
+            load(&file[..16 + PRG_CHUNK_SIZE - 1]),
+            Some(CartridgeError::TruncatedPrg)
+        ));
+        assert!(matches!(
+            load(&file[..file.len() - 1]),
+            Some(CartridgeError::TruncatedChr)
+        ));
+        assert!(matches!(
+            Cartridge::from_reader(BrokenReader),
+            Err(CartridgeError::Io(_))
+        ));
+        assert!(matches!(
+            Cartridge::new("/this/is/not/a/rom.nes"),
+            Err(CartridgeError::Io(_))
+        ));
+    }
+    #[test]
+    fn nametables_mirror_the_way_each_mirroring_type_says() {
+        use MirroringType::*;
+        for (mirroring, expected) in [
+            (Horizontal, [0x000, 0x000, 0x400, 0x400]),
+            (Vertical, [0x000, 0x400, 0x000, 0x400]),
+            (SingleScreenLower, [0x000, 0x000, 0x000, 0x000]),
+            (SingleScreenUpper, [0x400, 0x400, 0x400, 0x400]),
+            (FourScreen, [0x000, 0x400, 0x800, 0xC00]),
+        ] {
+            for (address, offset) in [0x2000, 0x2400, 0x2800, 0x2C00].into_iter().zip(expected) {
+                assert_eq!(
+                    mirroring.map_nametable_address(address),
+                    offset,
+                    "{mirroring:?} ${address:04X}",
+                );
+                // The byte at the far end of the same nametable.
+                assert_eq!(
+                    mirroring.map_nametable_address(address + 0x3FF),
+                    offset + 0x3FF,
+                    "{mirroring:?} ${:04X}",
+                    address + 0x3FF,
+                );
+            }
+        }
     }
 }
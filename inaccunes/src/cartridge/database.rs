@@ -0,0 +1,59 @@
+//! Overrides for cartridges whose iNES headers lie. See `database.txt` for
+//! the format.
+
+use log::*;
+
+use super::MirroringType;
+
+const DATABASE: &str = include_str!("database.txt");
+
+/// What we know better than the header. `None` means "believe the header".
+#[derive(Debug, Default)]
+pub struct HeaderOverride {
+    pub mapper_type: Option<u8>,
+    pub mirroring_type: Option<MirroringType>,
+    pub has_save_ram: Option<bool>,
+}
+
+/// Look up a cartridge by the CRC32 of its PRG data followed by its CHR data.
+pub fn lookup(crc: u32) -> Option<HeaderOverride> {
+    for (line_number, line) in DATABASE.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let mut fields = line.split_whitespace();
+        let Some(line_crc) = fields.next() else {
+            continue;
+        };
+        if u32::from_str_radix(line_crc, 16) != Ok(crc) {
+            continue;
+        }
+        let mut result = HeaderOverride::default();
+        for field in fields {
+            match field.split_once('=') {
+                Some(("mapper", x)) => result.mapper_type = x.parse().ok(),
+                Some(("mirroring", x)) => {
+                    result.mirroring_type = match x {
+                        "horizontal" => Some(MirroringType::Horizontal),
+                        "vertical" => Some(MirroringType::Vertical),
+                        "single-lower" => Some(MirroringType::SingleScreenLower),
+                        "single-upper" => Some(MirroringType::SingleScreenUpper),
+                        "four-screen" => Some(MirroringType::FourScreen),
+                        _ => {
+                            warn!(
+                                "Cartridge database line {}: unknown mirroring {x:?}",
+                                line_number + 1
+                            );
+                            None
+                        }
+                    }
+                }
+                Some(("save_ram", x)) => result.has_save_ram = Some(x == "yes"),
+                _ => warn!(
+                    "Cartridge database line {}: don't know what {field:?} means",
+                    line_number + 1
+                ),
+            }
+        }
+        return Some(result);
+    }
+    None
+}
@@ -0,0 +1,276 @@
+//! ROM patches, which is how most translations, hacks, and bug fixes get
+//! passed around. We do the two common formats:
+//!
+//! - IPS: `PATCH`, then a list of "put these bytes at this offset" records,
+//!   then `EOF`. Dead simple, no checksums, can't go past 16MiB.
+//! - BPS: `BPS1`, then a list of actions that build the new ROM out of
+//!   pieces of the old one (and of itself), then CRC32s of the old ROM, the
+//!   new ROM, and the patch, so we can tell if it's the wrong patch.
+//!
+//! Patches apply to the whole file, header and all, same as every other
+//! patching tool.
+
+use anyhow::anyhow;
+
+use super::crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+/// An IPS record at this offset is really the end of the patch.
+const IPS_EOF: usize = 0x454F46; // "EOF"
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Three CRC32s at the end of every BPS patch.
+const BPS_FOOTER_SIZE: usize = 12;
+
+/// Apply `patch` (IPS or BPS, we can tell which) to `rom`, and return the
+/// patched ROM.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if let Some(records) = patch.strip_prefix(IPS_MAGIC) {
+        apply_ips(rom, records)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(anyhow!("That's not an IPS or BPS patch"))
+    }
+}
+
+/// Take `length` bytes off the front of `data`.
+fn take<'a>(data: &mut &'a [u8], length: usize) -> anyhow::Result<&'a [u8]> {
+    if data.len() < length {
+        return Err(anyhow!("The patch ends too soon"));
+    }
+    let (result, rest) = data.split_at(length);
+    *data = rest;
+    Ok(result)
+}
+
+/// A big-endian number, `length` bytes long, off the front of `data`.
+fn take_be(data: &mut &[u8], length: usize) -> anyhow::Result<usize> {
+    Ok(take(data, length)?
+        .iter()
+        .fold(0, |result, byte| (result << 8) | *byte as usize))
+}
+
+fn apply_ips(rom: &[u8], mut records: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut result = rom.to_vec();
+    loop {
+        let offset = take_be(&mut records, 3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let length = take_be(&mut records, 2)?;
+        // A length of 0 means "run length encoded": a count, and then one
+        // byte to repeat that many times.
+        let (length, data) = if length == 0 {
+            let length = take_be(&mut records, 2)?;
+            (length, vec![take(&mut records, 1)?[0]; length])
+        } else {
+            (length, take(&mut records, length)?.to_vec())
+        };
+        if result.len() < offset + length {
+            // Records are allowed to make the file bigger.
+            result.resize(offset + length, 0);
+        }
+        result[offset..offset + length].copy_from_slice(&data);
+    }
+    // Some patchers tack on a length to cut the file down to, too.
+    if records.len() >= 3 {
+        let truncated_length = take_be(&mut records, 3)?;
+        result.truncate(truncated_length);
+    }
+    Ok(result)
+}
+
+/// BPS numbers are variable length: seven bits per byte, least significant
+/// first, with the top bit set on the last byte. Each byte after the first
+/// also adds one to what's above it, so there's only one way to write any
+/// number.
+fn take_bps_number(data: &mut &[u8]) -> anyhow::Result<usize> {
+    let mut result = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = take(data, 1)?[0];
+        result = ((byte & 0x7F) as usize)
+            .checked_mul(shift)
+            .and_then(|x| result.checked_add(x))
+            .ok_or_else(|| anyhow!("Number too big in the patch"))?;
+        if byte & 0x80 != 0 {
+            return Ok(result);
+        }
+        shift = shift
+            .checked_mul(0x80)
+            .ok_or_else(|| anyhow!("Number too big in the patch"))?;
+        result = result
+            .checked_add(shift)
+            .ok_or_else(|| anyhow!("Number too big in the patch"))?;
+    }
+}
+
+/// A BPS copy offset: a number whose bottom bit is the sign.
+fn take_bps_offset(data: &mut &[u8]) -> anyhow::Result<isize> {
+    let number = take_bps_number(data)?;
+    let magnitude = (number >> 1) as isize;
+    Ok(if number & 1 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    })
+}
+
+/// Move `position` by `offset`, as long as that lands within `0..limit`.
+fn seek(position: usize, offset: isize, limit: usize) -> anyhow::Result<usize> {
+    position
+        .checked_add_signed(offset)
+        .filter(|x| *x < limit)
+        .ok_or_else(|| anyhow!("The patch wants to copy from outside the ROM"))
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err(anyhow!("The patch ends too soon"));
+    }
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER_SIZE);
+    let read_crc =
+        |index: usize| u32::from_le_bytes(footer[index * 4..index * 4 + 4].try_into().unwrap());
+    let (source_crc, target_crc, patch_crc) = (read_crc(0), read_crc(1), read_crc(2));
+    if crc32(&[body, &footer[..8]]) != patch_crc {
+        return Err(anyhow!("The patch is corrupted (its checksum is wrong)"));
+    }
+    if crc32(&[rom]) != source_crc {
+        return Err(anyhow!("This patch is for a different ROM"));
+    }
+    let mut actions = &body[BPS_MAGIC.len()..];
+    let source_size = take_bps_number(&mut actions)?;
+    let target_size = take_bps_number(&mut actions)?;
+    let metadata_size = take_bps_number(&mut actions)?;
+    take(&mut actions, metadata_size)?;
+    if source_size != rom.len() {
+        return Err(anyhow!("This patch is for a different ROM"));
+    }
+    let mut result = vec![];
+    let (mut source_position, mut target_position) = (0usize, 0usize);
+    while !actions.is_empty() {
+        let action = take_bps_number(&mut actions)?;
+        let length = (action >> 2) + 1;
+        if result.len() + length > target_size {
+            return Err(anyhow!("The patch makes the ROM too big"));
+        }
+        match action & 3 {
+            // Copy from the old ROM, from the same place we're writing to.
+            0 => {
+                let start = result.len();
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or_else(|| anyhow!("The patch wants to copy from outside the ROM"))?;
+                result.extend_from_slice(bytes);
+            }
+            // New bytes, straight out of the patch.
+            1 => result.extend_from_slice(take(&mut actions, length)?),
+            // Copy from anywhere in the old ROM.
+            2 => {
+                source_position = seek(source_position, take_bps_offset(&mut actions)?, rom.len())?;
+                let bytes = rom
+                    .get(source_position..source_position + length)
+                    .ok_or_else(|| anyhow!("The patch wants to copy from outside the ROM"))?;
+                result.extend_from_slice(bytes);
+                source_position += length;
+            }
+            // Copy from what we've written so far. This can overlap what
+            // it's writing (that's how BPS does runs), so one at a time.
+            _ => {
+                target_position = seek(
+                    target_position,
+                    take_bps_offset(&mut actions)?,
+                    result.len(),
+                )?;
+                for _ in 0..length {
+                    result.push(result[target_position]);
+                    target_position += 1;
+                }
+            }
+        }
+    }
+    if result.len() != target_size || crc32(&[&result]) != target_crc {
+        return Err(anyhow!("The patch didn't come out right (wrong checksum)"));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ips() {
+        let rom: Vec<u8> = (0..16).collect();
+        let mut patch = IPS_MAGIC.to_vec();
+        // Three bytes at 2...
+        patch.extend([0x00, 0x00, 0x02, 0x00, 0x03, 0xAA, 0xBB, 0xCC]);
+        // ...four $EEs at 8...
+        patch.extend([0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x04, 0xEE]);
+        // ...and two bytes past the end, which makes the file bigger.
+        patch.extend([0x00, 0x00, 0x12, 0x00, 0x02, 0x11, 0x22]);
+        patch.extend(b"EOF");
+        let mut expected = vec![0, 1, 0xAA, 0xBB, 0xCC, 5, 6, 7, 0xEE, 0xEE, 0xEE, 0xEE];
+        expected.extend([12, 13, 14, 15, 0, 0, 0x11, 0x22]);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), expected);
+        // The same, cut down to 12 bytes after the EOF.
+        patch.extend([0x00, 0x00, 0x0C]);
+        expected.truncate(12);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), expected);
+        // And no EOF at all is a broken patch.
+        assert!(apply_patch(&rom, &patch[..patch.len() - 6]).is_err());
+    }
+
+    /// Add `number` to `patch` the way BPS writes them (see
+    /// `take_bps_number`).
+    fn put_bps_number(patch: &mut Vec<u8>, mut number: usize) {
+        loop {
+            let low_bits = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                patch.push(0x80 | low_bits);
+                return;
+            }
+            patch.push(low_bits);
+            number -= 1;
+        }
+    }
+
+    /// Add one BPS action to `patch`: which kind, and how long.
+    fn put_bps_action(patch: &mut Vec<u8>, kind: usize, length: usize) {
+        put_bps_number(patch, ((length - 1) << 2) | kind);
+    }
+
+    #[test]
+    fn bps() {
+        let rom = b"Hello, world! 0123456789";
+        let expected = b"HelloNES0123333";
+        let mut patch = BPS_MAGIC.to_vec();
+        put_bps_number(&mut patch, rom.len());
+        put_bps_number(&mut patch, expected.len());
+        put_bps_number(&mut patch, 0);
+        // "Hello", from the same place in the ROM.
+        put_bps_action(&mut patch, 0, 5);
+        // "NES", from the patch.
+        put_bps_action(&mut patch, 1, 3);
+        patch.extend(b"NES");
+        // "0123", from 14 bytes into the ROM.
+        put_bps_action(&mut patch, 2, 4);
+        put_bps_number(&mut patch, 14 << 1);
+        // And "333", by copying the last "3" over and over.
+        put_bps_action(&mut patch, 3, 3);
+        put_bps_number(&mut patch, 11 << 1);
+        patch.extend(crc32(&[rom]).to_le_bytes());
+        patch.extend(crc32(&[expected]).to_le_bytes());
+        patch.extend(crc32(&[&patch]).to_le_bytes());
+        assert_eq!(apply_patch(rom, &patch).unwrap(), expected);
+        // It's not for some other ROM.
+        let error = apply_patch(b"Hello, world! 0123456788", &patch).unwrap_err();
+        assert!(error.to_string().contains("different ROM"), "{error}");
+        // And if the patch gets mangled, its own CRC catches it.
+        let mut corrupted = patch.clone();
+        corrupted[BPS_MAGIC.len() + 4] ^= 1;
+        let error = apply_patch(rom, &corrupted).unwrap_err();
+        assert!(error.to_string().contains("corrupted"), "{error}");
+    }
+}
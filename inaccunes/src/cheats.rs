@@ -0,0 +1,212 @@
+//! Cheat codes, the Game Genie way: the Game Genie sits between the console
+//! and the cartridge, and when the CPU reads one particular address, it
+//! answers with a different byte instead of what's really in the ROM.
+//!
+//! Codes can be actual Game Genie codes (six or eight letters), or "raw"
+//! codes that just say what they do: `AAAA:VV` to always read `VV` from
+//! `AAAA`, or `AAAA?CC:VV` to only do it when the ROM really has `CC`
+//! there (which is what an eight letter code does).
+
+use anyhow::{anyhow, Context};
+
+/// The Game Genie alphabet. Each letter is worth its position in here.
+const GAME_GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cheat {
+    pub address: u16,
+    /// What to read instead.
+    pub value: u8,
+    /// If present, only substitute `value` when the real byte is this.
+    /// Banked games have a lot of different code at the same address, and
+    /// this keeps a cheat from landing on the wrong one.
+    pub compare: Option<u8>,
+}
+
+impl Cheat {
+    /// Turn a Game Genie code or a raw code into a `Cheat`.
+    pub fn parse(code: &str) -> anyhow::Result<Cheat> {
+        let code = code.trim();
+        if code.contains(':') {
+            Cheat::parse_raw(code)
+        } else {
+            Cheat::parse_game_genie(code)
+        }
+    }
+    fn parse_raw(code: &str) -> anyhow::Result<Cheat> {
+        let (left, value) = code.split_once(':').unwrap();
+        let (address, compare) = match left.split_once('?') {
+            Some((address, compare)) => (address, Some(compare)),
+            None => (left, None),
+        };
+        let parse_hex = |x: &str, what: &str, digits: usize| {
+            if x.len() != digits {
+                return Err(anyhow!("{x:?} is not a {digits} digit {what}"));
+            }
+            u16::from_str_radix(x, 16).with_context(|| format!("{x:?} is not a hex {what}"))
+        };
+        let address = parse_hex(address, "address", 4)?;
+        if address < 0x8000 {
+            return Err(anyhow!(
+                "Cheats only work on the cartridge's ROM ($8000-$FFFF), not ${address:04X}"
+            ));
+        }
+        Ok(Cheat {
+            address,
+            value: parse_hex(value, "value", 2)? as u8,
+            compare: compare
+                .map(|x| parse_hex(x, "compare value", 2))
+                .transpose()?
+                .map(|x| x as u8),
+        })
+    }
+    /// The letters are a scrambled-up bit field:
+    ///
+    /// ```text
+    /// letter:  0       1       2       3       4       5       6       7
+    /// bit:     3 2 1 0 3 2 1 0 3 2 1 0 3 2 1 0 3 2 1 0 3 2 1 0 3 2 1 0 3 2 1 0
+    /// 6 long:  1 6 7 8 H 2 3 4 - I J K L A B C D M N O 5 E F G
+    /// 8 long:  1 6 7 8 H 2 3 4 - I J K L A B C D M N O % E F G ! ^ & * 5 @ # $
+    /// ```
+    ///
+    /// where the value is `12345678`, the compare value is `!@#$%^&*`, and
+    /// the address is `$8000` plus `ABCDEFGHIJKLMNO`. (Bit 3 of letter 2
+    /// is supposed to say how long the code is, but the real Game Genie
+    /// just counts the letters, and so do we.)
+    fn parse_game_genie(code: &str) -> anyhow::Result<Cheat> {
+        let letters = code
+            .bytes()
+            .map(|letter| {
+                GAME_GENIE_LETTERS
+                    .iter()
+                    .position(|x| *x == letter.to_ascii_uppercase())
+                    .map(|x| x as u16)
+                    .ok_or_else(|| anyhow!("{:?} is not a Game Genie letter", letter as char))
+            })
+            .collect::<anyhow::Result<Vec<u16>>>()
+            .with_context(|| format!("Bad cheat code {code:?}"))?;
+        let n = match letters.len() {
+            6 | 8 => &letters,
+            _ => {
+                return Err(anyhow!(
+                    "Bad cheat code {code:?}: Game Genie codes are 6 or 8 letters long"
+                ))
+            }
+        };
+        let address = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[4] & 8) << 8)
+            | ((n[5] & 7) << 8)
+            | ((n[1] & 8) << 4)
+            | ((n[2] & 7) << 4)
+            | (n[3] & 8)
+            | (n[4] & 7);
+        // Bit 3 of the value comes from the last letter, whichever that is.
+        let value = ((n[0] & 8) << 4) | ((n[1] & 7) << 4) | (n[n.len() - 1] & 8) | (n[0] & 7);
+        let compare =
+            (n.len() == 8).then(|| ((n[6] & 8) << 4) | ((n[7] & 7) << 4) | (n[5] & 8) | (n[6] & 7));
+        Ok(Cheat {
+            address,
+            value: value as u8,
+            compare: compare.map(|x| x as u8),
+        })
+    }
+    /// What the CPU should see at `address`, if the real byte is `data`.
+    fn apply(&self, address: u16, data: u8) -> u8 {
+        if address == self.address && self.compare.is_none_or(|x| x == data) {
+            self.value
+        } else {
+            data
+        }
+    }
+}
+
+/// Every cheat that's turned on.
+#[derive(Debug, Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+    pub fn get_cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+    /// What the CPU should see when it reads `data` from the cartridge at
+    /// `address`.
+    pub fn apply(&self, address: u16, data: u8) -> u8 {
+        self.cheats
+            .iter()
+            .fold(data, |data, cheat| cheat.apply(address, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn six_letter_code() {
+        // Super Mario Bros., infinite lives.
+        let cheat = Cheat::parse("SXIOPO").unwrap();
+        assert_eq!(
+            cheat,
+            Cheat {
+                address: 0x91D9,
+                value: 0xAD,
+                compare: None,
+            }
+        );
+        // Lowercase is fine too.
+        assert_eq!(Cheat::parse("sxiopo").unwrap(), cheat);
+    }
+
+    #[test]
+    fn eight_letter_codes() {
+        assert_eq!(
+            Cheat::parse("AAEAULPA").unwrap(),
+            Cheat {
+                address: 0x8B03,
+                value: 0x00,
+                compare: Some(0x01),
+            }
+        );
+        assert_eq!(
+            Cheat::parse("YEUZUGAA").unwrap(),
+            Cheat {
+                address: 0xACB3,
+                value: 0x07,
+                compare: Some(0x00),
+            }
+        );
+    }
+
+    #[test]
+    fn raw_codes() {
+        assert_eq!(
+            Cheat::parse("91D9:AD").unwrap(),
+            Cheat::parse("SXIOPO").unwrap()
+        );
+        assert_eq!(
+            Cheat::parse("8B03?01:00").unwrap(),
+            Cheat::parse("AAEAULPA").unwrap()
+        );
+        for bad in ["SXIOP", "SXIOPQ", "91D9:A", "1234:00", "91D9?1:00"] {
+            assert!(Cheat::parse(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn compare_byte() {
+        let mut cheats = Cheats::default();
+        cheats.add(Cheat::parse("8B03?01:00").unwrap());
+        assert_eq!(cheats.apply(0x8B03, 0x01), 0x00);
+        assert_eq!(cheats.apply(0x8B03, 0x02), 0x02);
+        assert_eq!(cheats.apply(0x8B04, 0x01), 0x01);
+    }
+}
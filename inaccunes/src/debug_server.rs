@@ -0,0 +1,322 @@
+//! A tiny GDB remote protocol stub, so you can poke at a running game from a
+//! debugger. Start inaccunes with `--debug-port 6502`, and then, from your
+//! debugger of choice, `target remote localhost:6502`.
+//!
+//! GDB doesn't know what a 6502 is, so the register layout is our own. The
+//! `g` and `G` packets use these seven bytes, in this order:
+//!
+//! ```text
+//! A X Y S P PCL PCH
+//! ```
+//!
+//! (That is, PC is little-endian, like everything else on the 6502.)
+//!
+//! The packets we understand are:
+//!
+//! - `?`: why did we stop? (Always `S05`, SIGTRAP.)
+//! - `g` / `G XX...`: read / write all the registers.
+//! - `m addr,length` / `M addr,length:XX...`: read / write memory. Reads
+//!   don't have side effects (PPU registers read as 0), and writes only
+//!   work on work RAM.
+//! - `s [addr]`: run one instruction (from `addr`, if given).
+//! - `c [addr]`: keep running (from `addr`, if given) until a breakpoint,
+//!   or until the debugger sends a Ctrl-C.
+//! - `Z0,addr,kind` / `z0,addr,kind`: add / remove a software breakpoint.
+//!   We don't touch memory for these, so ROM breakpoints work just fine.
+//! - `Z2`, `Z3`, `Z4` (and `z`): add (remove) a write, read, or access
+//!   watchpoint. The CPU stops right after the instruction that touched the
+//!   address, and the stop reply says which watchpoint it was.
+//! - `D` / `k`: detach / kill. Either way, the game keeps running and we
+//!   wait for another debugger to connect.
+//!
+//! Anything else gets the empty reply, which is GDB for "huh?"
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use inaccunes::system::{System, WatchKind, Watchpoint};
+use log::*;
+
+const STOP_REPLY: &str = "S05";
+/// The most bytes one `m` packet can ask for: all of CPU memory. The length
+/// comes straight off the wire, so without this, a typo (or anyone who can
+/// reach the port) could make us try to allocate way too much.
+const MAX_READ_LENGTH: usize = 0x10000;
+
+pub struct DebugServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    /// Bytes we've received that aren't a complete packet yet.
+    buffer: Vec<u8>,
+    is_halted: bool,
+}
+
+impl DebugServer {
+    pub fn new(port: u16) -> anyhow::Result<DebugServer> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        info!("Debug server listening on port {port}");
+        Ok(DebugServer {
+            listener,
+            client: None,
+            buffer: vec![],
+            is_halted: false,
+        })
+    }
+    /// Deal with whatever the debugger has sent us since last time. Call this
+    /// once per frame. Returns whether the system should run a frame.
+    pub fn poll(&mut self, system: &mut System) -> bool {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    info!("Debugger connected from {address}");
+                    stream
+                        .set_nonblocking(true)
+                        .expect("Couldn't make the debugger's socket nonblocking");
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    // GDB expects the target to be stopped when it attaches.
+                    self.is_halted = true;
+                }
+                Err(x) if x.kind() == ErrorKind::WouldBlock => (),
+                Err(x) => warn!("Couldn't accept a debugger connection: {x}"),
+            }
+        }
+        if self.client.is_some() {
+            if let Err(x) = self.receive(system) {
+                warn!("Lost the debugger: {x}");
+                self.disconnect(system);
+            }
+        }
+        if !self.is_halted && system.is_at_breakpoint() {
+            self.is_halted = true;
+            let reply = stop_reply(system);
+            self.send_packet(&reply);
+        }
+        !self.is_halted
+    }
+    fn disconnect(&mut self, system: &mut System) {
+        self.client = None;
+        self.is_halted = false;
+        system.resume();
+    }
+    fn receive(&mut self, system: &mut System) -> std::io::Result<()> {
+        let client = self.client.as_mut().unwrap();
+        let mut chunk = [0; 1024];
+        loop {
+            match client.read(&mut chunk) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(x) if x.kind() == ErrorKind::WouldBlock => break,
+                Err(x) => return Err(x),
+            }
+        }
+        loop {
+            // Throw away acks, and anything else that isn't the start of a
+            // packet. A Ctrl-C (0x03) means "stop!"
+            while let Some(&byte) = self.buffer.first() {
+                if byte == b'$' {
+                    break;
+                }
+                if byte == 0x03 && !self.is_halted {
+                    self.is_halted = true;
+                    self.send_packet(STOP_REPLY);
+                }
+                self.buffer.remove(0);
+            }
+            // $packet#XX
+            let Some(end) = self.buffer.iter().position(|x| *x == b'#') else {
+                break;
+            };
+            if self.buffer.len() < end + 3 {
+                break;
+            }
+            let packet = String::from_utf8_lossy(&self.buffer[1..end]).into_owned();
+            self.buffer.drain(..end + 3);
+            self.client.as_mut().unwrap().write_all(b"+")?;
+            debug!("Debugger says: {packet:?}");
+            if let Some(reply) = self.handle_packet(system, &packet) {
+                self.send_packet(&reply);
+            }
+            if self.client.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+    fn send_packet(&mut self, packet: &str) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        let checksum = packet.bytes().fold(0u8, |a, b| a.wrapping_add(b));
+        if let Err(x) = write!(client, "${packet}#{checksum:02x}") {
+            warn!("Couldn't talk to the debugger: {x}");
+        }
+    }
+    /// Do what a packet says. Returns the reply, if there is one right now.
+    fn handle_packet(&mut self, system: &mut System, packet: &str) -> Option<String> {
+        let (command, rest) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => STOP_REPLY.to_string(),
+            "g" => {
+                let cpu = system.get_cpu();
+                let [pc_low, pc_high] = cpu.get_pc().to_le_bytes();
+                to_hex(&[
+                    cpu.get_a(),
+                    cpu.get_x(),
+                    cpu.get_y(),
+                    cpu.get_s(),
+                    cpu.get_p(),
+                    pc_low,
+                    pc_high,
+                ])
+            }
+            "G" => match from_hex(rest).as_deref() {
+                Some(&[a, x, y, s, p, pc_low, pc_high]) => {
+                    let cpu = system.get_cpu_mut();
+                    cpu.set_a(a);
+                    cpu.set_x(x);
+                    cpu.set_y(y);
+                    cpu.set_s(s);
+                    cpu.set_p(p);
+                    cpu.set_pc(u16::from_le_bytes([pc_low, pc_high]));
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "m" => match parse_address_and_length(rest) {
+                Some((address, length)) if length <= MAX_READ_LENGTH => {
+                    let bytes: Vec<u8> = (0..length)
+                        .map(|n| {
+                            system
+                                .get_devices()
+                                .peek_byte(address.wrapping_add(n as u16))
+                        })
+                        .collect();
+                    to_hex(&bytes)
+                }
+                _ => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = rest.split_once(':').and_then(|(header, data)| {
+                    Some((parse_address_and_length(header)?, from_hex(data)?))
+                });
+                match parsed {
+                    Some(((address, length), data)) if data.len() == length => {
+                        let mut all_ok = true;
+                        for (n, byte) in data.into_iter().enumerate() {
+                            all_ok &= system.poke_byte(address.wrapping_add(n as u16), byte);
+                        }
+                        if all_ok { "OK" } else { "E02" }.to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "s" | "c" => {
+                if let Ok(address) = u16::from_str_radix(rest, 16) {
+                    system.get_cpu_mut().set_pc(address);
+                }
+                if command == "s" {
+                    system.step_instruction();
+                    stop_reply(system)
+                } else {
+                    // We'll reply when we stop.
+                    system.resume();
+                    self.is_halted = false;
+                    return None;
+                }
+            }
+            "Z" | "z" => {
+                let mut fields = rest.split(',');
+                let kind = fields.next();
+                let address = fields.next().and_then(|x| u16::from_str_radix(x, 16).ok());
+                let watch_kind = match kind {
+                    Some("2") => Some(WatchKind::Write),
+                    Some("3") => Some(WatchKind::Read),
+                    Some("4") => Some(WatchKind::Access),
+                    _ => None,
+                };
+                match (kind, watch_kind, address) {
+                    (Some("0"), _, Some(address)) => {
+                        if command == "Z" {
+                            system.add_breakpoint(address);
+                        } else {
+                            system.remove_breakpoint(address);
+                        }
+                        "OK".to_string()
+                    }
+                    (_, Some(kind), Some(address)) => {
+                        let watchpoint = Watchpoint { address, kind };
+                        if command == "Z" {
+                            system.add_watchpoint(watchpoint);
+                        } else {
+                            system.remove_watchpoint(watchpoint);
+                        }
+                        "OK".to_string()
+                    }
+                    // No hardware breakpoints, sorry.
+                    _ => String::new(),
+                }
+            }
+            "D" => {
+                self.send_packet("OK");
+                info!("Debugger detached");
+                self.disconnect(system);
+                return None;
+            }
+            "k" => {
+                info!("Debugger killed us. We got better.");
+                self.disconnect(system);
+                return None;
+            }
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+}
+
+/// Why we stopped: a plain SIGTRAP, unless a watchpoint went off.
+fn stop_reply(system: &mut System) -> String {
+    match system.take_watchpoint_hit() {
+        Some(hit) => {
+            info!(
+                "Watchpoint: {} ${:04X} ({:02X})",
+                if hit.was_write { "write to" } else { "read from" },
+                hit.watchpoint.address,
+                hit.value
+            );
+            let kind = match hit.watchpoint.kind {
+                WatchKind::Write => "watch",
+                WatchKind::Read => "rwatch",
+                WatchKind::Access => "awatch",
+            };
+            format!("T05{kind}:{:04x};", hit.watchpoint.address)
+        }
+        None => STOP_REPLY.to_string(),
+    }
+}
+
+/// Parse `addr,length`, both in hex.
+fn parse_address_and_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|n| u8::from_str_radix(text.get(n..n + 2)?, 16).ok())
+        .collect()
+}
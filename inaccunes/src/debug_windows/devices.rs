@@ -1,5 +1,5 @@
 use super::*;
-use sdl2::pixels::Color;
+use sdl2::{pixels::Color, rect::Rect};
 
 const OVERALL_BACKGROUND: Color = Color {
     r: 0,
@@ -10,6 +10,10 @@ const OVERALL_BACKGROUND: Color = Color {
 
 const LEFT_MARGIN: i32 = 3;
 const TOP_MARGIN: i32 = 1;
+/// How wide each color is in the palette strips.
+const SWATCH_WIDTH: u32 = 12;
+/// Where the palette strips start, leaving room for their labels.
+const SWATCH_LEFT: i32 = 96;
 pub struct DebugDevicesWindow {
     window: DebugWindow,
 }
@@ -41,10 +45,26 @@ impl DebugWindowThing for DebugDevicesWindow {
             canvas,
             LEFT_MARGIN,
             TOP_MARGIN + y * font.get_glyph_height() as i32,
-            &format!("Controllers: {:?}", controllers),
+            &format!(
+                "Controllers: {:?}\t\tRegion: {:?}",
+                controllers,
+                system.get_region()
+            ),
+        );
+        let y = y + 1;
+        let (scanline, dot) = system.get_render_position();
+        font.render_to_canvas(
+            canvas,
+            LEFT_MARGIN,
+            TOP_MARGIN + y * font.get_glyph_height() as i32,
+            &format!(
+                "Frame: {frame}\t\tScanline: {scanline}\tDot: {dot}\tSprite 0 hit: {hit}",
+                frame = system.get_frame_count(),
+                hit = if ppu.is_sprite_0_hit() { "YES" } else { "no" },
+            ),
         );
         let y = y + 2;
-        let data = ppu.register_control;
+        let data = ppu.get_control();
         font.render_to_canvas(
             canvas,
             LEFT_MARGIN,
@@ -79,7 +99,7 @@ impl DebugWindowThing for DebugDevicesWindow {
             ),
         );
         let y = y + 4;
-        let data = ppu.register_mask;
+        let data = ppu.get_mask();
         font.render_to_canvas(
             canvas,
             LEFT_MARGIN,
@@ -126,27 +146,56 @@ impl DebugWindowThing for DebugDevicesWindow {
             TOP_MARGIN + y * font.get_glyph_height() as i32,
             &format!(
                 "OAM ADDRESS = ${oam:02X}", //\t\tPPU ADDRESS = ${ppudata:04X}",
-                oam = ppu.register_oam_address,
+                oam = ppu.get_oam_address(),
                 //ppudata = ppu.register_ppudata_address,
             ),
         );
         let y = y + 2;
 
-        let shift_x = ppu.register_control & 1;
-        let shift_y = (ppu.register_control & 2) >> 1;
+        let shift_x = ppu.get_control() & 1;
+        let shift_y = (ppu.get_control() & 2) >> 1;
         font.render_to_canvas(
             canvas,
             LEFT_MARGIN,
             TOP_MARGIN + y * font.get_glyph_height() as i32,
             &format!(
                 "x = ${x:04X}/{x_extra}\t\ty = ${y:04X}/{y_extra}",
-                x = ppu.register_scroll_x,
-                y = ppu.register_scroll_y,
-                x_extra = ppu.register_scroll_x as u16 + (256 * shift_x as u16),
-                y_extra = ppu.register_scroll_y as u16 + (240 * shift_y as u16),
+                x = ppu.get_scroll_x(),
+                y = ppu.get_scroll_y(),
+                x_extra = ppu.get_scroll_x() as u16 + (256 * shift_x as u16),
+                y_extra = ppu.get_scroll_y() as u16 + (240 * shift_y as u16),
             ),
         );
         let y = y + 2;
+        // The whole palette, twice: once as the game wrote it, and once as it
+        // looks on the TV right now. If they don't match, emphasis or
+        // grayscale is on.
+        let strips = [
+            ("Palette:", false, 0),
+            (
+                "On TV:",
+                ppu.is_grayscale(),
+                system.get_region().fix_emphasis(ppu.get_emphasis()),
+            ),
+        ];
+        for (row, (label, grayscale, emphasis)) in strips.into_iter().enumerate() {
+            let top = TOP_MARGIN + (y + row as i32) * font.get_glyph_height() as i32;
+            font.render_to_canvas(canvas, LEFT_MARGIN, top, label);
+            for (index, color_index) in ppu.get_cram().iter().enumerate() {
+                let [_, r, g, b] =
+                    system::get_palette_color(grayscale, emphasis, *color_index as usize)
+                        .to_be_bytes();
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                canvas
+                    .fill_rect(Rect::new(
+                        SWATCH_LEFT + index as i32 * SWATCH_WIDTH as i32,
+                        top,
+                        SWATCH_WIDTH,
+                        font.get_glyph_height(),
+                    ))
+                    .unwrap();
+            }
+        }
         canvas.present();
     }
 }
@@ -0,0 +1,83 @@
+use super::*;
+use inaccu6502::Instruction;
+use sdl2::{pixels::Color, rect::Rect};
+
+const OVERALL_BACKGROUND: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0,
+};
+const NEXT_INSTRUCTION_BACKGROUND: Color = Color {
+    r: 0,
+    g: 64,
+    b: 64,
+    a: 0,
+};
+
+const LEFT_MARGIN: i32 = 3;
+const TOP_MARGIN: i32 = 1;
+/// How many instructions to show, starting with the one at PC.
+const INSTRUCTION_COUNT: usize = 24;
+
+/// The next few instructions the CPU is going to run (if it doesn't branch),
+/// with their operands worked out. Most useful while stopped at a
+/// breakpoint.
+pub struct DebugDisassemblyWindow {
+    window: DebugWindow,
+}
+
+impl DebugDisassemblyWindow {
+    pub fn new(video: &VideoSubsystem, font: Arc<FontData>) -> Box<Self> {
+        let window = DebugWindow::new(
+            "Disassembly Window",
+            48 * font.get_glyph_width(),
+            (INSTRUCTION_COUNT as u32 + 2) * font.get_glyph_height(),
+            video,
+            font,
+        );
+        Box::new(Self { window })
+    }
+}
+
+impl DebugWindowThing for DebugDisassemblyWindow {
+    fn draw(&mut self, system: &System) {
+        let DebugWindow { canvas, font, .. } = &mut self.window;
+        let devices = system.get_devices();
+        let cpu = system.get_cpu();
+        let peek = |address| devices.peek_byte(address);
+        let glyph_height = font.get_glyph_height() as i32;
+        canvas.set_draw_color(OVERALL_BACKGROUND);
+        canvas.clear();
+        canvas.set_draw_color(NEXT_INSTRUCTION_BACKGROUND);
+        canvas
+            .fill_rect(Rect::new(
+                0,
+                TOP_MARGIN,
+                canvas.window().size().0,
+                glyph_height as u32,
+            ))
+            .unwrap();
+        let mut address = cpu.get_pc();
+        for y in 0..INSTRUCTION_COUNT as i32 {
+            let instruction = Instruction::decode(address, peek);
+            let bytes: Vec<String> = instruction
+                .get_bytes()
+                .iter()
+                .map(|x| format!("{x:02X}"))
+                .collect();
+            font.render_to_canvas(
+                canvas,
+                LEFT_MARGIN,
+                TOP_MARGIN + y * glyph_height,
+                &format!(
+                    "{address:04X}  {:8}  {}",
+                    bytes.join(" "),
+                    instruction.show_with_operands(cpu, peek)
+                ),
+            );
+            address = address.wrapping_add(instruction.get_length());
+        }
+        canvas.present();
+    }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use sdl2::{pixels::Color, rect::Rect};
+use sdl2::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect};
 
 const OVERALL_BACKGROUND: Color = Color {
     r: 0,
@@ -32,23 +32,36 @@ const STACK_ODD_BACKGROUND: Color = Color {
     a: 0,
 };
 
-const LEFT_MARGIN: i32 = 3;
+const LEFT_MARGIN: i32 = 4;
 const TOP_MARGIN: i32 = 1;
+/// How many rows of memory fit in the window at once.
+const ROWS_ON_SCREEN: u16 = VISIBLE_MEMORY_ROWS as u16 - 1;
 
 pub struct DebugMemoryWindow {
     window: DebugWindow,
+    /// The row at the top of the window.
+    first_row: u16,
+    /// If present, the user pressed G and is typing in an address to go to.
+    goto_address: Option<String>,
 }
 
 impl DebugMemoryWindow {
     pub fn new(video: &VideoSubsystem, font: Arc<FontData>) -> Box<Self> {
         let window = DebugWindow::new(
-            "Work RAM Window",
+            "Memory Window",
             VISIBLE_MEMORY_COLUMNS * (font.get_glyph_width() + 1),
             VISIBLE_MEMORY_ROWS * (font.get_glyph_height() + 2),
             video,
             font,
         );
-        Box::new(Self { window })
+        Box::new(Self {
+            window,
+            first_row: 0,
+            goto_address: None,
+        })
+    }
+    fn scroll_to_row(&mut self, row: i32) {
+        self.first_row = row.clamp(0, (NUM_MEMORY_ROWS - ROWS_ON_SCREEN) as i32) as u16;
     }
 }
 
@@ -61,19 +74,28 @@ impl DebugWindowThing for DebugMemoryWindow {
         let cell_height = font.get_glyph_height() as i32 + 2;
         let left_margin = LEFT_MARGIN * cell_width;
         let top_margin = TOP_MARGIN * cell_height;
-        for x in 0..4 {
-            for (i, ch) in b"0123456789ABCDEF".iter().enumerate() {
-                font.render_to_canvas(
-                    canvas,
-                    left_margin + (x * 16 as i32 + i as i32) * cell_width * 3,
-                    1,
-                    &format!(".{}", *ch as char),
-                );
+        if let Some(goto_address) = &self.goto_address {
+            font.render_to_canvas(
+                canvas,
+                left_margin,
+                1,
+                &format!("Go to address (Enter to go, Escape to cancel): ${goto_address}_"),
+            );
+        } else {
+            for x in 0..4 {
+                for (i, ch) in b"0123456789ABCDEF".iter().enumerate() {
+                    font.render_to_canvas(
+                        canvas,
+                        left_margin + (x * 16 as i32 + i as i32) * cell_width * 3,
+                        1,
+                        &format!(".{}", *ch as char),
+                    );
+                }
             }
         }
-        for y in 0..NUM_MEMORY_ROWS {
-            let target_address = y * BYTES_PER_MEMORY_ROW;
-            if target_address >= 0x0100 && target_address <= 0x01FF {
+        for y in 0..ROWS_ON_SCREEN {
+            let target_address = (self.first_row + y) * BYTES_PER_MEMORY_ROW;
+            if (0x0100..=0x01FF).contains(&target_address) {
                 if y & 1 == 0 {
                     canvas.set_draw_color(STACK_EVEN_BACKGROUND);
                 } else {
@@ -98,7 +120,7 @@ impl DebugWindowThing for DebugMemoryWindow {
                 canvas,
                 0,
                 top_margin + y as i32 * (cell_height) + 2,
-                &format!("{:02X}", (target_address >> 4)),
+                &format!("{:03X}", (target_address >> 4)),
             );
             for x in 0..BYTES_PER_MEMORY_ROW {
                 let target_address = target_address + x;
@@ -106,7 +128,7 @@ impl DebugWindowThing for DebugMemoryWindow {
                     canvas,
                     left_margin + (x as i32) * (cell_width) * 3,
                     top_margin + y as i32 * (cell_height) + 2,
-                    &format!("{:02X}", system.get_work_memory_byte(target_address)),
+                    &format!("{:02X}", system.get_devices().peek_byte(target_address)),
                 );
                 if target_address == 0x74A || target_address == 0xCE || target_address == 0x86 {
                     // HACK!
@@ -114,11 +136,61 @@ impl DebugWindowThing for DebugMemoryWindow {
                         canvas,
                         left_margin + (x as i32) * (cell_width) * 3 + 1,
                         top_margin + y as i32 * (cell_height) + 2,
-                        &format!("{:02X}", system.get_work_memory_byte(target_address)),
+                        &format!("{:02X}", system.get_devices().peek_byte(target_address)),
                     );
                 }
             }
         }
         canvas.present();
     }
+    fn handle_event(&mut self, event: &Event) -> bool {
+        if !self.window.owns_event(event) {
+            return false;
+        }
+        let Event::KeyDown {
+            keycode: Some(keycode),
+            ..
+        } = event
+        else {
+            return false;
+        };
+        if let Some(goto_address) = self.goto_address.as_mut() {
+            match *keycode {
+                Keycode::Return | Keycode::KpEnter => {
+                    if let Ok(address) = u16::from_str_radix(goto_address, 16) {
+                        // Put the address on the second row, so you can see a
+                        // little of what comes before it.
+                        self.scroll_to_row((address / BYTES_PER_MEMORY_ROW) as i32 - 1);
+                    }
+                    self.goto_address = None;
+                }
+                Keycode::Escape => self.goto_address = None,
+                Keycode::Backspace => {
+                    goto_address.pop();
+                }
+                _ => {
+                    let name = keycode.name();
+                    if name.len() == 1
+                        && name.chars().all(|x| x.is_ascii_hexdigit())
+                        && goto_address.len() < 4
+                    {
+                        goto_address.push_str(&name);
+                    }
+                }
+            }
+            return true;
+        }
+        let first_row = self.first_row as i32;
+        match *keycode {
+            Keycode::Up => self.scroll_to_row(first_row - 1),
+            Keycode::Down => self.scroll_to_row(first_row + 1),
+            Keycode::PageUp => self.scroll_to_row(first_row - ROWS_ON_SCREEN as i32),
+            Keycode::PageDown => self.scroll_to_row(first_row + ROWS_ON_SCREEN as i32),
+            Keycode::Home => self.scroll_to_row(0),
+            Keycode::End => self.scroll_to_row(NUM_MEMORY_ROWS as i32),
+            Keycode::G => self.goto_address = Some(String::new()),
+            _ => return false,
+        }
+        true
+    }
 }
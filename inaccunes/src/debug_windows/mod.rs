@@ -1,7 +1,8 @@
 use crate::*;
 pub mod devices;
+pub mod disassembly;
 pub mod memory;
-use sdl2::{render::WindowCanvas, VideoSubsystem};
+use sdl2::{event::Event, render::WindowCanvas, VideoSubsystem};
 
 struct DebugWindow {
     font: FontInstance,
@@ -27,8 +28,18 @@ impl DebugWindow {
         let font = FontInstance::new(font, &canvas.texture_creator());
         DebugWindow { font, canvas }
     }
+    /// Whether this event happened in this window (rather than another one,
+    /// or no window at all).
+    fn owns_event(&self, event: &Event) -> bool {
+        event.get_window_id() == Some(self.canvas.window().id())
+    }
 }
 
 pub trait DebugWindowThing {
     fn draw(&mut self, system: &System);
+    /// Do something with an event, if it's for us. Returns true if we used
+    /// it up, so nobody else should see it.
+    fn handle_event(&mut self, _event: &Event) -> bool {
+        false
+    }
 }
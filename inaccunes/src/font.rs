@@ -32,6 +32,23 @@ impl FontData {
         glyphs_per_row: u8,
     ) -> Result<FontData, anyhow::Error> {
         //-> anyhow::Result<FontData> {
+        // Catch odd metrics now, so the math below (and in
+        // `get_valid_glyph_range`) can't overflow later.
+        if num_glyphs == 0 || glyphs_per_row == 0 {
+            return Err(anyhow!("A font needs at least one glyph, and one per row"));
+        }
+        if first_glyph as u32 + num_glyphs as u32 > 256 {
+            return Err(anyhow!(
+                "A font can't have {num_glyphs} glyphs starting from {first_glyph}, since that \
+                 goes past 255"
+            ));
+        }
+        // Anything we don't have a glyph for gets drawn as a ?, so there had
+        // better be one.
+        let last_glyph = first_glyph + (num_glyphs - 1);
+        if !(first_glyph..=last_glyph).contains(&b'?') {
+            return Err(anyhow!("A font needs a glyph for '?'"));
+        }
         // integer divide with round up
         let num_rows = (num_glyphs as u32 + (glyphs_per_row - 1) as u32) / glyphs_per_row as u32;
         // some day, when #88581 is done, we can do this instead:
@@ -76,7 +93,11 @@ impl FontData {
         });
     }
     pub fn get_valid_glyph_range(&self) -> RangeInclusive<u8> {
-        self.first_glyph..=self.first_glyph + (self.num_glyphs - 1)
+        // (`load_from_png` made sure this doesn't go past 255.)
+        let last_glyph = self
+            .first_glyph
+            .saturating_add(self.num_glyphs.saturating_sub(1));
+        self.first_glyph..=last_glyph
     }
 
     pub fn get_glyph_height(&self) -> u32 {
@@ -177,3 +198,23 @@ impl Deref for FontInstance {
 pub fn load_monaco() -> anyhow::Result<FontData> {
     FontData::load_from_png(&include_bytes!("monaco.png")[..], 6, 12, b' ', 96, 32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odd_metrics_are_errors() {
+        // None of these get as far as reading the PNG.
+        let load = |first_glyph, num_glyphs, glyphs_per_row| {
+            FontData::load_from_png(&[][..], 6, 12, first_glyph, num_glyphs, glyphs_per_row)
+        };
+        assert!(load(200, 100, 32).is_err());
+        assert!(load(b'?', 0, 32).is_err());
+        assert!(load(b'?', 1, 0).is_err());
+        assert!(load(b'A', 26, 13).is_err());
+        // And the one we ship is fine.
+        let monaco = load_monaco().unwrap();
+        assert_eq!(monaco.get_valid_glyph_range(), b' '..=0x7F);
+    }
+}
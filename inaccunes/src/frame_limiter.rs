@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+/// Sleeping is never exact, so we wake up this early and spin the rest of the
+/// way.
+const SPIN_TIME: Duration = Duration::from_millis(2);
+
+/// Keeps the frames coming at the console's rate, no matter how fast (or
+/// slow) the monitor is.
+pub struct FrameLimiter {
+    frame_duration: Duration,
+    /// When the next frame is supposed to start.
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(frames_per_second: f64) -> FrameLimiter {
+        FrameLimiter {
+            frame_duration: Duration::from_secs_f64(1.0 / frames_per_second),
+            next_frame: Instant::now(),
+        }
+    }
+    /// Wait until it's time for the next frame. Call this once per frame.
+    pub fn wait(&mut self) {
+        self.next_frame += self.frame_duration;
+        let now = Instant::now();
+        if now >= self.next_frame {
+            // We're running behind. If it's by more than a frame (say, the
+            // window got dragged around), don't try to catch up by running
+            // at warp speed, just start over from here.
+            if now - self.next_frame > self.frame_duration {
+                self.next_frame = now;
+            }
+            return;
+        }
+        let remaining = self.next_frame - now;
+        if remaining > SPIN_TIME {
+            std::thread::sleep(remaining - SPIN_TIME);
+        }
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Settings for one particular game, so that a game that needs
+//! `--mirroring vertical` (or its own cheats, or its own controls) gets it
+//! every time without anyone having to remember. A game config is a text
+//! file full of command line options, named after the ROM's CRC32 (see
+//! `Cartridge::rom_crc32`), in the game config directory:
+//!
+//! 1. `--game-config-dir`, if it's there
+//! 2. `$XDG_CONFIG_HOME/inaccunes/games`
+//! 3. `~/.config/inaccunes/games`
+//!
+//! Going by CRC means a renamed ROM still gets its config, and a different
+//! dump of the same game doesn't. Whatever the game config says wins over
+//! the command line, but only for that game: switching to another one goes
+//! back to just the command line.
+
+use std::{io::ErrorKind, path::PathBuf};
+
+use inaccunes::cartridge::Cartridge;
+use log::*;
+
+use crate::options::Options;
+
+/// Where to look for game configs.
+fn config_dir(options: &Options) -> Option<PathBuf> {
+    if let Some(dir) = options.game_config_dir.as_ref() {
+        return Some(PathBuf::from(dir));
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("inaccunes").join("games"))
+}
+
+/// `options`, with `cartridge`'s game config (if it has one) on top. A game
+/// config that won't parse gets logged and ignored, rather than keeping the
+/// game from starting.
+pub fn apply(options: &Options, cartridge: &Cartridge) -> Options {
+    let mut result = options.clone();
+    let Some(dir) = config_dir(options) else {
+        return result;
+    };
+    let path = dir.join(format!("{:08X}.txt", cartridge.rom_crc32()));
+    let text = match std::fs::read_to_string(&path) {
+        Ok(x) => x,
+        Err(x) if x.kind() == ErrorKind::NotFound => return result,
+        Err(x) => {
+            warn!("Couldn't read the game config {}: {x}", path.display());
+            return result;
+        }
+    };
+    match result.apply_game_config(&text) {
+        Ok(()) => {
+            info!("Using the game config {}", path.display());
+            result
+        }
+        Err(x) => {
+            error!("Ignoring the game config {}: {x:#}", path.display());
+            options.clone()
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Gamepads, through SDL's game controller API, so that anything SDL knows
+//! the layout of works the same way. The first one plugged in is player 1,
+//! the next is player 2, and so on. (The keyboard is player 1 too.) The
+//! buttons go where they are on an NES pad: the bottom face button is B and
+//! the right one is A, and the other two are turbo. The left stick works as
+//! well as the D-pad, once it's pushed far enough (see `GamepadSettings`).
+
+use inaccunes::system::{Controller, System};
+use log::*;
+use sdl2::{
+    controller::{Axis, Button, GameController},
+    event::Event,
+    GameControllerSubsystem, Sdl,
+};
+
+/// How the raw inputs turn into NES buttons. Different gamepads (and
+/// different players) want different numbers here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GamepadSettings {
+    /// How far (out of 100) the stick has to leave the middle before it
+    /// counts as moving at all. Worn out sticks never quite go back to the
+    /// middle, and need more.
+    pub deadzone: u8,
+    /// How far (out of 100) the stick has to go between the edge of the
+    /// deadzone and the edge of the stick before it presses the D-pad.
+    pub threshold: u8,
+    /// Whether left on the stick is right on the D-pad.
+    pub invert_x: bool,
+    /// Whether up on the stick is down on the D-pad.
+    pub invert_y: bool,
+    /// Whether the bottom face button is A instead of B (and the left one is
+    /// turbo A), for players who go by the names on an Xbox pad instead of
+    /// where the buttons are.
+    pub swap_ab: bool,
+}
+
+impl Default for GamepadSettings {
+    /// Enough deadzone for most sticks, and halfway past it to press.
+    fn default() -> GamepadSettings {
+        GamepadSettings {
+            deadzone: 15,
+            threshold: 50,
+            invert_x: false,
+            invert_y: false,
+            swap_ab: false,
+        }
+    }
+}
+
+impl GamepadSettings {
+    /// Which way the D-pad goes for an axis at `value`: -1 for left or up, 1
+    /// for right or down, and 0 for neither.
+    fn axis_to_direction(&self, value: i16, is_inverted: bool) -> i8 {
+        // i16::MIN is one further than i16::MAX, so leave it out.
+        let magnitude = (value as i32).unsigned_abs().min(i16::MAX as u32);
+        let deadzone = i16::MAX as u32 * self.deadzone.min(100) as u32 / 100;
+        if magnitude <= deadzone {
+            return 0;
+        }
+        let past_deadzone = (magnitude - deadzone) * 100 / (i16::MAX as u32 - deadzone);
+        if past_deadzone < self.threshold.min(100) as u32 {
+            return 0;
+        }
+        match (value < 0) != is_inverted {
+            true => -1,
+            false => 1,
+        }
+    }
+}
+
+/// One gamepad that's plugged in.
+struct Gamepad {
+    controller: GameController,
+    /// Which way the stick is pushing the D-pad, on each axis, so that we
+    /// only touch the D-pad when that changes. (Otherwise, a stick sitting
+    /// in the middle would let go of whatever the real D-pad is holding.)
+    stick_x: i8,
+    stick_y: i8,
+}
+
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    /// In the order they were plugged in, which is also player order.
+    gamepads: Vec<Gamepad>,
+    settings: GamepadSettings,
+}
+
+impl Gamepads {
+    /// Start listening for gamepads. Ones that are already plugged in show
+    /// up as events too, so there's nothing to open yet.
+    pub fn new(sdl: &Sdl, settings: GamepadSettings) -> Gamepads {
+        Gamepads {
+            subsystem: sdl
+                .game_controller()
+                .expect("Unable to initialize SDL game controllers"),
+            gamepads: vec![],
+            settings,
+        }
+    }
+    /// Switch to `settings`, say for a game with its own in its game config.
+    pub fn set_settings(&mut self, settings: GamepadSettings) {
+        self.settings = settings;
+    }
+    /// Do whatever `event` says, if it's from a gamepad. Returns whether it
+    /// was.
+    pub fn handle_event(&mut self, event: &Event, system: &mut System) -> bool {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    info!(
+                        "Gamepad {:?} is player {}",
+                        controller.name(),
+                        self.gamepads.len() + 1
+                    );
+                    self.gamepads.push(Gamepad {
+                        controller,
+                        stick_x: 0,
+                        stick_y: 0,
+                    });
+                }
+                Err(x) => warn!("Couldn't open gamepad {which}: {x}"),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                let Some(player) = self.find_player(which) else {
+                    return true;
+                };
+                let old_len = self.gamepads.len();
+                let gamepad = self.gamepads.remove(player);
+                info!("Gamepad {:?} is gone", gamepad.controller.name());
+                // Don't leave its buttons held down. Everyone after it moves
+                // up a player, so let go of theirs too, or the player they
+                // used to be would be stuck holding them. They'll press them
+                // again as their next events come in.
+                for controller in system
+                    .get_controllers_mut()
+                    .iter_mut()
+                    .take(old_len)
+                    .skip(player)
+                {
+                    press(controller, None, false);
+                }
+                for gamepad in &mut self.gamepads[player..] {
+                    gamepad.stick_x = 0;
+                    gamepad.stick_y = 0;
+                }
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.press_button(system, which, button, true)
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.press_button(system, which, button, false)
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => self.move_axis(system, which, axis, value),
+            _ => return false,
+        }
+        true
+    }
+    /// Which player the gamepad with SDL instance ID `which` is.
+    fn find_player(&self, which: u32) -> Option<usize> {
+        self.gamepads
+            .iter()
+            .position(|gamepad| gamepad.controller.instance_id() == which)
+    }
+    fn press_button(&self, system: &mut System, which: u32, button: Button, is_pressed: bool) {
+        let Some(player) = self.find_player(which) else {
+            return;
+        };
+        let button = match button {
+            Button::A if self.settings.swap_ab => Button::B,
+            Button::B if self.settings.swap_ab => Button::A,
+            Button::X if self.settings.swap_ab => Button::Y,
+            Button::Y if self.settings.swap_ab => Button::X,
+            x => x,
+        };
+        // Players past what's plugged into the console don't do anything.
+        if let Some(controller) = system.get_controllers_mut().get_mut(player) {
+            press(controller, Some(button), is_pressed);
+        }
+    }
+    fn move_axis(&mut self, system: &mut System, which: u32, axis: Axis, value: i16) {
+        let Some(player) = self.find_player(which) else {
+            return;
+        };
+        let settings = self.settings;
+        let gamepad = &mut self.gamepads[player];
+        let (direction, old_direction) = match axis {
+            Axis::LeftX => (
+                settings.axis_to_direction(value, settings.invert_x),
+                &mut gamepad.stick_x,
+            ),
+            Axis::LeftY => (
+                settings.axis_to_direction(value, settings.invert_y),
+                &mut gamepad.stick_y,
+            ),
+            _ => return,
+        };
+        if direction == *old_direction {
+            return;
+        }
+        *old_direction = direction;
+        let Some(controller) = system.get_controllers_mut().get_mut(player) else {
+            return;
+        };
+        match axis {
+            Axis::LeftX => {
+                controller.button_left = direction < 0;
+                controller.button_right = direction > 0;
+            }
+            _ => {
+                controller.button_up = direction < 0;
+                controller.button_down = direction > 0;
+            }
+        }
+    }
+}
+
+/// Press (or let go of) the NES button where `button` is on an NES pad, or
+/// all of them if `button` is `None`. The face buttons are named for where
+/// they are on an Xbox pad: A is on the bottom, B on the right, X on the
+/// left, and Y on top.
+fn press(controller: &mut Controller, button: Option<Button>, is_pressed: bool) {
+    let everything = button.is_none();
+    let is = |x: Button| everything || button == Some(x);
+    if is(Button::B) {
+        controller.button_a = is_pressed;
+    }
+    if is(Button::A) {
+        controller.button_b = is_pressed;
+    }
+    if is(Button::Y) {
+        controller.turbo_a = is_pressed;
+    }
+    if is(Button::X) {
+        controller.turbo_b = is_pressed;
+    }
+    if is(Button::Back) {
+        controller.button_select = is_pressed;
+    }
+    if is(Button::Start) {
+        controller.button_start = is_pressed;
+    }
+    if is(Button::DPadUp) {
+        controller.button_up = is_pressed;
+    }
+    if is(Button::DPadDown) {
+        controller.button_down = is_pressed;
+    }
+    if is(Button::DPadLeft) {
+        controller.button_left = is_pressed;
+    }
+    if is(Button::DPadRight) {
+        controller.button_right = is_pressed;
+    }
+}
@@ -0,0 +1,53 @@
+//! Grids drawn over the TV picture, so you can see where the tiles and the
+//! metatiles (the 16x16 squares that share an attribute) are, even while
+//! the game is scrolling.
+
+use inaccunes::system::BackgroundGridPixel;
+
+const TILE_LINE_COLOR: u32 = 0x40FF_FFFF;
+const METATILE_LINE_COLOR: u32 = 0xC0FF_FF00;
+/// One color per background palette.
+// TODO: a nametable viewer (whenever there is one) should get a tinted mode
+// too, using these same colors, so the two agree. It'd have to decode the
+// attribute tables itself (the byte for tile (x, y) is at $23C0 + y / 4 * 8
+// + x / 4 in the first nametable, and which two bits depend on x / 2 % 2 and
+// y / 2 % 2), since `BackgroundGridPixel` only covers what's on screen.
+const PALETTE_TINTS: [u32; 4] = [0x50FF_0000, 0x5000_FF00, 0x5000_00FF, 0x50FF_00FF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GridOverlay {
+    #[default]
+    Off,
+    Grid,
+    /// Grid, plus every metatile tinted by which palette it uses.
+    TintedGrid,
+}
+
+impl GridOverlay {
+    /// The one after this one, for flipping through them with a key.
+    pub fn next(self) -> GridOverlay {
+        match self {
+            GridOverlay::Off => GridOverlay::Grid,
+            GridOverlay::Grid => GridOverlay::TintedGrid,
+            GridOverlay::TintedGrid => GridOverlay::Off,
+        }
+    }
+    /// Draw the overlay for `grid` (from `System::get_background_grid`) into
+    /// `out`, as `0xAARRGGBB`. Anywhere there's nothing to draw is fully
+    /// transparent.
+    pub fn draw(self, grid: &[BackgroundGridPixel], out: &mut [u32]) {
+        for (pixel, grid_pixel) in out.iter_mut().zip(grid) {
+            *pixel = if self == GridOverlay::Off {
+                0
+            } else if grid_pixel.is_metatile_left_edge() || grid_pixel.is_metatile_top_edge() {
+                METATILE_LINE_COLOR
+            } else if grid_pixel.is_tile_left_edge() || grid_pixel.is_tile_top_edge() {
+                TILE_LINE_COLOR
+            } else if self == GridOverlay::TintedGrid {
+                PALETTE_TINTS[grid_pixel.get_palette()]
+            } else {
+                0
+            };
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Hold controller input back a few frames before the game sees it, to even
+//! out latency: say, so that a movie recorded on a slow TV plays the same as
+//! one from a fast monitor. Everything downstream (movie recording, netplay)
+//! gets the delayed input, as if that's what was pressed. Only the eight
+//! buttons get held back: turbo A and B aren't part of the controller state,
+//! so they go straight through.
+
+use std::collections::VecDeque;
+
+use inaccunes::system::System;
+
+/// The most frames `--input-delay` goes up to.
+pub const MAX_FRAMES: usize = 5;
+
+pub struct InputDelay {
+    /// For each player, what was held on each of the last however many
+    /// frames, oldest first.
+    queues: Vec<VecDeque<u8>>,
+    /// What's really held right now, while the controllers have the delayed
+    /// input in them. See `end_frame`.
+    live: Vec<u8>,
+}
+
+impl InputDelay {
+    /// Delay input by `frames` frames. Until that many frames have gone by,
+    /// nothing's pressed.
+    pub fn new(frames: usize, system: &System) -> InputDelay {
+        let players = system.get_controllers().len();
+        InputDelay {
+            queues: vec![VecDeque::from(vec![0; frames]); players],
+            live: vec![0; players],
+        }
+    }
+    /// Swap what's held right now for what was held a few frames ago. Call
+    /// right before each frame, and `end_frame` right after it.
+    pub fn start_frame(&mut self, system: &mut System) {
+        for (player, queue) in self.queues.iter_mut().enumerate() {
+            let live = system.get_controller_state(player);
+            self.live[player] = live;
+            queue.push_back(live);
+            let delayed = queue.pop_front().unwrap_or(live);
+            system.set_controller_state(player, delayed);
+        }
+    }
+    /// Put back what's really held, so that the keyboard (which only says
+    /// when keys go up or down) picks up where it left off.
+    pub fn end_frame(&mut self, system: &mut System) {
+        for (player, live) in self.live.iter().enumerate() {
+            system.set_controller_state(player, *live);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use inaccunes::cartridge::Cartridge;
+
+    /// A console with a cartridge that's nothing but zeroes. None of it
+    /// runs.
+    fn system() -> System {
+        let mut file = vec![b'N', b'E', b'S', 0x1A, 1, 1];
+        file.resize(16 + 16 * 1024 + 8 * 1024, 0);
+        System::new(Cartridge::from_reader(&file[..]).unwrap())
+    }
+
+    #[test]
+    fn presses_show_up_exactly_that_many_frames_later() {
+        for frames in [0, 1, 3, MAX_FRAMES] {
+            let mut system = system();
+            let mut input_delay = InputDelay::new(frames, &system);
+            for frame in 0..8 {
+                let live = 1 << frame;
+                system.set_controller_state(0, live);
+                input_delay.start_frame(&mut system);
+                let expected = match frame >= frames {
+                    true => 1 << (frame - frames),
+                    false => 0,
+                };
+                assert_eq!(
+                    system.get_controller_state(0),
+                    expected,
+                    "frame {frame} with {frames} frames of delay"
+                );
+                input_delay.end_frame(&mut system);
+                assert_eq!(system.get_controller_state(0), live);
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! The core of the emulator: everything you need to run a game, and nothing
+//! that needs a window, a filesystem, or SDL. The `inaccunes` binary (behind
+//! the `native` feature) and the browser frontend are both built on this.
+
+use log::*;
+
+pub mod cartridge;
+use cartridge::Cartridge;
+pub mod cheats;
+use cheats::{Cheat, Cheats};
+pub mod region;
+use region::{Region, DOTS_PER_SCANLINE};
+#[cfg(feature = "ntsc-filter")]
+pub mod ntsc_filter;
+pub mod system;
+
+pub const WORK_RAM_SIZE: usize = 2048;
+pub const NES_WIDTH: usize = 256;
+pub const NES_HEIGHT: usize = 240;
+pub const NES_PIXEL_COUNT: usize = NES_WIDTH * NES_HEIGHT;
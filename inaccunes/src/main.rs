@@ -1,39 +1,111 @@
-use std::sync::Arc;
+use std::{io::Write, sync::Arc};
 
+use inaccunes::{
+    cartridge::Cartridge,
+    region::Region,
+    system::{self, System},
+    *,
+};
 use log::*;
-use sdl2::{pixels::PixelFormatEnum, render::TextureAccess};
+use sdl2::{
+    pixels::PixelFormatEnum,
+    render::{BlendMode, TextureAccess},
+};
 
-mod cartridge;
-use cartridge::Cartridge;
-mod system;
-use system::System;
 mod font;
 use font::*;
+mod frame_limiter;
+use frame_limiter::FrameLimiter;
+mod game_config;
+mod gamepad;
+use gamepad::Gamepads;
+mod grid_overlay;
+use grid_overlay::GridOverlay;
+mod input_delay;
+use input_delay::InputDelay;
 mod debug_windows;
 use debug_windows::*;
+mod movie;
+use movie::{Movie, MovieRecorder};
+mod netplay;
+use netplay::Netplay;
+mod options;
+use options::{FrameSync, Options};
+mod palette_editor;
+use palette_editor::PaletteEditor;
+mod scaling;
+use scaling::Scaler;
+mod scanlines;
+use scanlines::Scanlines;
+#[cfg(feature = "debug-server")]
+mod debug_server;
+mod screenshot;
+mod sticky_turbo;
+use sticky_turbo::{StickyTurbo, TurboButton};
+mod timing_overlay;
 
-const WORK_RAM_SIZE: usize = 2048;
-const NES_WIDTH: usize = 256;
-const NES_HEIGHT: usize = 240;
+/// Both pattern tables, side by side, 16x16 tiles each.
+const PATTERN_TABLES_WIDTH: usize = 256;
+const PATTERN_TABLES_HEIGHT: usize = 128;
 const NES_PITCH: usize = std::mem::size_of::<u32>() * NES_WIDTH;
-const NES_PIXEL_COUNT: usize = NES_WIDTH * NES_HEIGHT;
 const BYTES_PER_MEMORY_ROW: u16 = 64;
-const NUM_MEMORY_ROWS: u16 =
-    (WORK_RAM_SIZE as u16 + (BYTES_PER_MEMORY_ROW - 1)) / BYTES_PER_MEMORY_ROW;
-const VISIBLE_MEMORY_COLUMNS: u32 = 3 + (BYTES_PER_MEMORY_ROW as u32) * 3; // 64 columns plus a heading on the left
+const NUM_MEMORY_ROWS: u16 = (0x10000 / BYTES_PER_MEMORY_ROW as u32) as u16; // the whole CPU address space
+const VISIBLE_MEMORY_COLUMNS: u32 = 4 + (BYTES_PER_MEMORY_ROW as u32) * 3; // 64 columns plus a heading on the left
 const VISIBLE_MEMORY_ROWS: u32 = 1 + 32; // 32 rows plus a header
 
 fn main() {
     env_logger::init();
-    let our_arguments: Vec<String> = std::env::args().collect();
-    println!("our_arguments: {:?}", our_arguments);
-    if our_arguments.len() != 2 {
-        error!("Wrong nubmer of arguments. Please provide only the file path to ROM file.");
-        error!("Usage: inaccunes path/to/game.nes");
+    let mut base_options = match Options::parse(std::env::args().skip(1)) {
+        Ok(x) => x,
+        Err(x) => {
+            error!("{x:#}");
+            eprintln!("{}", options::USAGE);
+            return;
+        }
+    };
+    println!("options: {:?}", base_options);
+    // `options` is `base_options` plus the game config, if there is one.
+    let (mut options, cartridge) = load_game(&base_options);
+    if let Some(dump_chr_path) = options.dump_chr_path.as_deref() {
+        let pixels = draw_pattern_tables(&cartridge);
+        screenshot::save_png(
+            dump_chr_path,
+            PATTERN_TABLES_WIDTH,
+            PATTERN_TABLES_HEIGHT,
+            &pixels,
+        )
+        .expect("Could not save the pattern tables");
         return;
     }
-    let cartridge = Cartridge::new(&our_arguments[1]);
+    if let Some((start, end)) = options.disasm {
+        print!(
+            "{}",
+            inaccu6502::disassemble(start, end, |address| cartridge.perform_prg_read(address))
+        );
+        return;
+    }
+    let mut region = pick_region(&options, &cartridge);
     let mut system = System::new(cartridge);
+    configure(&mut system, &options, region);
+    if let Some(trace_path) = options.trace_path.as_deref() {
+        let file = std::fs::File::create(trace_path).expect("Could not create the trace file");
+        system.set_trace_writer(Some(Box::new(std::io::BufWriter::new(file))));
+    }
+    let mut movie = options
+        .movie_path
+        .as_ref()
+        .map(|path| Movie::load(path).expect("Could not load the movie file"));
+    if let Some(frames) = options.frames {
+        run_headless(
+            &mut system,
+            &mut movie,
+            frames,
+            options.dump_path.as_deref(),
+            options.ascii,
+        );
+        finish_trace(&mut system);
+        return;
+    }
 
     let monaco =
         load_monaco().expect("Could not load Monaco, the best [bitmapped] monospace font evar");
@@ -45,8 +117,10 @@ fn main() {
     let mut debug_windows: Vec<Box<dyn DebugWindowThing>> = vec![
         debug_windows::memory::DebugMemoryWindow::new(&video, monaco.clone()),
         debug_windows::devices::DebugDevicesWindow::new(&video, monaco.clone()),
+        debug_windows::disassembly::DebugDisassemblyWindow::new(&video, monaco.clone()),
     ];
     let mut event_pump = sdl.event_pump().expect("Couldn't get an event pump?!");
+    let mut gamepads = Gamepads::new(&sdl, options.gamepad);
     // TV window
     let tv_window = video
         .window("inaccunes", 512, 480)
@@ -59,6 +133,7 @@ fn main() {
     tv_canvas.clear();
     tv_canvas.present();
     let tv_texture_creator = tv_canvas.texture_creator();
+    let mut scaler = Scaler::new(&tv_canvas);
     let mut tv_texture = tv_texture_creator
         .create_texture(
             PixelFormatEnum::ARGB8888,
@@ -67,33 +142,158 @@ fn main() {
             NES_HEIGHT as u32,
         )
         .expect("Could not create a native size texture.");
+    #[cfg(feature = "ntsc-filter")]
+    let mut ntsc_texture = tv_texture_creator
+        .create_texture(
+            PixelFormatEnum::ARGB8888,
+            TextureAccess::Streaming,
+            ntsc_filter::NTSC_OUTPUT_WIDTH as u32,
+            NES_HEIGHT as u32,
+        )
+        .expect("Could not create an NTSC size texture.");
+    #[cfg(feature = "ntsc-filter")]
+    let mut ntsc_filter = ntsc_filter::NtscFilter::new();
+    #[cfg(feature = "ntsc-filter")]
+    let mut ntsc_pixels = vec![0; ntsc_filter::NTSC_OUTPUT_PIXEL_COUNT];
+    #[cfg(feature = "ntsc-filter")]
+    let mut is_ntsc_filter_on = false;
+    let mut grid_texture = tv_texture_creator
+        .create_texture(
+            PixelFormatEnum::ARGB8888,
+            TextureAccess::Streaming,
+            NES_WIDTH as u32,
+            NES_HEIGHT as u32,
+        )
+        .expect("Could not create a grid overlay texture.");
+    grid_texture.set_blend_mode(BlendMode::Blend);
+    let mut grid_overlay = GridOverlay::default();
+    let mut grid_pixels = [0; NES_PIXEL_COUNT];
+    let mut palette_editor = PaletteEditor::default();
+    let mut is_timing_overlay_on = false;
+    let mut sticky_turbo = StickyTurbo::default();
+    let mut input_delay = InputDelay::new(options.input_delay, &system);
+    let mut scanlines = Scanlines {
+        is_on: options.scanlines.is_some(),
+        intensity: options.scanlines.unwrap_or(scanlines::DEFAULT_INTENSITY),
+    };
     let monaco_for_tv = FontInstance::new(monaco.clone(), &tv_texture_creator);
+    let mut movie_recorder: Option<MovieRecorder> = None;
+    #[cfg(feature = "debug-server")]
+    let mut debug_server = options.debug_port.map(|port| {
+        debug_server::DebugServer::new(port).expect("Could not start the debug server")
+    });
+    #[cfg(not(feature = "debug-server"))]
+    if options.debug_port.is_some() {
+        warn!("--debug-port needs the debug-server feature. Ignoring it.");
+    }
+    let mut netplay = options.netplay.as_ref().map(|mode| {
+        Netplay::connect(mode, hash_setup(&options, region)).expect("Could not start netplay")
+    });
+    let mut frame_limiter = match options.sync {
+        FrameSync::Clock => Some(FrameLimiter::new(region.frames_per_second())),
+        FrameSync::Vsync => None,
+    };
+    let mut pixels = [0; NES_PIXEL_COUNT];
+    let mut is_paused = false;
+    // Whether to run one frame even though we're paused. Keys pressed
+    // before this gets set are already on the controller, so you can hold
+    // down jump and then advance into it.
+    let mut should_advance_frame = false;
+    // Which of `options.rom_paths` is in the console.
+    let mut rom_index = 0;
     'running: loop {
         ///////////////////////////////////////////////////////////////////////
         // Draw the TV
         ///////////////////////////////////////////////////////////////////////
-        let pixels = system.render();
-        // transmute is *unsafe*, in that the compiler can't help us if we make
-        // a mistake. Unsafe justification: we are passing the u32s to the
-        // graphics API, and it's just using &[u8] because it wants a bunch of
-        // bytes, not because it *needs* it to *actually be* an array of
-        // individual, meaningful byte values.
-        let pixels_as_u8: &[u8] = unsafe { std::mem::transmute(&pixels[..]) };
-        tv_texture
-            .update(None, pixels_as_u8, NES_PITCH)
-            .expect("Could not update the native texture with raw pixel data");
-        tv_canvas
-            .copy(&tv_texture, None, None)
-            .expect("could not copy native texture to window texture");
+        #[cfg(feature = "debug-server")]
+        let should_run = debug_server
+            .as_mut()
+            .is_none_or(|server| server.poll(&mut system));
+        #[cfg(not(feature = "debug-server"))]
+        let should_run = true;
+        let should_run = should_run && (!is_paused || should_advance_frame);
+        should_advance_frame = false;
+        if should_run {
+            sticky_turbo.apply(&mut system.get_controllers_mut()[0]);
+            input_delay.start_frame(&mut system);
+            apply_movie_input(&mut system, &mut movie);
+            if let Some(recorder) = movie_recorder.as_mut() {
+                let controllers = system.get_controllers();
+                recorder
+                    .record_frame([controllers[0].capture_byte(), controllers[1].capture_byte()])
+                    .expect("Could not record movie input");
+            }
+            match netplay.as_mut() {
+                Some(session) => {
+                    if let Err(x) = session.run_frame(&mut system, &mut pixels) {
+                        error!("Netplay is over: {x:#}");
+                        netplay = None;
+                    }
+                }
+                None => system.render_into(&mut pixels),
+            }
+            input_delay.end_frame(&mut system);
+        }
+        let overscan = options.overscan;
+        let visible_area = sdl2::rect::Rect::new(
+            overscan.left as i32,
+            overscan.top as i32,
+            NES_WIDTH as u32 - overscan.left - overscan.right,
+            NES_HEIGHT as u32 - overscan.top - overscan.bottom,
+        );
+        #[cfg(feature = "ntsc-filter")]
+        let is_filtered = is_ntsc_filter_on;
+        #[cfg(not(feature = "ntsc-filter"))]
+        let is_filtered = false;
+        if !is_filtered {
+            tv_texture
+                .update(None, system::framebuffer_as_bytes(&pixels), NES_PITCH)
+                .expect("Could not update the native texture with raw pixel data");
+            scaler.draw(&mut tv_canvas, &tv_texture, visible_area);
+        }
+        #[cfg(feature = "ntsc-filter")]
+        if is_filtered {
+            ntsc_filter.apply(&pixels, &mut ntsc_pixels);
+            ntsc_texture
+                .update(
+                    None,
+                    system::framebuffer_as_bytes(&ntsc_pixels),
+                    std::mem::size_of::<u32>() * ntsc_filter::NTSC_OUTPUT_WIDTH,
+                )
+                .expect("Could not update the NTSC texture with filtered pixel data");
+            // The filtered picture is twice as wide, so the overscan is too.
+            let visible_area = sdl2::rect::Rect::new(
+                visible_area.x() * 2,
+                visible_area.y(),
+                visible_area.width() * 2,
+                visible_area.height(),
+            );
+            scaler.draw(&mut tv_canvas, &ntsc_texture, visible_area);
+        }
+        scanlines.draw(&mut tv_canvas, visible_area.height());
+        if grid_overlay != GridOverlay::Off {
+            grid_overlay.draw(system.get_background_grid(), &mut grid_pixels);
+            grid_texture
+                .update(None, system::framebuffer_as_bytes(&grid_pixels), NES_PITCH)
+                .expect("Could not update the grid overlay texture");
+            tv_canvas
+                .copy(&grid_texture, visible_area, None)
+                .expect("could not copy grid overlay texture to window texture");
+        }
+        if palette_editor.is_on {
+            monaco_for_tv.render_to_canvas(&mut tv_canvas, 4, 4, &palette_editor.describe(&system));
+        }
+        if is_timing_overlay_on {
+            timing_overlay::draw(&mut tv_canvas, &monaco_for_tv, &system, visible_area);
+        }
         // HACK
         if false {
-            for chunk in system.get_devices().get_ppu().oam.chunks_exact(4) {
-                let (y, tile, attributes, x) = (chunk[0], chunk[1], chunk[2], chunk[3]);
+            for sprite in system.get_devices().get_ppu().decoded_sprites() {
                 monaco_for_tv.render_to_canvas(
                     &mut tv_canvas,
-                    x as i32 * 2,
-                    y as i32 * 2,
-                    &format!("{tile:02X}\n{attributes:02X}"),
+                    sprite.x as i32 * 2,
+                    sprite.y as i32 * 2,
+                    &format!("{:02X}\n{}", sprite.tile_index, sprite.palette),
                 );
             }
         }
@@ -108,14 +308,203 @@ fn main() {
         // All done drawing, do user input
         ///////////////////////////////////////////////////////////////////////
         for event in event_pump.poll_iter() {
-            use sdl2::{event::Event, keyboard::Keycode};
+            use sdl2::{
+                event::{Event, WindowEvent},
+                keyboard::Keycode,
+                mouse::MouseButton,
+            };
+            if debug_windows
+                .iter_mut()
+                .any(|debug_window| debug_window.handle_event(&event))
+            {
+                continue;
+            }
+            if gamepads.handle_event(&event, &mut system) {
+                continue;
+            }
+            let tv_window_id = tv_canvas.window().id();
             match event {
                 Event::Quit { .. } => break 'running,
+                Event::MouseMotion {
+                    window_id, x, y, ..
+                } if window_id == tv_window_id => {
+                    let aim = window_to_nes(tv_canvas.window().size(), visible_area, x, y);
+                    if let Some(zapper) = system.get_zapper_mut() {
+                        zapper.aim = aim;
+                    }
+                }
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Leave,
+                    ..
+                } if window_id == tv_window_id => {
+                    if let Some(zapper) = system.get_zapper_mut() {
+                        zapper.aim = None;
+                    }
+                }
+                Event::MouseButtonDown {
+                    window_id,
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } if window_id == tv_window_id => {
+                    if let Some(zapper) = system.get_zapper_mut() {
+                        zapper.is_trigger_pulled = true;
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(zapper) = system.get_zapper_mut() {
+                        zapper.is_trigger_pulled = false;
+                    }
+                }
                 Event::KeyDown {
                     keycode: Some(keycode),
                     ..
                 } => match keycode {
                     Keycode::Escape => break 'running,
+                    x if x == options.sticky_turbo_keys.a || x == options.sticky_turbo_keys.b => {
+                        let button = match x == options.sticky_turbo_keys.a {
+                            true => TurboButton::A,
+                            false => TurboButton::B,
+                        };
+                        sticky_turbo.toggle(button, &mut system.get_controllers_mut()[0]);
+                        let is_on = match button {
+                            TurboButton::A => sticky_turbo.is_a_on,
+                            TurboButton::B => sticky_turbo.is_b_on,
+                        };
+                        info!(
+                            "Sticky turbo {button:?} {}",
+                            if is_on { "on" } else { "off" }
+                        );
+                    }
+                    Keycode::F2 if netplay.is_some() => {
+                        warn!("Can't record a movie during netplay");
+                    }
+                    Keycode::F2 => match movie_recorder.take() {
+                        Some(recorder) => recorder.finish().expect("Could not finish the movie"),
+                        None => {
+                            // Movies always start from power-on, so pull the
+                            // plug and plug it back in.
+                            let trace_writer = system.set_trace_writer(None);
+                            let (game_options, cartridge) = load_game(&base_options);
+                            options = game_options;
+                            system = System::new(cartridge);
+                            configure(&mut system, &options, region);
+                            system.set_trace_writer(trace_writer);
+                            movie = None;
+                            let path = next_free_path(&options.rom_path, "fm2");
+                            movie_recorder = Some(
+                                MovieRecorder::create(&path, &options.rom_path)
+                                    .expect("Could not start recording a movie"),
+                            );
+                        }
+                    },
+                    #[cfg(feature = "ntsc-filter")]
+                    Keycode::F3 => {
+                        is_ntsc_filter_on = !is_ntsc_filter_on;
+                        info!(
+                            "NTSC filter {}",
+                            if is_ntsc_filter_on { "on" } else { "off" }
+                        );
+                    }
+                    Keycode::F4 => {
+                        grid_overlay = grid_overlay.next();
+                        info!("Grid overlay: {grid_overlay:?}");
+                    }
+                    Keycode::LeftBracket | Keycode::RightBracket if netplay.is_some() => {
+                        warn!("Can't switch games during netplay");
+                    }
+                    Keycode::LeftBracket | Keycode::RightBracket
+                        if base_options.rom_paths.len() > 1 =>
+                    {
+                        let count = base_options.rom_paths.len();
+                        rom_index = match keycode {
+                            Keycode::RightBracket => (rom_index + 1) % count,
+                            _ => (rom_index + count - 1) % count,
+                        };
+                        base_options.rom_path = base_options.rom_paths[rom_index].clone();
+                        info!("Switching to '{}'", base_options.rom_path);
+                        // The movie's for the old game. (So would save RAM
+                        // be, but we don't do that yet.)
+                        if let Some(recorder) = movie_recorder.take() {
+                            recorder.finish().expect("Could not finish the movie");
+                        }
+                        movie = None;
+                        let trace_writer = system.set_trace_writer(None);
+                        let (game_options, cartridge) = load_game(&base_options);
+                        options = game_options;
+                        region = pick_region(&options, &cartridge);
+                        system = System::new(cartridge);
+                        configure(&mut system, &options, region);
+                        system.set_trace_writer(trace_writer);
+                        // The old game's config might have had its own
+                        // controls.
+                        gamepads.set_settings(options.gamepad);
+                        input_delay = InputDelay::new(options.input_delay, &system);
+                        if frame_limiter.is_some() {
+                            frame_limiter = Some(FrameLimiter::new(region.frames_per_second()));
+                        }
+                    }
+                    Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 => {
+                        let scale = keycode as i32 - Keycode::Num0 as i32;
+                        set_window_scale(tv_canvas.window_mut(), visible_area, scale as u32);
+                    }
+                    Keycode::F5 => {
+                        scaler.mode = scaler.mode.next();
+                        info!("Scaling: {:?}", scaler.mode);
+                    }
+                    Keycode::F6 => {
+                        palette_editor.toggle(&mut system);
+                        info!(
+                            "Palette editor {}",
+                            if palette_editor.is_on { "on" } else { "off" }
+                        );
+                    }
+                    Keycode::F7 => {
+                        // Stick with it for the next game, too.
+                        options.no_sprite_limit = !options.no_sprite_limit;
+                        base_options.no_sprite_limit = options.no_sprite_limit;
+                        system.set_sprite_limit_lifted(options.no_sprite_limit);
+                        info!(
+                            "Sprite limit {}",
+                            if options.no_sprite_limit { "off" } else { "on" }
+                        );
+                    }
+                    Keycode::F8 => {
+                        is_timing_overlay_on = !is_timing_overlay_on;
+                        info!(
+                            "Timing overlay {}",
+                            if is_timing_overlay_on { "on" } else { "off" }
+                        );
+                    }
+                    Keycode::F9 => {
+                        scanlines.is_on = !scanlines.is_on;
+                        info!("Scanlines {}", if scanlines.is_on { "on" } else { "off" });
+                    }
+                    Keycode::F10 => println!("{}", system.dump_state_string()),
+                    Keycode::PageUp if palette_editor.is_on => palette_editor.move_target(-1),
+                    Keycode::PageDown if palette_editor.is_on => palette_editor.move_target(1),
+                    Keycode::Minus if palette_editor.is_on => {
+                        palette_editor.change_color(&mut system, -1)
+                    }
+                    Keycode::Equals if palette_editor.is_on => {
+                        palette_editor.change_color(&mut system, 1)
+                    }
+                    Keycode::P | Keycode::Period if netplay.is_some() => {
+                        warn!("Can't pause during netplay");
+                    }
+                    Keycode::P => {
+                        is_paused = !is_paused;
+                        info!("{}", if is_paused { "Paused" } else { "Unpaused" });
+                    }
+                    // Frame advance. If we're running, this pauses after
+                    // the next frame.
+                    Keycode::Period => {
+                        is_paused = true;
+                        should_advance_frame = true;
+                    }
                     Keycode::Up => system.get_controllers_mut()[0].button_up = true,
                     Keycode::Down => system.get_controllers_mut()[0].button_down = true,
                     Keycode::Left => system.get_controllers_mut()[0].button_left = true,
@@ -124,6 +513,9 @@ fn main() {
                     Keycode::LShift => system.get_controllers_mut()[0].button_b = true,
                     Keycode::Return => system.get_controllers_mut()[0].button_start = true,
                     Keycode::Tab => system.get_controllers_mut()[0].button_select = true,
+                    Keycode::X => system.get_controllers_mut()[0].turbo_a = true,
+                    Keycode::Z => system.get_controllers_mut()[0].turbo_b = true,
+                    Keycode::M => system.get_controllers_mut()[1].microphone = true,
                     _ => info!("Key I don't care about: {keycode}"),
                 },
                 Event::KeyUp {
@@ -138,10 +530,246 @@ fn main() {
                     Keycode::LShift => system.get_controllers_mut()[0].button_b = false,
                     Keycode::Return => system.get_controllers_mut()[0].button_start = false,
                     Keycode::Tab => system.get_controllers_mut()[0].button_select = false,
+                    Keycode::X => system.get_controllers_mut()[0].turbo_a = false,
+                    Keycode::Z => system.get_controllers_mut()[0].turbo_b = false,
+                    Keycode::M => system.get_controllers_mut()[1].microphone = false,
                     _ => (),
                 },
                 _ => {}
             }
         }
+        if let Some(frame_limiter) = frame_limiter.as_mut() {
+            frame_limiter.wait();
+        }
+    }
+    shut_down(&mut system, movie_recorder);
+}
+
+/// Everything that has to happen on the way out, whether that was Escape or
+/// closing the window. Nothing in here panics: if one thing can't be saved,
+/// that's no reason to lose the rest.
+// TODO: battery-backed save RAM belongs here too (written out to a .sav next
+// to the ROM), but we don't have any yet: `Cartridge` turns down ROMs with a
+// battery. Same goes for a final auto-save state once there are save states,
+// and a WAV recording once there's sound. Name the .sav and .state files
+// after `Cartridge::rom_crc32` as well as the ROM, so that two games with the
+// same file name (or one ROM, renamed) don't clobber each other's saves.
+fn shut_down(system: &mut System, movie_recorder: Option<MovieRecorder>) {
+    if let Some(recorder) = movie_recorder {
+        if let Err(x) = recorder.finish() {
+            error!("Could not finish the movie: {x:#}");
+        }
+    }
+    finish_trace(system);
+}
+
+/// Stop tracing (if we were), and make sure every last line made it out.
+fn finish_trace(system: &mut System) {
+    if let Some(mut trace_writer) = system.set_trace_writer(None) {
+        if let Err(x) = trace_writer.flush() {
+            error!("Could not finish the trace file: {x}");
+        }
+    }
+}
+
+/// Load the ROM from the command line, patched if we were asked to.
+fn load_cartridge(options: &Options) -> Cartridge {
+    let result = match options.patch_path.as_deref() {
+        Some(patch_path) => {
+            info!("Patching '{}' with '{patch_path}'", options.rom_path);
+            let rom = std::fs::File::open(&options.rom_path).expect("failed to open that file");
+            let patch = std::fs::File::open(patch_path).expect("Could not open the patch file");
+            Cartridge::from_reader_with_patch(rom, patch)
+        }
+        None => Cartridge::new(&options.rom_path),
+    };
+    result.unwrap_or_else(|x| panic!("Could not load '{}': {x}", options.rom_path))
+}
+
+/// Load the ROM from the command line, and work out the options to run it
+/// with: `base_options`, plus its game config (if it has one).
+fn load_game(base_options: &Options) -> (Options, Cartridge) {
+    let mut cartridge = load_cartridge(base_options);
+    let options = game_config::apply(base_options, &cartridge);
+    if let Some(mirroring_type) = options.mirroring {
+        info!(
+            "Mirroring: {mirroring_type:?} (instead of {:?})",
+            cartridge.mirroring_type
+        );
+        // The PPU looks at this every time it touches a nametable.
+        cartridge.mirroring_type = mirroring_type;
+    }
+    (options, cartridge)
+}
+
+/// Which region to be for `cartridge`, which came from `options.rom_path`.
+fn pick_region(options: &Options, cartridge: &Cartridge) -> Region {
+    let region = options
+        .region
+        .unwrap_or_else(|| Region::detect(&options.rom_path, cartridge));
+    info!("Region: {region:?}");
+    region
+}
+
+/// Set up a freshly made `System` the way the command line asked for.
+fn configure(system: &mut System, options: &Options, region: Region) {
+    system.set_region(region);
+    system.fill_ram(options.ram_pattern);
+    system.set_famicom(options.famicom);
+    system.set_four_score_plugged_in(options.four_score);
+    system.set_zapper_plugged_in(options.zapper);
+    system.set_sprite_limit_lifted(options.no_sprite_limit);
+    system.set_overclock(options.overclock.unwrap_or(1));
+    for code in options.cheats.iter() {
+        system
+            .add_cheat(code)
+            .expect("Cheat codes get checked in Options::parse");
+    }
+}
+
+/// Hash everything that has to match for netplay to work: the ROM file, the
+/// patch (if any), and every setting that changes how the game runs.
+fn hash_setup(options: &Options, region: Region) -> u64 {
+    let rom = std::fs::read(&options.rom_path).expect("Could not read the ROM file again");
+    let settings = format!(
+        "{region:?} {:?} {:?} {} {} {:?} {:?}",
+        options.mirroring,
+        options.ram_pattern,
+        options.famicom,
+        options.four_score,
+        options.cheats,
+        options.overclock
+    );
+    let patch = options.patch_path.as_ref().map_or(vec![], |path| {
+        std::fs::read(path).expect("Could not read the patch file again")
+    });
+    let hash = netplay::hash_bytes(netplay::HASH_START, &rom);
+    let hash = netplay::hash_bytes(hash, &patch);
+    netplay::hash_bytes(hash, settings.as_bytes())
+}
+
+/// Draw both pattern tables side by side, 16x16 tiles each, with color 0
+/// as black and color 3 as white.
+fn draw_pattern_tables(cartridge: &Cartridge) -> Vec<u32> {
+    const GRAYS: [u32; 4] = [0x000000, 0x555555, 0xAAAAAA, 0xFFFFFF];
+    const TILE_BYTES: u16 = 16;
+    let mut result = vec![0; PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT];
+    if cartridge.chr_data.is_empty() {
+        // CHR RAM, which starts out blank.
+        warn!("This cartridge has no CHR ROM, so there are no tiles to dump");
+        return result;
+    }
+    for (y, row) in result.chunks_exact_mut(PATTERN_TABLES_WIDTH).enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let (table, tile_x) = (x / 128, (x % 128) / 8);
+            let tile_number = (table * 256 + (y / 8) * 16 + tile_x) as u16;
+            let color = cartridge.get_tile(tile_number * TILE_BYTES, x % 8, y % 8);
+            *pixel = GRAYS[color as usize];
+        }
+    }
+    result
+}
+
+/// Run exactly `frames` frames without touching SDL at all, and optionally
+/// save (or print) the last one. Same ROM + same frame count = same picture,
+/// every time.
+fn run_headless(
+    system: &mut System,
+    movie: &mut Option<Movie>,
+    frames: usize,
+    dump_path: Option<&str>,
+    ascii: bool,
+) {
+    let mut pixels = [0; NES_PIXEL_COUNT];
+    for _ in 0..frames {
+        apply_movie_input(system, movie);
+        system.render_into(&mut pixels);
+    }
+    info!(
+        "Ran {frames} frames headlessly. {}",
+        system.show_cpu_state()
+    );
+    if let Some(dump_path) = dump_path {
+        screenshot::save_png(dump_path, NES_WIDTH, NES_HEIGHT, &pixels)
+            .expect("Could not save the final frame");
+    }
+    if ascii {
+        print!(
+            "{}",
+            system::render_ascii(
+                &pixels,
+                system::ASCII_MAX_COLUMNS,
+                system::ASCII_MAX_ROWS,
+                system::ASCII_RAMP
+            )
+        );
+    }
+}
+
+/// If a movie is playing, replace the controller state with the movie's
+/// input for the upcoming frame. This must happen right before each call to
+/// `System::render`, and nowhere else, so that frame N of the movie always
+/// lands on frame N of emulation no matter what the keyboard is doing.
+fn apply_movie_input(system: &mut System, movie: &mut Option<Movie>) {
+    let Some(playing_movie) = movie else {
+        return;
+    };
+    match playing_movie.next_input() {
+        Some(input) => {
+            for (player, buttons) in input.into_iter().enumerate() {
+                system.set_controller_state(player, buttons);
+            }
+        }
+        None => {
+            info!("Movie playback finished. The keyboard is yours again.");
+            for player in 0..system.get_controllers().len() {
+                system.set_controller_state(player, 0);
+            }
+            *movie = None;
+        }
+    }
+}
+
+/// Which NES pixel is at `(x, y)` in a window of `window_size`, if the
+/// window is showing `visible_area` of the picture stretched to fill it.
+/// (Mouse coordinates are in window units, not pixels, so this works even
+/// on high-DPI screens.)
+fn window_to_nes(
+    window_size: (u32, u32),
+    visible_area: sdl2::rect::Rect,
+    x: i32,
+    y: i32,
+) -> Option<(usize, usize)> {
+    let (window_width, window_height) = window_size;
+    if x < 0 || y < 0 || x as u32 >= window_width || y as u32 >= window_height {
+        return None;
     }
+    let nes_x = visible_area.x() as u32 + x as u32 * visible_area.width() / window_width;
+    let nes_y = visible_area.y() as u32 + y as u32 * visible_area.height() / window_height;
+    Some((nes_x as usize, nes_y as usize))
+}
+
+/// Make the TV window exactly `scale` times the size of `visible_area`, and
+/// put it back in the middle of the screen. Window sizes are in window units,
+/// not pixels, so on a high-DPI screen this is bigger than it sounds, which
+/// is what you want.
+fn set_window_scale(window: &mut sdl2::video::Window, visible_area: sdl2::rect::Rect, scale: u32) {
+    let (width, height) = (visible_area.width() * scale, visible_area.height() * scale);
+    match window.set_size(width, height) {
+        Ok(()) => info!("Window scale: {scale}x ({width}x{height})"),
+        Err(x) => warn!("Couldn't resize the window: {x}"),
+    }
+    window.set_position(
+        sdl2::video::WindowPos::Centered,
+        sdl2::video::WindowPos::Centered,
+    );
+}
+
+/// Find a path like `game-1.fm2` next to `game.nes` that isn't taken yet.
+fn next_free_path(rom_path: &str, extension: &str) -> String {
+    let stem = std::path::Path::new(rom_path).with_extension("");
+    (1..)
+        .map(|n| format!("{}-{n}.{extension}", stem.display()))
+        .find(|path| !std::path::Path::new(path).exists())
+        .unwrap()
 }
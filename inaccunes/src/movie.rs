@@ -0,0 +1,161 @@
+use std::{
+    fs::{read_to_string, File},
+    io::{BufWriter, Write},
+};
+
+use anyhow::{anyhow, Context};
+use log::*;
+
+/// The letters used for each button in an input line, in the order they
+/// appear. This is the same order as FCEUX's FM2 format, and it's also our
+/// own bit order (see `BUTTON_*` in `system.rs`) read from bit 7 down to
+/// bit 0. How convenient!
+const BUTTON_LETTERS: &[u8; 8] = b"RLDUTSBA";
+
+/// Per-frame input for both controllers, to be played back one frame at a
+/// time.
+///
+/// The file format is a subset of FM2. Every line that starts with `|` is
+/// one frame of input, and looks like this:
+///
+/// ```text
+/// |0|RLDUTSBA|........|
+/// ```
+///
+/// The first field is the FM2 "commands" field, which we ignore. The next
+/// two are controllers 1 and 2. A `.` or a space means "not pressed",
+/// anything else means "pressed". Any other line (FM2 header lines, blank
+/// lines...) is ignored.
+pub struct Movie {
+    frames: Vec<[u8; 2]>,
+    next_frame: usize,
+}
+
+impl Movie {
+    pub fn load(path: &str) -> anyhow::Result<Movie> {
+        let text =
+            read_to_string(path).with_context(|| format!("Unable to read movie file {path:?}"))?;
+        let movie = Movie::parse(&text).with_context(|| format!("In movie file {path:?}"))?;
+        info!("Loaded a {} frame movie from {path:?}", movie.frames.len());
+        Ok(movie)
+    }
+    pub fn parse(text: &str) -> anyhow::Result<Movie> {
+        let mut frames = vec![];
+        for (line_number, line) in text.lines().enumerate() {
+            let Some(line) = line.strip_prefix('|') else {
+                continue;
+            };
+            let fields: Vec<&str> = line.split('|').collect();
+            if fields.len() < 3 {
+                return Err(anyhow!(
+                    "Line {}: expected at least two controllers",
+                    line_number + 1
+                ));
+            }
+            let mut frame = [0; 2];
+            for (controller, field) in frame.iter_mut().zip(&fields[1..3]) {
+                *controller = parse_buttons(field)
+                    .ok_or_else(|| anyhow!("Line {}: bad buttons {field:?}", line_number + 1))?;
+            }
+            frames.push(frame);
+        }
+        Ok(Movie {
+            frames,
+            next_frame: 0,
+        })
+    }
+    /// Get the input for the next frame, or `None` if playback is over.
+    pub fn next_input(&mut self) -> Option<[u8; 2]> {
+        let result = self.frames.get(self.next_frame).copied();
+        if result.is_some() {
+            self.next_frame += 1;
+        }
+        result
+    }
+}
+
+/// Writes per-frame input for both controllers out in the same format that
+/// `Movie` reads.
+///
+/// We don't have save states, so every movie starts from power-on. Whoever
+/// starts a recording is responsible for power cycling the `System` first.
+pub struct MovieRecorder {
+    path: String,
+    writer: BufWriter<File>,
+    frame_count: usize,
+}
+
+impl MovieRecorder {
+    pub fn create(path: &str, rom_path: &str) -> anyhow::Result<MovieRecorder> {
+        let file = File::create(path).with_context(|| format!("Unable to create {path:?}"))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "version 3")?;
+        writeln!(writer, "emuVersion 0")?;
+        writeln!(writer, "romFilename {rom_path}")?;
+        writeln!(writer, "port0 1")?;
+        writeln!(writer, "port1 1")?;
+        writeln!(
+            writer,
+            "comment recorded by inaccunes, starting from power-on"
+        )?;
+        info!("Recording a movie to {path:?}");
+        Ok(MovieRecorder {
+            path: path.to_string(),
+            writer,
+            frame_count: 0,
+        })
+    }
+    /// Record the input for one frame. `input` must be exactly what the
+    /// controllers will latch for the upcoming frame.
+    pub fn record_frame(&mut self, input: [u8; 2]) -> anyhow::Result<()> {
+        let [first, second] = input.map(unparse_buttons);
+        writeln!(self.writer, "|0|{first}|{second}||")
+            .with_context(|| format!("Unable to write to {:?}", self.path))?;
+        self.frame_count += 1;
+        Ok(())
+    }
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.writer
+            .flush()
+            .with_context(|| format!("Unable to write to {:?}", self.path))?;
+        info!(
+            "Finished recording a {} frame movie to {:?}",
+            self.frame_count, self.path
+        );
+        Ok(())
+    }
+}
+
+/// Turn e.g. `"R......A"` into a packed controller byte. An empty field
+/// counts as "nothing pressed", since that's what FM2 does for controllers
+/// that aren't plugged in.
+fn parse_buttons(field: &str) -> Option<u8> {
+    if field.is_empty() {
+        return Some(0);
+    }
+    if field.len() != BUTTON_LETTERS.len() {
+        return None;
+    }
+    let mut result = 0;
+    for (index, ch) in field.bytes().enumerate() {
+        if ch != b'.' && ch != b' ' {
+            result |= 0x80 >> index;
+        }
+    }
+    Some(result)
+}
+
+/// The opposite of `parse_buttons`.
+fn unparse_buttons(buttons: u8) -> String {
+    BUTTON_LETTERS
+        .iter()
+        .enumerate()
+        .map(|(index, letter)| {
+            if buttons & (0x80 >> index) != 0 {
+                *letter as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
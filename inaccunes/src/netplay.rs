@@ -0,0 +1,239 @@
+//! Lockstep netplay: two copies of inaccunes, on two machines, playing the
+//! same game together. One side hosts (`--netplay-host PORT`) and is player
+//! 1, the other joins (`--netplay-join HOST:PORT`) and is player 2.
+//!
+//! Emulation is deterministic, so the only thing we have to send is each
+//! frame's controller input. Neither side runs a frame until it has both
+//! players' input for it. To keep that from stalling every single frame,
+//! input takes effect `INPUT_DELAY` frames after it's pressed, which gives
+//! it that long to make it across the network.
+//!
+//! Every message is `MESSAGE_LENGTH` bytes: a kind, a frame number (`u32`,
+//! little-endian), and a payload (`u64`, little-endian). Before any of that,
+//! both sides send a `HELLO` with a hash of the ROM and settings, so we don't
+//! try to play two different games against each other.
+//!
+//! Only controller buttons (and turbo) go over the wire. The microphone
+//! doesn't, and neither does a Zapper, so `--zapper` isn't allowed.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use inaccunes::{system::System, WORK_RAM_SIZE};
+use log::*;
+
+/// How many frames late everyone's input shows up. Each frame is about
+/// 17ms, so this hides a round trip of about 33ms.
+const INPUT_DELAY: u32 = 2;
+/// How often (in frames) to compare notes on what the game looks like.
+const HASH_INTERVAL: u32 = 60;
+/// If we hear nothing at all for this long, assume the other side is gone.
+const TIMEOUT: Duration = Duration::from_secs(10);
+const HELLO: &[u8; 8] = b"INACNET1";
+const MESSAGE_LENGTH: usize = 13;
+const MESSAGE_INPUT: u8 = 0;
+const MESSAGE_HASH: u8 = 1;
+
+/// How to get connected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetplayMode {
+    /// Wait for someone to connect on this port, and be player 1.
+    Host(u16),
+    /// Connect to someone at this address, and be player 2.
+    Join(String),
+}
+
+pub struct Netplay {
+    stream: TcpStream,
+    /// 0 if we're hosting, 1 if we joined.
+    player: usize,
+    /// The next frame we're going to run.
+    frame: u32,
+    /// Input we've sent, for frames we haven't run yet.
+    local_inputs: VecDeque<u8>,
+    /// Input we've received, for frames we haven't run yet.
+    remote_inputs: VecDeque<u8>,
+    /// Hashes from one side that the other side hasn't caught up to yet.
+    local_hashes: HashMap<u32, u64>,
+    remote_hashes: HashMap<u32, u64>,
+}
+
+impl Netplay {
+    /// Get connected, and make sure we're both about to play the same thing.
+    /// `setup_hash` should cover everything that could make the two sides
+    /// run differently (see `hash_bytes`). This blocks until the other side
+    /// shows up.
+    pub fn connect(mode: &NetplayMode, setup_hash: u64) -> anyhow::Result<Netplay> {
+        let (stream, player) = match mode {
+            NetplayMode::Host(port) => {
+                let listener = TcpListener::bind(("0.0.0.0", *port))
+                    .with_context(|| format!("Could not listen on port {port}"))?;
+                info!("Netplay: waiting for player 2 on port {port}...");
+                let (stream, address) = listener.accept()?;
+                info!("Netplay: player 2 connected from {address}");
+                (stream, 0)
+            }
+            NetplayMode::Join(address) => {
+                info!("Netplay: connecting to {address}...");
+                let stream = TcpStream::connect(address)
+                    .with_context(|| format!("Could not connect to {address}"))?;
+                info!("Netplay: connected, we're player 2");
+                (stream, 1)
+            }
+        };
+        // Every message is tiny and urgent.
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut netplay = Netplay {
+            stream,
+            player,
+            frame: 0,
+            // Nobody's pressed anything before the game starts.
+            local_inputs: VecDeque::from(vec![0; INPUT_DELAY as usize]),
+            remote_inputs: VecDeque::from(vec![0; INPUT_DELAY as usize]),
+            local_hashes: HashMap::new(),
+            remote_hashes: HashMap::new(),
+        };
+        let mut hello = [0; 16];
+        hello[..8].copy_from_slice(HELLO);
+        hello[8..].copy_from_slice(&setup_hash.to_le_bytes());
+        netplay.stream.write_all(&hello)?;
+        let mut their_hello = [0; 16];
+        netplay.stream.read_exact(&mut their_hello)?;
+        if their_hello[..8] != HELLO[..] {
+            return Err(anyhow!("That's not an inaccunes netplay session"));
+        }
+        if their_hello[8..] != hello[8..] {
+            return Err(anyhow!(
+                "The other side has a different ROM or different settings \
+                 (region, --ram, --famicom, --four-score, --cheat)"
+            ));
+        }
+        Ok(netplay)
+    }
+    /// Run one frame, with both players' input. Use this instead of
+    /// `System::render_into`.
+    ///
+    /// Whatever the keyboard is doing to the first controller is this
+    /// side's input, whichever player we are. It gets put back after the
+    /// frame, so that the keyboard picks up where it left off.
+    pub fn run_frame(&mut self, system: &mut System, pixels: &mut [u32]) -> anyhow::Result<()> {
+        let keyboard = &mut system.get_controllers_mut()[0];
+        // Turbo gets baked into the buttons here, so that the other side
+        // sees exactly what we do without having to know about it.
+        let local_input = keyboard.capture_byte();
+        let held_buttons = keyboard.get_buttons();
+        let turbo = (keyboard.turbo_a, keyboard.turbo_b);
+        (keyboard.turbo_a, keyboard.turbo_b) = (false, false);
+        let microphone = std::mem::take(&mut system.get_controllers_mut()[1].microphone);
+        let mut inputs = [0; 2];
+        inputs[self.player] = self.exchange_input(local_input)?;
+        inputs[1 - self.player] = self.receive_input()?;
+        for (player, buttons) in inputs.into_iter().enumerate() {
+            system.set_controller_state(player, buttons);
+        }
+        system.render_into(pixels);
+        system.set_controller_state(0, held_buttons);
+        let keyboard = &mut system.get_controllers_mut()[0];
+        (keyboard.turbo_a, keyboard.turbo_b) = turbo;
+        system.get_controllers_mut()[1].microphone = microphone;
+        if self.frame.is_multiple_of(HASH_INTERVAL) {
+            let hash = hash_frame(system, pixels);
+            self.send(MESSAGE_HASH, self.frame, hash)?;
+            self.local_hashes.insert(self.frame, hash);
+            self.compare_hashes();
+        }
+        self.frame += 1;
+        Ok(())
+    }
+    /// Send this frame's local input off, and get back the local input
+    /// that's due now.
+    fn exchange_input(&mut self, local_input: u8) -> anyhow::Result<u8> {
+        self.send(MESSAGE_INPUT, self.frame + INPUT_DELAY, local_input as u64)?;
+        self.local_inputs.push_back(local_input);
+        Ok(self.local_inputs.pop_front().unwrap())
+    }
+    /// Get the other side's input for this frame, waiting for it if it
+    /// hasn't shown up yet.
+    fn receive_input(&mut self) -> anyhow::Result<u8> {
+        while self.remote_inputs.is_empty() {
+            let mut message = [0; MESSAGE_LENGTH];
+            self.stream
+                .read_exact(&mut message)
+                .context("Lost the other player")?;
+            let kind = message[0];
+            let frame = u32::from_le_bytes(message[1..5].try_into().unwrap());
+            let payload = u64::from_le_bytes(message[5..].try_into().unwrap());
+            match kind {
+                MESSAGE_INPUT => {
+                    if frame != self.frame {
+                        return Err(anyhow!(
+                            "Got input for frame {frame} when we wanted frame {}",
+                            self.frame
+                        ));
+                    }
+                    self.remote_inputs.push_back(payload as u8);
+                }
+                MESSAGE_HASH => {
+                    self.remote_hashes.insert(frame, payload);
+                    self.compare_hashes();
+                }
+                x => return Err(anyhow!("Unknown netplay message {x}")),
+            }
+        }
+        Ok(self.remote_inputs.pop_front().unwrap())
+    }
+    fn send(&mut self, kind: u8, frame: u32, payload: u64) -> anyhow::Result<()> {
+        let mut message = [0; MESSAGE_LENGTH];
+        message[0] = kind;
+        message[1..5].copy_from_slice(&frame.to_le_bytes());
+        message[5..].copy_from_slice(&payload.to_le_bytes());
+        self.stream
+            .write_all(&message)
+            .context("Lost the other player")
+    }
+    /// Check every frame that both sides have a hash for. A mismatch means
+    /// the two games have drifted apart, and will probably never come back.
+    fn compare_hashes(&mut self) {
+        let frames: Vec<u32> = self
+            .local_hashes
+            .keys()
+            .filter(|frame| self.remote_hashes.contains_key(frame))
+            .copied()
+            .collect();
+        for frame in frames {
+            let local_hash = self.local_hashes.remove(&frame).unwrap();
+            let remote_hash = self.remote_hashes.remove(&frame).unwrap();
+            if local_hash != remote_hash {
+                error!("Netplay desync at frame {frame}! The two games are no longer the same.");
+            }
+        }
+    }
+}
+
+/// FNV-1a, which is tiny and, unlike `DefaultHasher`, guaranteed to give
+/// the same answer on both machines.
+pub fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Where `hash_bytes` starts.
+pub const HASH_START: u64 = 0xCBF2_9CE4_8422_2325;
+
+/// Boil the state of the game down to a number: work RAM (which usually
+/// goes wrong first), plus the picture. (The pixels are hashed
+/// little-endian, in case the two machines disagree.)
+fn hash_frame(system: &System, pixels: &[u32]) -> u64 {
+    let ram: Vec<u8> = (0..WORK_RAM_SIZE as u16)
+        .map(|address| system.get_work_memory_byte(address))
+        .collect();
+    let pixel_bytes: Vec<u8> = pixels.iter().flat_map(|x| x.to_le_bytes()).collect();
+    hash_bytes(hash_bytes(HASH_START, &ram), &pixel_bytes)
+}
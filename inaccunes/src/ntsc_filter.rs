@@ -0,0 +1,100 @@
+//! A cheap imitation of what happens to the picture on its way through a
+//! composite video cable: colors bleed into each other sideways, and fine
+//! detail picks up rainbow fringes that crawl around from frame to frame.
+//!
+//! This isn't a real signal simulation (for that, see blargg's nes_ntsc). We
+//! just turn every pixel into YIQ, smear the chroma a lot more than the luma
+//! (since it has a lot less bandwidth), and leak a little chroma into the
+//! luma at the color subcarrier's phase. It's enough to get the look.
+
+use crate::{NES_HEIGHT, NES_PIXEL_COUNT, NES_WIDTH};
+
+/// How many output pixels each NES pixel turns into.
+const SAMPLES_PER_PIXEL: usize = 2;
+/// How wide the filtered picture is.
+pub const NTSC_OUTPUT_WIDTH: usize = NES_WIDTH * SAMPLES_PER_PIXEL;
+pub const NTSC_OUTPUT_PIXEL_COUNT: usize = NTSC_OUTPUT_WIDTH * NES_HEIGHT;
+/// The color subcarrier goes through one cycle every 12 master clocks, and a
+/// pixel is 8 master clocks, so each of our samples (half a pixel) moves
+/// the phase along by a third of a cycle.
+const PHASES: usize = 3;
+/// The chroma filter is this many samples wide. Luma gets 3.
+const CHROMA_TAPS: usize = 6;
+/// How much chroma leaks into luma.
+const CROSSTALK: f32 = 0.25;
+
+#[derive(Default)]
+pub struct NtscFilter {
+    /// Where the subcarrier starts on the first scanline. It moves from frame
+    /// to frame, which is what makes the dots crawl.
+    frame_phase: usize,
+}
+
+impl NtscFilter {
+    pub fn new() -> NtscFilter {
+        NtscFilter::default()
+    }
+    /// Filter one frame from `System::render_into` (`NES_PIXEL_COUNT` pixels)
+    /// into `output` (`NTSC_OUTPUT_PIXEL_COUNT` pixels, `NTSC_OUTPUT_WIDTH`
+    /// per row). Both are `0x00RRGGBB`.
+    pub fn apply(&mut self, input: &[u32], output: &mut [u32]) {
+        assert_eq!(input.len(), NES_PIXEL_COUNT, "Wrong size input");
+        assert_eq!(output.len(), NTSC_OUTPUT_PIXEL_COUNT, "Wrong size output");
+        let mut yiq = vec![[0.0f32; 3]; NTSC_OUTPUT_WIDTH];
+        for (y, (in_row, out_row)) in input
+            .chunks_exact(NES_WIDTH)
+            .zip(output.chunks_exact_mut(NTSC_OUTPUT_WIDTH))
+            .enumerate()
+        {
+            for (sample, pixel) in yiq.iter_mut().zip(
+                in_row
+                    .iter()
+                    .flat_map(|x| std::iter::repeat_n(*x, SAMPLES_PER_PIXEL)),
+            ) {
+                *sample = rgb_to_yiq(pixel);
+            }
+            // Each scanline is 341 dots, which leaves the subcarrier a third
+            // of a cycle further along than where the last one started.
+            let line_phase = self.frame_phase + y;
+            for (x, out_pixel) in out_row.iter_mut().enumerate() {
+                let luma = (yiq[x.saturating_sub(1)][0]
+                    + yiq[x][0] * 2.0
+                    + yiq[(x + 1).min(NTSC_OUTPUT_WIDTH - 1)][0])
+                    / 4.0;
+                let (mut i, mut q) = (0.0, 0.0);
+                let first = x.saturating_sub(CHROMA_TAPS / 2);
+                let last = (first + CHROMA_TAPS).min(NTSC_OUTPUT_WIDTH);
+                for sample in &yiq[first..last] {
+                    i += sample[1];
+                    q += sample[2];
+                }
+                let taps = (last - first) as f32;
+                let (i, q) = (i / taps, q / taps);
+                let phase =
+                    ((line_phase + x) % PHASES) as f32 * std::f32::consts::TAU / PHASES as f32;
+                let luma = luma + CROSSTALK * (i * phase.cos() + q * phase.sin());
+                *out_pixel = yiq_to_rgb([luma, i, q]);
+            }
+        }
+        self.frame_phase = (self.frame_phase + 1) % PHASES;
+    }
+}
+
+fn rgb_to_yiq(pixel: u32) -> [f32; 3] {
+    let [_, r, g, b] = pixel.to_be_bytes().map(|x| x as f32 / 255.0);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        0.596 * r - 0.274 * g - 0.322 * b,
+        0.211 * r - 0.523 * g + 0.312 * b,
+    ]
+}
+
+fn yiq_to_rgb([y, i, q]: [f32; 3]) -> u32 {
+    let to_byte = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    u32::from_be_bytes([
+        0,
+        to_byte(y + 0.956 * i + 0.621 * q),
+        to_byte(y - 0.272 * i - 0.647 * q),
+        to_byte(y - 1.106 * i + 1.703 * q),
+    ])
+}
@@ -0,0 +1,579 @@
+use anyhow::{anyhow, Context};
+use log::*;
+
+use inaccunes::{
+    cartridge::MirroringType, cheats::Cheat, region::Region, system::RamPattern, NES_HEIGHT,
+    NES_WIDTH,
+};
+
+use crate::gamepad::GamepadSettings;
+use crate::input_delay;
+use crate::netplay::NetplayMode;
+use crate::sticky_turbo::StickyTurboKeys;
+
+pub const USAGE: &str = "\
+Usage: inaccunes [options] path/to/game.nes [more.nes...]
+
+Give it more than one ROM, or a directory full of them, and [ and ] switch
+between them.
+
+Options:
+    --frames N          Run exactly N frames without opening any windows,
+                        then exit.
+    --dump out.png      (with --frames) Save the final frame as a PNG.
+    --ascii             (with --frames) Print the final frame as ASCII art,
+                        for when there's no screen to look at.
+    --dump-chr out.png  Save both pattern tables (every tile in CHR) as a
+                        256x128 grayscale PNG, then exit.
+    --disasm START END  Print a disassembly of the PRG ROM from START to END
+                        (CPU addresses in hex, like 8000 FFFF), then exit.
+                        Anything that doesn't look like an instruction
+                        shows up as .byte, but data can still look like
+                        code.
+    --trace trace.log   Write every instruction the CPU runs to a file, in the
+                        same format as nestest.log.
+    --patch file.ips    Apply an IPS or BPS patch to the ROM before
+                        playing it. (The file stays as it is.)
+    --movie file.fm2    Play back controller input from a movie file.
+    --region ntsc|pal   Which kind of console to be. (Default: guess from the
+                        ROM header and filename, or NTSC.)
+    --mirroring horizontal|vertical|single0|single1|four
+                        Ignore the ROM header's nametable mirroring and use
+                        this instead, for ROMs whose headers are wrong. On
+                        a ROM whose header is right, this breaks the
+                        scrolling (or worse)!
+    --ram PATTERN       What's in work RAM at power-on: zeroes, ones,
+                        stripes (four $00s, four $FFs, repeat), or
+                        random[:SEED]. Without a seed, random picks one and
+                        logs it. (Default: zeroes.)
+    --famicom           Be a Famicom instead of an NES. The second
+                        controller gets a microphone (hold M), which games
+                        see as bit 2 of $4016.
+    --cheat CODE        Turn on a cheat: a Game Genie code (like SXIOPO),
+                        or a raw one (AAAA:VV, or AAAA?CC:VV to only
+                        replace CC). Use it more than once for more cheats.
+    --four-score        Plug in a Four Score, for up to four players.
+    --zapper            Plug a Zapper into the second port instead of a
+                        controller. Aim with the mouse, click to shoot.
+    --no-sprite-limit   Draw every sprite on a scanline, not just the first
+                        eight like a real NES, for less flicker. (F7
+                        switches this on and off while playing.)
+    --overclock N       Give the CPU N times (1-4) as much time each frame as
+                        it really gets, so games that slow down don't. Not
+                        at all accurate: some games will break.
+    --overscan T,B,L,R  Hide this many pixels at the top, bottom, left, and
+                        right edges of the picture, like a TV would. One
+                        number hides that much on every edge. (Default:
+                        8,8,0,0. Screenshots always get the whole picture.)
+    --scanlines N       Darken every other line of the window by N percent,
+                        like a CRT. (F9 switches this on and off while
+                        playing, at 50 if you didn't say.) Needs the window
+                        to be at least twice the size of the picture.
+    --input-delay N     Hold controller input back N frames (0-5) before the
+                        game sees it, to make up for a display with less
+                        lag than the one you're matching. Movies record
+                        the delayed input. (Default: 0.)
+    --sticky-turbo-keys A,B
+                        Which keys switch sticky turbo A and turbo B on and
+                        off. Once it's on, it keeps firing without holding
+                        anything down. (Default: S,A.)
+    --deadzone N        How far (in percent) a gamepad's stick has to leave
+                        the middle before it counts as moving. (Default: 15.)
+    --stick-threshold N How far (in percent) past the deadzone the stick has
+                        to go to press the D-pad. (Default: 50.)
+    --invert-x          Left on the stick is right on the D-pad.
+    --invert-y          Up on the stick is down on the D-pad.
+    --swap-ab           The bottom button on a gamepad is A instead of B, and
+                        the left one is turbo A instead of turbo B.
+    --sync clock|vsync  What decides how fast the game runs: the clock (at
+                        the console's real frame rate), or the monitor's
+                        refresh rate, which is only right at 60Hz (or 50Hz
+                        for PAL). (Default: clock.)
+    --netplay-host PORT Wait for a friend to connect on this port, and play
+                        together, with them as player 2. You both need the
+                        same ROM and settings.
+    --netplay-join HOST:PORT
+                        Connect to a friend who's hosting, and be player 2.
+    --game-config-dir DIR
+                        Where to look for game configs. (Default:
+                        $XDG_CONFIG_HOME/inaccunes/games, or
+                        ~/.config/inaccunes/games.) A game config is a file
+                        of options, named after the ROM's CRC32 (like
+                        1A2B3C4D.txt, see the log), that get used whenever
+                        that game's loaded, on top of the command line's.
+                        Handy for fixing a game's mirroring or region for
+                        good, or giving it its own cheats or controls.
+                        Options that only matter at startup (like --patch,
+                        --movie, or --frames) don't work in one.
+    --debug-port PORT   Listen for a GDB remote debugger on this port. (Only
+                        if built with the debug-server feature.)";
+
+/// The most `--overclock` goes up to. Any more, and a frame takes long
+/// enough to run that we can't keep up.
+const MAX_OVERCLOCK: usize = 4;
+
+/// Options that only matter when we start up, or while the ROM's being
+/// loaded, which is before we know which game config to look at. A game
+/// config that has one of these is an error, instead of silently doing
+/// nothing (or only doing something for the first game).
+const LOAD_TIME_OPTIONS: &[&str] = &[
+    "--frames",
+    "--dump",
+    "--ascii",
+    "--dump-chr",
+    "--disasm",
+    "--trace",
+    "--patch",
+    "--movie",
+    "--netplay-host",
+    "--netplay-join",
+    "--game-config-dir",
+    "--debug-port",
+];
+
+/// How many pixels to hide at each edge of the picture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overscan {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Default for Overscan {
+    /// What most emulators do: the top and bottom 8 lines are usually
+    /// garbage, the sides usually aren't.
+    fn default() -> Overscan {
+        Overscan {
+            top: 8,
+            bottom: 8,
+            left: 0,
+            right: 0,
+        }
+    }
+}
+
+impl Overscan {
+    fn parse(value: &str) -> anyhow::Result<Overscan> {
+        let numbers = value
+            .split(',')
+            .map(|x| x.parse())
+            .collect::<Result<Vec<u32>, _>>()
+            .with_context(|| format!("{value:?} is not a list of numbers"))?;
+        let (top, bottom, left, right) = match numbers[..] {
+            [all] => (all, all, all, all),
+            [top, bottom, left, right] => (top, bottom, left, right),
+            _ => return Err(anyhow!("--overscan needs one number, or four")),
+        };
+        if top + bottom >= NES_HEIGHT as u32 || left + right >= NES_WIDTH as u32 {
+            return Err(anyhow!("--overscan {value} wouldn't leave any picture"));
+        }
+        Ok(Overscan {
+            top,
+            bottom,
+            left,
+            right,
+        })
+    }
+}
+
+/// What we wait for between frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameSync {
+    /// Sleep until it's time for the next frame. (We still wait for vsync
+    /// too, but only so the picture doesn't tear.)
+    #[default]
+    Clock,
+    /// Just wait for vsync, and hope the monitor runs at the right speed.
+    Vsync,
+}
+
+/// Everything we were told on the command line (and in the game config, if
+/// there is one).
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// The ROM we're playing right now. (One of `rom_paths`.)
+    pub rom_path: String,
+    /// Every ROM we were given, in order, with directories already looked
+    /// inside of.
+    pub rom_paths: Vec<String>,
+    /// If present, run headlessly for this many frames and then exit.
+    pub frames: Option<usize>,
+    /// If present, where to save the last frame of a headless run.
+    pub dump_path: Option<String>,
+    /// Whether to print the last frame of a headless run as text.
+    pub ascii: bool,
+    /// If present, where to save the pattern tables instead of running.
+    pub dump_chr_path: Option<String>,
+    /// If present, the first and last address to disassemble instead of
+    /// running.
+    pub disasm: Option<(u16, u16)>,
+    /// If present, where to write a line for every instruction.
+    pub trace_path: Option<String>,
+    /// If present, a patch to apply to the ROM as we load it.
+    pub patch_path: Option<String>,
+    /// If present, a movie file to take controller input from.
+    pub movie_path: Option<String>,
+    /// If present, the region to use instead of guessing.
+    pub region: Option<Region>,
+    /// If present, the mirroring to use instead of the header's.
+    pub mirroring: Option<MirroringType>,
+    /// What to fill work RAM with at power-on.
+    pub ram_pattern: RamPattern,
+    /// Whether to be a Famicom instead of an NES.
+    pub famicom: bool,
+    /// Cheat codes to turn on.
+    pub cheats: Vec<String>,
+    /// Whether there's a Four Score plugged in.
+    pub four_score: bool,
+    /// Whether there's a Zapper in the second port.
+    pub zapper: bool,
+    /// Whether to draw more than eight sprites on a scanline.
+    pub no_sprite_limit: bool,
+    /// If present, how many times as many CPU cycles to give each frame.
+    pub overclock: Option<usize>,
+    /// How much of the picture to hide when showing it.
+    pub overscan: Overscan,
+    /// If present, how dark (out of 100) to start the scanlines out at.
+    pub scanlines: Option<u8>,
+    /// How many frames to hold controller input back.
+    pub input_delay: usize,
+    /// Which keys switch sticky turbo on and off.
+    pub sticky_turbo_keys: StickyTurboKeys,
+    /// How gamepads turn into NES controllers.
+    pub gamepad: GamepadSettings,
+    /// What decides how fast frames go by.
+    pub sync: FrameSync,
+    /// If present, how to connect to the other player.
+    pub netplay: Option<NetplayMode>,
+    /// If present, where to look for game configs instead of the usual
+    /// place.
+    pub game_config_dir: Option<String>,
+    /// If present, the port to listen for a debugger on.
+    pub debug_port: Option<u16>,
+}
+
+impl Options {
+    /// Parse the command line arguments, *not* including the program name.
+    pub fn parse<I: Iterator<Item = String>>(arguments: I) -> anyhow::Result<Options> {
+        let mut result = Options::default();
+        result.parse_arguments(arguments)?;
+        result.rom_path = result
+            .rom_paths
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow!("Please provide a ROM file."))?;
+        result.validate()?;
+        Ok(result)
+    }
+    /// Put the options from a game config file (see `game_config`) on top of
+    /// these ones. It's the same options as on the command line, as many to
+    /// a line as you like, with `#` starting a comment.
+    pub fn apply_game_config(&mut self, text: &str) -> anyhow::Result<()> {
+        let arguments: Vec<String> = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split_whitespace())
+            .map(|x| x.to_string())
+            .collect();
+        if let Some(option) = arguments
+            .iter()
+            .find(|x| LOAD_TIME_OPTIONS.contains(&x.as_str()))
+        {
+            return Err(anyhow!("{option} only works on the command line"));
+        }
+        let rom_count = self.rom_paths.len();
+        self.parse_arguments(arguments.into_iter())?;
+        if self.rom_paths.len() != rom_count {
+            return Err(anyhow!("Only options go in a game config, not ROMs"));
+        }
+        self.validate()
+    }
+    /// Catch options that don't make sense together, wherever they came
+    /// from.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.rom_paths.len() > 1 {
+            // These only make sense for one particular ROM.
+            if self.patch_path.is_some() {
+                return Err(anyhow!("--patch only works with one ROM"));
+            }
+            if self.netplay.is_some() {
+                return Err(anyhow!("Netplay only works with one ROM"));
+            }
+        }
+        if self.dump_path.is_some() && self.frames.is_none() {
+            return Err(anyhow!("--dump only makes sense along with --frames"));
+        }
+        if self.ascii && self.frames.is_none() {
+            return Err(anyhow!("--ascii only makes sense along with --frames"));
+        }
+        if self.netplay.is_some() {
+            // Anything that would make input come from somewhere other than
+            // the two keyboards.
+            if self.frames.is_some() {
+                return Err(anyhow!("Netplay needs windows, so no --frames"));
+            }
+            if self.movie_path.is_some() {
+                return Err(anyhow!("Netplay and --movie don't mix"));
+            }
+            if self.zapper {
+                return Err(anyhow!("The Zapper doesn't work over netplay"));
+            }
+        }
+        Ok(())
+    }
+    /// Set whatever `arguments` say to, and add any ROMs to `rom_paths`.
+    fn parse_arguments<I: Iterator<Item = String>>(
+        &mut self,
+        mut arguments: I,
+    ) -> anyhow::Result<()> {
+        let result = self;
+        while let Some(argument) = arguments.next() {
+            // Every option (so far) takes exactly one value, except for
+            // flags like --famicom, which take none, and --disasm, which
+            // takes two.
+            let mut value_for = |option: &str| {
+                arguments
+                    .next()
+                    .ok_or_else(|| anyhow!("{option} needs a value"))
+            };
+            match argument.as_str() {
+                "--frames" => {
+                    let value = value_for("--frames")?;
+                    result.frames = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("{value:?} is not a number of frames"))?,
+                    );
+                }
+                "--dump" => result.dump_path = Some(value_for("--dump")?),
+                "--ascii" => result.ascii = true,
+                "--dump-chr" => result.dump_chr_path = Some(value_for("--dump-chr")?),
+                "--disasm" => {
+                    let start = parse_address(&value_for("--disasm")?)?;
+                    let end = parse_address(&value_for("--disasm")?)?;
+                    if start < 0x8000 || end < start {
+                        return Err(anyhow!("--disasm needs START <= END, in $8000-$FFFF"));
+                    }
+                    result.disasm = Some((start, end));
+                }
+                "--trace" => result.trace_path = Some(value_for("--trace")?),
+                "--patch" => result.patch_path = Some(value_for("--patch")?),
+                "--movie" => result.movie_path = Some(value_for("--movie")?),
+                "--region" => {
+                    result.region = Some(match value_for("--region")?.as_str() {
+                        "ntsc" => Region::Ntsc,
+                        "pal" => Region::Pal,
+                        x => return Err(anyhow!("Unknown region: {x} (try ntsc or pal)")),
+                    })
+                }
+                "--mirroring" => {
+                    result.mirroring = Some(match value_for("--mirroring")?.as_str() {
+                        "horizontal" => MirroringType::Horizontal,
+                        "vertical" => MirroringType::Vertical,
+                        "single0" => MirroringType::SingleScreenLower,
+                        "single1" => MirroringType::SingleScreenUpper,
+                        "four" => MirroringType::FourScreen,
+                        x => {
+                            return Err(anyhow!(
+                                "Unknown mirroring: {x} (try horizontal, vertical, single0, single1, or four)"
+                            ))
+                        }
+                    })
+                }
+                "--ram" => result.ram_pattern = parse_ram_pattern(&value_for("--ram")?)?,
+                "--famicom" => result.famicom = true,
+                "--cheat" => {
+                    let code = value_for("--cheat")?;
+                    Cheat::parse(&code)?;
+                    result.cheats.push(code);
+                }
+                "--four-score" => result.four_score = true,
+                "--zapper" => result.zapper = true,
+                "--no-sprite-limit" => result.no_sprite_limit = true,
+                "--overclock" => {
+                    let value = value_for("--overclock")?;
+                    result.overclock = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|x| (1..=MAX_OVERCLOCK).contains(x))
+                            .ok_or_else(|| {
+                                anyhow!("{value:?} is not a multiplier (1-{MAX_OVERCLOCK})")
+                            })?,
+                    );
+                }
+                "--overscan" => result.overscan = Overscan::parse(&value_for("--overscan")?)?,
+                "--scanlines" => {
+                    result.scanlines = Some(parse_percentage(&value_for("--scanlines")?)?)
+                }
+                "--input-delay" => {
+                    let value = value_for("--input-delay")?;
+                    result.input_delay = value
+                        .parse()
+                        .ok()
+                        .filter(|x| *x <= input_delay::MAX_FRAMES)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "{value:?} is not a number of frames (0-{})",
+                                input_delay::MAX_FRAMES
+                            )
+                        })?;
+                }
+                "--sticky-turbo-keys" => {
+                    result.sticky_turbo_keys =
+                        StickyTurboKeys::parse(&value_for("--sticky-turbo-keys")?)?
+                }
+                "--deadzone" => {
+                    result.gamepad.deadzone = parse_percentage(&value_for("--deadzone")?)?
+                }
+                "--stick-threshold" => {
+                    result.gamepad.threshold = parse_percentage(&value_for("--stick-threshold")?)?
+                }
+                "--invert-x" => result.gamepad.invert_x = true,
+                "--invert-y" => result.gamepad.invert_y = true,
+                "--swap-ab" => result.gamepad.swap_ab = true,
+                "--sync" => {
+                    result.sync = match value_for("--sync")?.as_str() {
+                        "clock" => FrameSync::Clock,
+                        "vsync" => FrameSync::Vsync,
+                        // TODO: once there's sound, this is the best one
+                        "audio" => return Err(anyhow!("There's no sound to sync to yet")),
+                        x => return Err(anyhow!("Unknown sync: {x} (try clock or vsync)")),
+                    }
+                }
+                "--netplay-host" => {
+                    let value = value_for("--netplay-host")?;
+                    let port = value
+                        .parse()
+                        .with_context(|| format!("{value:?} is not a port number"))?;
+                    result.netplay = Some(NetplayMode::Host(port));
+                }
+                "--netplay-join" => {
+                    result.netplay = Some(NetplayMode::Join(value_for("--netplay-join")?))
+                }
+                "--game-config-dir" => {
+                    result.game_config_dir = Some(value_for("--game-config-dir")?)
+                }
+                "--debug-port" => {
+                    let value = value_for("--debug-port")?;
+                    result.debug_port = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("{value:?} is not a port number"))?,
+                    );
+                }
+                x if x.starts_with("--") => return Err(anyhow!("Unknown option: {x}")),
+                _ => result.rom_paths.extend(find_roms(argument)?),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// If `path` is a directory, every `.nes` file in it (in order, and not
+/// looking any deeper). Otherwise, just `path`.
+fn find_roms(path: String) -> anyhow::Result<Vec<String>> {
+    if !std::path::Path::new(&path).is_dir() {
+        return Ok(vec![path]);
+    }
+    let mut result = vec![];
+    for entry in std::fs::read_dir(&path).with_context(|| format!("Could not look in {path}"))? {
+        let entry_path = entry?.path();
+        let is_rom = entry_path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("nes"));
+        if is_rom && entry_path.is_file() {
+            result.push(entry_path.to_string_lossy().into_owned());
+        }
+    }
+    if result.is_empty() {
+        return Err(anyhow!("There are no .nes files in {path}"));
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// A CPU address in hex, with or without a `$` or `0x` in front.
+fn parse_address(value: &str) -> anyhow::Result<u16> {
+    let digits = value
+        .strip_prefix('$')
+        .or_else(|| value.strip_prefix("0x"))
+        .unwrap_or(value);
+    u16::from_str_radix(digits, 16).with_context(|| format!("{value:?} is not an address"))
+}
+
+/// A whole number from 0 to 100.
+fn parse_percentage(value: &str) -> anyhow::Result<u8> {
+    value
+        .parse()
+        .ok()
+        .filter(|x| *x <= 100)
+        .ok_or_else(|| anyhow!("{value:?} is not a percentage (0-100)"))
+}
+
+fn parse_ram_pattern(value: &str) -> anyhow::Result<RamPattern> {
+    Ok(match value {
+        "zeroes" => RamPattern::Zeroes,
+        "ones" => RamPattern::Ones,
+        "stripes" => RamPattern::Stripes,
+        "random" => {
+            let seed = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|x| x.as_nanos() as u64)
+                .unwrap_or_default();
+            info!("RAM seed: {seed} (use --ram random:{seed} to get this again)");
+            RamPattern::Random(seed)
+        }
+        x => match x.strip_prefix("random:") {
+            Some(seed) => RamPattern::Random(
+                seed.parse()
+                    .with_context(|| format!("{seed:?} is not a number"))?,
+            ),
+            None => {
+                return Err(anyhow!(
+                    "Unknown RAM pattern: {x} (try zeroes, ones, stripes, or random)"
+                ))
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &str) -> anyhow::Result<Options> {
+        Options::parse(arguments.split_whitespace().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn options_that_need_each_other() {
+        assert!(parse("--dump out.png game.nes").is_err());
+        assert!(parse("--frames 10 --dump out.png game.nes").is_ok());
+        assert!(parse("--netplay-host 1234 --zapper game.nes").is_err());
+        assert!(parse("--patch fix.ips a.nes b.nes").is_err());
+    }
+    #[test]
+    fn game_configs_get_checked_too() {
+        let mut options = parse("--netplay-host 1234 game.nes").unwrap();
+        assert!(options.apply_game_config("--zapper").is_err());
+        let mut options = parse("game.nes").unwrap();
+        assert!(options.apply_game_config("--ascii").is_err());
+        options
+            .apply_game_config("--mirroring vertical # for this one")
+            .unwrap();
+        assert_eq!(options.mirroring, Some(MirroringType::Vertical));
+        assert!(options.apply_game_config("other.nes").is_err());
+    }
+    #[test]
+    fn load_time_options_stay_on_the_command_line() {
+        for option in LOAD_TIME_OPTIONS {
+            let mut options = parse("game.nes").unwrap();
+            let error = options
+                .apply_game_config(&format!("{option} 1"))
+                .unwrap_err();
+            assert!(error.to_string().contains(option), "{error}");
+        }
+    }
+}
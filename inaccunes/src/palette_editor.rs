@@ -0,0 +1,54 @@
+//! Try out different palettes without touching the ROM. Turn it on, pick a
+//! palette entry, and flip through the NES's colors for it. Lots of games
+//! write their whole palette every frame, so the colors we pick stick
+//! around no matter what the game writes (see
+//! `System::set_palette_override`). Turning it off lets the game have its
+//! palette back, as soon as it next writes it.
+
+use inaccunes::system::System;
+
+/// How many entries there are in CRAM: the universal background color,
+/// then four palettes for the background and four for sprites.
+const PALETTE_ENTRIES: usize = 32;
+/// How many colors the NES has.
+const COLOR_COUNT: u8 = 64;
+
+#[derive(Debug, Default)]
+pub struct PaletteEditor {
+    pub is_on: bool,
+    /// Which entry the keys change.
+    target: usize,
+}
+
+impl PaletteEditor {
+    pub fn toggle(&mut self, system: &mut System) {
+        self.is_on = !self.is_on;
+        for index in 0..PALETTE_ENTRIES {
+            system.set_palette_override(index, None);
+        }
+    }
+    /// Pick the entry `offset` away from the current one.
+    pub fn move_target(&mut self, offset: isize) {
+        self.target = self.target.wrapping_add_signed(offset) % PALETTE_ENTRIES;
+    }
+    /// Change the current entry's color by `offset`, starting from whatever
+    /// is there now.
+    pub fn change_color(&self, system: &mut System, offset: i8) {
+        let color = system.get_devices().get_ppu().get_cram()[self.target];
+        let color = color.wrapping_add_signed(offset) % COLOR_COUNT;
+        system.set_palette_override(self.target, Some(color));
+    }
+    /// What to show over the picture, so you know what you're changing.
+    pub fn describe(&self, system: &System) -> String {
+        let color = system.get_devices().get_ppu().get_cram()[self.target];
+        let which = match self.target {
+            0 => "universal background".to_string(),
+            x if x < 16 => format!("background palette {} color {}", x / 4, x % 4),
+            x => format!("sprite palette {} color {}", x / 4 - 4, x % 4),
+        };
+        format!(
+            "PALETTE ${:04X}: ${color:02X}\n{which}\nPgUp/PgDn: pick, -/=: change",
+            0x3F00 + self.target
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+
+/// Which kind of TV the console was built for. This changes how many
+/// scanlines there are per frame, how fast the CPU runs compared to the PPU,
+/// and (slightly) how colors come out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    /// 2C02 PPU, 262 scanlines, 60Hz. Also what the 2C03 palette is for.
+    #[default]
+    Ntsc,
+    /// 2C07 PPU, 312 scanlines, 50Hz.
+    Pal,
+}
+
+/// Every scanline is this many PPU dots long, in every region.
+pub const DOTS_PER_SCANLINE: usize = 341;
+
+impl Region {
+    /// Guess the region, first from the iNES header's TV system bit, then
+    /// from the "(E)"/"(Europe)" style tags in the name of the ROM file.
+    /// NTSC if we can't tell.
+    pub fn detect(rom_path: &str, cartridge: &Cartridge) -> Region {
+        if cartridge.header_says_pal {
+            return Region::Pal;
+        }
+        let file_name = Path::new(rom_path)
+            .file_name()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if ["(e)", "(europe)", "(pal)"]
+            .iter()
+            .any(|tag| file_name.contains(tag))
+        {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+    /// How long one CPU cycle is, in master clock ticks. (The master clock
+    /// is the crystal that everything else divides down from.)
+    pub fn master_clocks_per_cpu_cycle(self) -> usize {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+        }
+    }
+    /// How long one PPU dot is, in master clock ticks.
+    pub fn master_clocks_per_dot(self) -> usize {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal => 5,
+        }
+    }
+    /// How many frames the console puts out every second. (Not quite 60 or
+    /// 50, because a frame isn't quite a 60th or 50th of a second long.)
+    pub fn frames_per_second(self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal => 50.0070,
+        }
+    }
+    /// Number of scanlines of vertical blanking, between the post-render
+    /// scanline and the pre-render scanline.
+    pub fn vblank_scanlines(self) -> usize {
+        match self {
+            Region::Ntsc => 20,
+            Region::Pal => 70,
+        }
+    }
+    /// PAL PPUs have the red and green emphasis bits swapped. Turn PPUMASK's
+    /// emphasis bits into the order the palette file is in.
+    pub fn fix_emphasis(self, emphasis: usize) -> usize {
+        match self {
+            Region::Ntsc => emphasis,
+            Region::Pal => (emphasis & 0b100) | ((emphasis & 0b10) >> 1) | ((emphasis & 0b1) << 1),
+        }
+    }
+}
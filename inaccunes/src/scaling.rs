@@ -0,0 +1,110 @@
+//! How the TV picture gets stretched to fill the window.
+//!
+//! Nearest neighbor is perfectly sharp, but unless the window is an exact
+//! multiple of the picture, some pixels come out wider than others.
+//! Bilinear fixes that by blurring everything. Sharp bilinear is the happy
+//! medium: blow the picture up by a whole number with nearest neighbor
+//! first, then bilinear the rest of the way, so only the edges between
+//! pixels get smoothed.
+
+use log::*;
+use sdl2::{
+    pixels::PixelFormatEnum,
+    rect::Rect,
+    render::{Canvas, Texture, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalingMode {
+    #[default]
+    Nearest,
+    Bilinear,
+    SharpBilinear,
+}
+
+impl ScalingMode {
+    /// The one after this one, for flipping through them with a key.
+    pub fn next(self) -> ScalingMode {
+        match self {
+            ScalingMode::Nearest => ScalingMode::Bilinear,
+            ScalingMode::Bilinear => ScalingMode::SharpBilinear,
+            ScalingMode::SharpBilinear => ScalingMode::Nearest,
+        }
+    }
+}
+
+pub struct Scaler {
+    pub mode: ScalingMode,
+    texture_creator: TextureCreator<WindowContext>,
+    /// The picture, blown up by a whole number with nearest neighbor, ready
+    /// to be smoothly scaled the rest of the way. It gets made again
+    /// whenever it needs to be a different size.
+    prescaled: Option<Texture>,
+}
+
+impl Scaler {
+    pub fn new(canvas: &Canvas<Window>) -> Scaler {
+        Scaler {
+            mode: ScalingMode::default(),
+            texture_creator: canvas.texture_creator(),
+            prescaled: None,
+        }
+    }
+    /// Draw `area` of `texture` over the whole of `canvas`.
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, texture: &Texture, area: Rect) {
+        let (output_width, output_height) = canvas.output_size().unwrap_or((1, 1));
+        let (x_scale, y_scale) = match self.mode {
+            ScalingMode::Nearest => {
+                canvas
+                    .copy(texture, area, None)
+                    .expect("could not copy texture to window texture");
+                return;
+            }
+            ScalingMode::Bilinear => (1, 1),
+            // Output sizes are in real pixels, so this is right on high-DPI
+            // screens too.
+            ScalingMode::SharpBilinear => (
+                (output_width / area.width()).max(1),
+                (output_height / area.height()).max(1),
+            ),
+        };
+        let prescaled = self.get_prescaled(area.width() * x_scale, area.height() * y_scale);
+        let result = canvas.with_texture_canvas(prescaled, |prescaled_canvas| {
+            prescaled_canvas
+                .copy(texture, area, None)
+                .expect("could not copy texture to prescaled texture");
+        });
+        match result {
+            Ok(()) => canvas
+                .copy(prescaled, None, None)
+                .expect("could not copy prescaled texture to window texture"),
+            Err(x) => {
+                warn!("Can't do {:?} here ({x}), back to nearest", self.mode);
+                self.mode = ScalingMode::Nearest;
+                self.draw(canvas, texture, area);
+            }
+        }
+    }
+    /// `prescaled`, made `width` by `height` if it isn't already.
+    fn get_prescaled(&mut self, width: u32, height: u32) -> &mut Texture {
+        if let Some(texture) = self.prescaled.as_ref() {
+            let query = texture.query();
+            if (query.width, query.height) != (width, height) {
+                // With `unsafe_textures`, nobody frees these but us.
+                unsafe { self.prescaled.take().unwrap().destroy() };
+            }
+        }
+        self.prescaled.get_or_insert_with(|| {
+            // Scale quality sticks to a texture when it's made, so this is
+            // the only one that gets to be smooth.
+            sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "linear");
+            let texture = self
+                .texture_creator
+                .create_texture_target(PixelFormatEnum::ARGB8888, width, height)
+                .expect("Could not create a prescaled texture");
+            sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+            texture
+        })
+    }
+}
@@ -0,0 +1,42 @@
+//! Dark lines between the rows of the picture, like the gaps between a CRT's
+//! scanlines. They need at least two window pixels per NES row to fit in
+//! between, so they only show up once the window's twice the size of the
+//! picture or more, and look best at a whole-number scale (see the 1-4
+//! keys).
+
+use sdl2::{pixels::Color, rect::Rect, render::BlendMode, render::WindowCanvas};
+
+/// How dark the lines are (out of 100) if nobody said.
+pub const DEFAULT_INTENSITY: u8 = 50;
+
+#[derive(Debug)]
+pub struct Scanlines {
+    pub is_on: bool,
+    /// How dark the lines are: 0 is not at all, 100 is black.
+    pub intensity: u8,
+}
+
+impl Scanlines {
+    /// Darken the bottom half of each of `rows` picture rows, over the whole
+    /// of `canvas`.
+    pub fn draw(&self, canvas: &mut WindowCanvas, rows: u32) {
+        let (output_width, output_height) = canvas.output_size().unwrap_or((1, 1));
+        let thickness = output_height / rows / 2;
+        if !self.is_on || thickness == 0 {
+            return;
+        }
+        let alpha = (self.intensity.min(100) as u32 * 255 / 100) as u8;
+        let lines: Vec<Rect> = (1..=rows)
+            .map(|row| {
+                let bottom = (row * output_height / rows) as i32;
+                Rect::new(0, bottom - thickness as i32, output_width, thickness)
+            })
+            .collect();
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, alpha));
+        canvas
+            .fill_rects(&lines)
+            .expect("could not draw the scanlines");
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
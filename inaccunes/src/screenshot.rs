@@ -0,0 +1,29 @@
+use std::{fs::File, io::BufWriter};
+
+use anyhow::Context;
+use log::*;
+
+/// Write a `width`x`height` buffer of `0x00RRGGBB` pixels out as an RGB PNG.
+pub fn save_png(path: &str, width: usize, height: usize, pixels: &[u32]) -> anyhow::Result<()> {
+    debug_assert_eq!(pixels.len(), width * height);
+    let file = File::create(path).with_context(|| format!("Unable to create {path:?}"))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .context("Unable to write PNG header")?;
+    // Throw away the top (unused) byte of every pixel.
+    let rgb_data: Vec<u8> = pixels
+        .iter()
+        .flat_map(|pixel| {
+            let [_, r, g, b] = pixel.to_be_bytes();
+            [r, g, b]
+        })
+        .collect();
+    writer
+        .write_image_data(&rgb_data)
+        .context("Unable to write PNG image data")?;
+    info!("Wrote a {width}x{height} PNG to {path:?}");
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Turbo that stays on by itself. Press the key once and player 1's turbo A
+//! (or B) keeps going until it's pressed again, so nobody has to hold down
+//! X through a whole shmup. Held turbo still works the same while it's
+//! off.
+
+use anyhow::anyhow;
+use inaccunes::system::Controller;
+use sdl2::keyboard::Keycode;
+
+/// Which keys switch sticky turbo on and off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StickyTurboKeys {
+    pub a: Keycode,
+    pub b: Keycode,
+}
+
+impl Default for StickyTurboKeys {
+    /// Right above the held turbo keys, X and Z.
+    fn default() -> StickyTurboKeys {
+        StickyTurboKeys {
+            a: Keycode::S,
+            b: Keycode::A,
+        }
+    }
+}
+
+impl StickyTurboKeys {
+    /// Two key names (as SDL calls them), with a comma in between: the one
+    /// for turbo A, then the one for turbo B.
+    pub fn parse(value: &str) -> anyhow::Result<StickyTurboKeys> {
+        let parse_key = |name: &str| {
+            Keycode::from_name(name.trim()).ok_or_else(|| anyhow!("{name:?} is not a key"))
+        };
+        match value.split(',').collect::<Vec<_>>()[..] {
+            [a, b] => Ok(StickyTurboKeys {
+                a: parse_key(a)?,
+                b: parse_key(b)?,
+            }),
+            _ => Err(anyhow!("--sticky-turbo-keys needs two keys, like S,A")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurboButton {
+    A,
+    B,
+}
+
+#[derive(Debug, Default)]
+pub struct StickyTurbo {
+    pub is_a_on: bool,
+    pub is_b_on: bool,
+}
+
+impl StickyTurbo {
+    /// Switch sticky turbo for `button` on or off. Switching it off lets go
+    /// of turbo on `controller` right away, even if someone's also holding
+    /// down the held turbo key.
+    pub fn toggle(&mut self, button: TurboButton, controller: &mut Controller) {
+        let (is_on, turbo) = match button {
+            TurboButton::A => (&mut self.is_a_on, &mut controller.turbo_a),
+            TurboButton::B => (&mut self.is_b_on, &mut controller.turbo_b),
+        };
+        *is_on = !*is_on;
+        *turbo = *is_on;
+    }
+    /// Hold down turbo on `controller` for every button that's stuck on, on
+    /// top of whatever's holding it already. Call before every frame, since
+    /// letting go of the held turbo key lets go of turbo.
+    pub fn apply(&self, controller: &mut Controller) {
+        controller.turbo_a |= self.is_a_on;
+        controller.turbo_b |= self.is_b_on;
+    }
+}
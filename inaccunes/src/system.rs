@@ -1,14 +1,31 @@
-use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    io::Write,
+};
 
 use super::*;
 
+mod apu;
 mod ppu;
-use inaccu6502::{Cpu, Memory};
+use apu::*;
+use inaccu6502::{Cpu, Instruction, Memory};
 use ppu::*;
+pub use ppu::{PpuMemory, SpriteInfo};
 
 const TILE_BYTES: usize = 16;
 const MAX_SPRITES_PER_SCANLINE: usize = 8;
+/// How many CPU cycles after reset the PPU ignores writes to most of its
+/// registers.
+const PPU_WARM_UP_CPU_CYCLES: usize = 29658;
+/// How many cycles OAM DMA steals from the CPU. (Plus one more if it starts
+/// on an odd cycle.)
+const OAM_DMA_CYCLES: u32 = 513;
 const BACKGROUND_X_TILE_COUNT: usize = 32;
+/// The scanline right after the picture, where the PPU sits around doing
+/// nothing.
+const POST_RENDER_SCANLINE: u16 = 240;
+/// The scanline where vertical blanking starts.
+const FIRST_VBLANK_SCANLINE: u16 = 241;
 
 const BUTTON_A: u8 = /*     */ 0b0000_0001;
 const BUTTON_B: u8 = /*     */ 0b0000_0010;
@@ -18,8 +35,26 @@ const BUTTON_UP: u8 = /*    */ 0b0001_0000;
 const BUTTON_DOWN: u8 = /*  */ 0b0010_0000;
 const BUTTON_LEFT: u8 = /*  */ 0b0100_0000;
 const BUTTON_RIGHT: u8 = /* */ 0b1000_0000;
+/// Where a Famicom's microphone shows up when you read `$4016`.
+const MICROPHONE_BIT: u8 = 0b0000_0100;
+/// Set when the Zapper *doesn't* see light. (Yes, backwards.)
+const ZAPPER_NO_LIGHT_BIT: u8 = 0b0000_1000;
+const ZAPPER_TRIGGER_BIT: u8 = 0b0001_0000;
+/// How many scanlines the Zapper's photodiode keeps saying "light!" after
+/// the beam goes past the spot it's aimed at.
+const ZAPPER_LIGHT_SCANLINES: u16 = 20;
+/// How bright (0-255 luma) a pixel has to be for the Zapper to notice it.
+const ZAPPER_LIGHT_THRESHOLD: u32 = 0xC0;
+/// What a Four Score sends after both controllers' buttons, on `$4016` and
+/// `$4017` respectively, so games can tell it's plugged in. These go out
+/// bit 0 first, like the buttons do, so the 1 comes on read 20 of `$4016`
+/// and read 19 of `$4017`.
+const FOUR_SCORE_SIGNATURES: [u8; 2] = [0b0000_1000, 0b0000_0100];
 
-fn get_palette_color(grayscale: bool, emphasis: usize, color_index: usize) -> u32 {
+/// Turn a palette entry (`$00-$3F`) into a `0x00RRGGBB` color, the way the
+/// TV would see it with the given PPUMASK grayscale and emphasis bits.
+/// `emphasis` is in palette file order; see `Region::fix_emphasis`.
+pub fn get_palette_color(grayscale: bool, emphasis: usize, color_index: usize) -> u32 {
     const PALETTE_2C03: &[u8; 1536] = include_bytes!("2c03.pal");
     let color_index = if grayscale {
         color_index & 0x30
@@ -31,6 +66,109 @@ fn get_palette_color(grayscale: bool, emphasis: usize, color_index: usize) -> u3
     u32::from_be_bytes([0, color_bytes[0], color_bytes[1], color_bytes[2]])
 }
 
+/// Every color `get_palette_color` can come up with, indexed by
+/// `(emphasis << 6) | color_index`. Grayscale is just a mask on the color
+/// index (`$30` instead of `$3F`), so it doesn't need a table of its own.
+fn build_palette_table() -> [u32; 512] {
+    std::array::from_fn(|index| get_palette_color(false, index >> 6, index & 0x3F))
+}
+
+/// What's in work RAM at power-on. Real hardware comes up with something
+/// different every time (and different on every console), and a few games
+/// accidentally depend on it, so it's nice to be able to pick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamPattern {
+    /// All `$00`. Not realistic, but nice and predictable.
+    #[default]
+    Zeroes,
+    /// All `$FF`.
+    Ones,
+    /// Four bytes of `$00`, then four of `$FF`, over and over. A lot of
+    /// consoles come up looking something like this.
+    Stripes,
+    /// Garbage, but the same garbage every time for the same seed.
+    Random(u64),
+}
+
+impl RamPattern {
+    fn fill(self, ram: &mut [u8]) {
+        match self {
+            RamPattern::Zeroes => ram.fill(0x00),
+            RamPattern::Ones => ram.fill(0xFF),
+            RamPattern::Stripes => {
+                for (address, byte) in ram.iter_mut().enumerate() {
+                    *byte = if address & 0b100 == 0 { 0x00 } else { 0xFF };
+                }
+            }
+            RamPattern::Random(seed) => {
+                // xorshift64*, which is plenty random for this. (Zero is the
+                // one seed it can't handle, so nudge that.)
+                let mut state = seed | 1;
+                for byte in ram.iter_mut() {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    *byte = (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Something to call once per visible scanline. See
+/// `System::set_scanline_callback`.
+pub type ScanlineCallback = Box<dyn FnMut(u16) + Send>;
+
+/// View a framebuffer from `System::render_into` as raw bytes, for handing to
+/// a graphics API.
+///
+/// The bytes are in *native* endianness. That's exactly what SDL's
+/// `ARGB8888` means: it's a "packed" format, so it describes the bits of a
+/// native `u32` (`0xAARRGGBB`), not the order of the bytes in memory. If you
+/// need a byte-order format like `RGB24`, you'll have to convert.
+pub fn framebuffer_as_bytes(pixels: &[u32]) -> &[u8] {
+    bytemuck::cast_slice(pixels)
+}
+
+/// The usual ramp for `render_ascii`, from darkest to brightest.
+pub const ASCII_RAMP: &str = " .:-=+*#%@";
+/// How big `render_ascii`'s picture can get, in characters. Big enough to
+/// recognize things, small enough for a terminal.
+pub const ASCII_MAX_COLUMNS: usize = 80;
+pub const ASCII_MAX_ROWS: usize = 45;
+
+/// Draw a framebuffer from `System::render_into` as `rows` lines of
+/// `columns` characters each, for when there's no screen to look at. Each
+/// character is the average brightness of its patch of pixels, picked from
+/// `ramp`, which goes from darkest to brightest (see `ASCII_RAMP`).
+/// `columns` and `rows` get clamped to `ASCII_MAX_COLUMNS` and
+/// `ASCII_MAX_ROWS`.
+pub fn render_ascii(pixels: &[u32], columns: usize, rows: usize, ramp: &str) -> String {
+    assert_eq!(pixels.len(), NES_PIXEL_COUNT, "Wrong size framebuffer");
+    let ramp: Vec<char> = ramp.chars().collect();
+    assert!(!ramp.is_empty(), "The ramp needs at least one character");
+    let columns = columns.clamp(1, ASCII_MAX_COLUMNS);
+    let rows = rows.clamp(1, ASCII_MAX_ROWS);
+    let mut result = String::with_capacity((columns + 1) * rows);
+    for row in 0..rows {
+        let (top, bottom) = (row * NES_HEIGHT / rows, (row + 1) * NES_HEIGHT / rows);
+        for column in 0..columns {
+            let (left, right) = (
+                column * NES_WIDTH / columns,
+                (column + 1) * NES_WIDTH / columns,
+            );
+            let total: u32 = (top..bottom)
+                .flat_map(|y| pixels[y * NES_WIDTH + left..y * NES_WIDTH + right].iter())
+                .map(|pixel| get_luma(*pixel))
+                .sum();
+            let luma = total as usize / ((bottom - top) * (right - left));
+            result.push(ramp[luma * ramp.len() / 256]);
+        }
+        result.push('\n');
+    }
+    result
+}
+
 #[derive(Default)]
 pub struct Controller {
     pub button_a: bool,
@@ -41,6 +179,22 @@ pub struct Controller {
     pub button_down: bool,
     pub button_left: bool,
     pub button_right: bool,
+    /// Only the second controller on a Famicom has a microphone, and it
+    /// isn't part of the shift register at all. Whether anyone's making
+    /// noise into it shows up directly as bit 2 of `$4016`, on every read.
+    pub microphone: bool,
+    /// Turbo A and turbo B, like on an NES Advantage: while held, the button
+    /// goes on and off by itself, once per frame.
+    pub turbo_a: bool,
+    pub turbo_b: bool,
+    /// Whether the turbo buttons count as pressed this frame. This only
+    /// changes between frames (see `advance_frame`), so no matter when the
+    /// game strobes us, or how many times, it sees the same thing all frame.
+    is_turbo_phase_on: bool,
+    /// What `get_buttons` was at the start of the latest frame, and the one
+    /// before it, for `get_pressed_buttons` and `get_released_buttons`.
+    buttons_this_frame: u8,
+    buttons_last_frame: u8,
     latch_state: bool,
     captured_byte: u8,
 }
@@ -63,7 +217,22 @@ impl Debug for Controller {
 }
 
 impl Controller {
-    fn capture_byte(&self) -> u8 {
+    /// Pack every button into a byte, exactly as the controller's shift
+    /// register would latch it, turbo and all.
+    pub fn capture_byte(&self) -> u8 {
+        let mut result = self.get_buttons();
+        if self.turbo_a && self.is_turbo_phase_on {
+            result |= BUTTON_A;
+        }
+        if self.turbo_b && self.is_turbo_phase_on {
+            result |= BUTTON_B;
+        }
+        return result;
+    }
+    /// Pack the buttons that are being held down into a byte, in the same
+    /// order as the shift register. Unlike `capture_byte`, this leaves turbo
+    /// out, so it's always what the last `set_buttons` put in.
+    pub fn get_buttons(&self) -> u8 {
         let mut result = 0;
         if self.button_a {
             result |= BUTTON_A;
@@ -91,6 +260,41 @@ impl Controller {
         }
         return result;
     }
+    /// The opposite of `get_buttons`: set every button at once from a
+    /// packed byte.
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.button_a = buttons & BUTTON_A != 0;
+        self.button_b = buttons & BUTTON_B != 0;
+        self.button_select = buttons & BUTTON_SELECT != 0;
+        self.button_start = buttons & BUTTON_START != 0;
+        self.button_up = buttons & BUTTON_UP != 0;
+        self.button_down = buttons & BUTTON_DOWN != 0;
+        self.button_left = buttons & BUTTON_LEFT != 0;
+        self.button_right = buttons & BUTTON_RIGHT != 0;
+    }
+    /// Which buttons are different now from `previous` (which is packed the
+    /// same way as `get_buttons`).
+    pub fn changed_since(&self, previous: u8) -> u8 {
+        self.get_buttons() ^ previous
+    }
+    /// Which buttons went down between the last two frames, packed the same
+    /// way as `get_buttons`. A button held for a while only shows up here
+    /// on the first frame, which is what menus want.
+    pub fn get_pressed_buttons(&self) -> u8 {
+        (self.buttons_this_frame ^ self.buttons_last_frame) & self.buttons_this_frame
+    }
+    /// Which buttons were let go of between the last two frames.
+    pub fn get_released_buttons(&self) -> u8 {
+        (self.buttons_this_frame ^ self.buttons_last_frame) & self.buttons_last_frame
+    }
+    /// Flip the turbo buttons on or off, and remember what's held for
+    /// `get_pressed_buttons`. Call once per frame, before the game gets a
+    /// chance to read us.
+    fn advance_frame(&mut self) {
+        self.is_turbo_phase_on = !self.is_turbo_phase_on;
+        self.buttons_last_frame = self.buttons_this_frame;
+        self.buttons_this_frame = self.get_buttons();
+    }
     fn set_latch_state(&mut self, state: bool) {
         self.latch_state = state;
         if self.latch_state {
@@ -105,16 +309,202 @@ impl Controller {
         }
         let result = self.captured_byte & 1;
         if !self.latch_state {
-            // If the latch is off, we shift one bit out.
+            // If the latch is off, we shift one bit out. A 1 gets shifted
+            // in at the top every time, so after eight reads the byte is
+            // $FF and every read after that returns 1, just like a real
+            // controller. (Some games count on this to tell whether a
+            // controller is plugged in at all.)
             self.captured_byte = (self.captured_byte >> 1) | 0x80;
         }
         return result;
     }
 }
 
+/// The Four Score adapter, which lets four controllers share two ports. It
+/// has its own shift registers, one per port, 24 bits each: the first
+/// controller's buttons, then the second's (players 1 and 3 on `$4016`,
+/// players 2 and 4 on `$4017`), then a signature byte.
+#[derive(Debug, Default)]
+struct FourScore {
+    latch_state: bool,
+    captured_bits: [u32; 2],
+}
+
+impl FourScore {
+    fn capture(&mut self, controllers: &[Controller; 4]) {
+        for (port, captured_bits) in self.captured_bits.iter_mut().enumerate() {
+            *captured_bits = u32::from_le_bytes([
+                controllers[port].capture_byte(),
+                controllers[port + 2].capture_byte(),
+                FOUR_SCORE_SIGNATURES[port],
+                0,
+            ]);
+        }
+    }
+    fn set_latch_state(&mut self, state: bool, controllers: &[Controller; 4]) {
+        self.latch_state = state;
+        if self.latch_state {
+            self.capture(controllers);
+        }
+    }
+    /// Same idea as `Controller::perform_read`, but 24 bits long.
+    fn perform_read(&mut self, port: usize, controllers: &[Controller; 4]) -> u8 {
+        if self.latch_state {
+            self.capture(controllers);
+        }
+        let result = (self.captured_bits[port] & 1) as u8;
+        if !self.latch_state {
+            self.captured_bits[port] = (self.captured_bits[port] >> 1) | (1 << 23);
+        }
+        result
+    }
+}
+
+/// A light gun, plugged into the second port instead of a controller. It
+/// can't see the whole screen, only the one pixel it's aimed at, and only
+/// right after the beam draws that pixel. Games flash bright targets on a
+/// dark screen and then check `$4017` to find out what you hit.
+#[derive(Debug, Default)]
+pub struct Zapper {
+    /// Which pixel (x, y) the Zapper is pointing at, if it's pointing at the
+    /// screen at all.
+    pub aim: Option<(usize, usize)>,
+    pub is_trigger_pulled: bool,
+    /// How many more scanlines the photodiode will keep seeing light for.
+    light_scanlines_left: u16,
+}
+
+impl Zapper {
+    /// The beam just finished drawing scanline `y`. Did we see it?
+    fn observe_scanline(&mut self, y: usize, scanline: &[u32]) {
+        self.light_scanlines_left = self.light_scanlines_left.saturating_sub(1);
+        let Some((aim_x, aim_y)) = self.aim else {
+            return;
+        };
+        if aim_y == y && scanline.get(aim_x).is_some_and(|pixel| is_bright(*pixel)) {
+            self.light_scanlines_left = ZAPPER_LIGHT_SCANLINES;
+        }
+    }
+    fn perform_read(&self) -> u8 {
+        let mut result = 0;
+        if self.light_scanlines_left == 0 {
+            result |= ZAPPER_NO_LIGHT_BIT;
+        }
+        if self.is_trigger_pulled {
+            result |= ZAPPER_TRIGGER_BIT;
+        }
+        result
+    }
+}
+
+/// How bright a pixel looks, from 0 to 255.
+fn get_luma(pixel: u32) -> u32 {
+    let [_, r, g, b] = pixel.to_be_bytes().map(|x| x as u32);
+    (r * 299 + g * 587 + b * 114) / 1000
+}
+
+fn is_bright(pixel: u32) -> bool {
+    get_luma(pixel) >= ZAPPER_LIGHT_THRESHOLD
+}
+
+/// Where one pixel of the picture came from in the background: which
+/// attribute palette it used, and whether it's on the top or left edge of a
+/// tile or a metatile (the 16x16 squares that share an attribute). Handy for
+/// drawing grids over the picture that actually follow the scrolling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackgroundGridPixel(u8);
+
+impl BackgroundGridPixel {
+    const PALETTE_MASK: u8 = /*        */ 0b00_0011;
+    const TILE_LEFT_EDGE: u8 = /*      */ 0b00_0100;
+    const TILE_TOP_EDGE: u8 = /*       */ 0b00_1000;
+    const METATILE_LEFT_EDGE: u8 = /*  */ 0b01_0000;
+    const METATILE_TOP_EDGE: u8 = /*   */ 0b10_0000;
+    /// `render_address` is LoopyV, as of when the pixel was fetched.
+    fn new(fine_scroll_x: u8, render_address: u16, palette: usize) -> BackgroundGridPixel {
+        let coarse_x = render_address & 0b11111;
+        let coarse_y = (render_address >> 5) & 0b11111;
+        let fine_y = render_address >> 12;
+        let mut result = palette as u8 & Self::PALETTE_MASK;
+        if fine_scroll_x == 0 {
+            result |= Self::TILE_LEFT_EDGE;
+            if coarse_x.is_multiple_of(2) {
+                result |= Self::METATILE_LEFT_EDGE;
+            }
+        }
+        if fine_y == 0 {
+            result |= Self::TILE_TOP_EDGE;
+            if coarse_y.is_multiple_of(2) {
+                result |= Self::METATILE_TOP_EDGE;
+            }
+        }
+        BackgroundGridPixel(result)
+    }
+    /// Which of the four background palettes the attribute table picked.
+    pub fn get_palette(self) -> usize {
+        (self.0 & Self::PALETTE_MASK) as usize
+    }
+    pub fn is_tile_left_edge(self) -> bool {
+        self.0 & Self::TILE_LEFT_EDGE != 0
+    }
+    pub fn is_tile_top_edge(self) -> bool {
+        self.0 & Self::TILE_TOP_EDGE != 0
+    }
+    pub fn is_metatile_left_edge(self) -> bool {
+        self.0 & Self::METATILE_LEFT_EDGE != 0
+    }
+    pub fn is_metatile_top_edge(self) -> bool {
+        self.0 & Self::METATILE_TOP_EDGE != 0
+    }
+}
+
 pub struct System {
     cpu: Cpu,
     devices: Devices,
+    region: Region,
+    /// How many CPU cycles have happened since the last reset, up to
+    /// `PPU_WARM_UP_CPU_CYCLES`. (After that we stop counting.)
+    cpu_cycles_since_reset: usize,
+    /// How many master clock ticks the CPU still "owes" the PPU. Instructions
+    /// can't be split up, so the CPU usually overshoots a little; this is how
+    /// the overshoot gets paid back next time.
+    master_clocks_owed: isize,
+    /// Which scanline and dot the PPU is on, as of the start of the current
+    /// CPU instruction. Scanlines 0-239 are the picture, and vertical
+    /// blanking starts at `FIRST_VBLANK_SCANLINE`.
+    render_position: (u16, u16),
+    /// How many frames have been rendered since power-on.
+    frame_count: u64,
+    /// The first scanline where sprite 0 hit, this frame (or last frame, if
+    /// we're between frames).
+    sprite_0_hit_scanline: Option<u16>,
+    /// Which scanline the CPU started handling an NMI on, same as above.
+    nmi_scanline: Option<u16>,
+    /// Whether to draw every sprite on a scanline, not just the first
+    /// `MAX_SPRITES_PER_SCANLINE`. See `set_sprite_limit_lifted`.
+    is_sprite_limit_lifted: bool,
+    /// How many frames' worth of CPU time to fit into each frame. See
+    /// `set_overclock`.
+    overclock: usize,
+    /// `get_palette_color` for every emphasis and color, so that each pixel
+    /// is just an array index. See `build_palette_table`.
+    palette_table: [u32; 512],
+    /// Where every pixel of the last frame came from in the background. See
+    /// `get_background_grid`.
+    background_grid: Vec<BackgroundGridPixel>,
+    /// If present, where to write a line for every instruction. See
+    /// `set_trace_writer`.
+    trace_writer: Option<Box<dyn Write + Send>>,
+    /// If present, gets called once per visible scanline. See
+    /// `set_scanline_callback`.
+    scanline_callback: Option<ScanlineCallback>,
+    /// Addresses where the CPU should stop before executing an instruction.
+    #[cfg(feature = "debug-server")]
+    breakpoints: Vec<u16>,
+    /// Whether the CPU is stopped, either at one of `breakpoints` or right
+    /// after a watchpoint went off.
+    #[cfg(feature = "debug-server")]
+    breakpoint_hit: bool,
 }
 
 pub struct Devices {
@@ -122,10 +512,59 @@ pub struct Devices {
     /// Picture Processing Unit
     ppu: PPU,
     /// Audio Processing Unit
-    /// TODO: APU and IO registers
-    apu: [u8; 24],
+    apu: Apu,
     cartridge: Cartridge,
-    pub controllers: [Controller; 2],
+    /// Players 3 and 4 only get read if there's a Four Score.
+    pub controllers: [Controller; 4],
+    /// If present, the Four Score adapter that's plugged in.
+    four_score: Option<FourScore>,
+    /// If present, the Zapper that's plugged in instead of the second
+    /// controller.
+    zapper: Option<Zapper>,
+    /// Whether we're a Famicom instead of an NES. (The only difference we
+    /// care about is the microphone.)
+    is_famicom: bool,
+    /// Cheat codes, which get a say in every read from the cartridge.
+    cheats: Cheats,
+    /// A copy of `System::render_position`, so PPU register accesses can
+    /// say when they happened.
+    #[cfg(feature = "ppu-log")]
+    render_position: (u16, u16),
+    /// Addresses that should stop the CPU when they're read or written.
+    #[cfg(feature = "debug-server")]
+    watchpoints: Vec<Watchpoint>,
+    /// The last time one of `watchpoints` went off, if nobody's looked at it
+    /// yet.
+    #[cfg(feature = "debug-server")]
+    watchpoint_hit: Option<WatchpointHit>,
+}
+
+/// Which kinds of memory access a watchpoint cares about.
+#[cfg(feature = "debug-server")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Read *or* write.
+    Access,
+}
+
+#[cfg(feature = "debug-server")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub kind: WatchKind,
+}
+
+/// What happened when a watchpoint went off.
+#[cfg(feature = "debug-server")]
+#[derive(Debug, Clone, Copy)]
+pub struct WatchpointHit {
+    pub watchpoint: Watchpoint,
+    /// Whether it was a write (as opposed to a read).
+    pub was_write: bool,
+    /// The byte that got read or written.
+    pub value: u8,
 }
 
 // 0x2456
@@ -137,48 +576,124 @@ pub struct Devices {
 //    x xxxx xxxx xAAA
 
 impl Memory for Devices {
-    fn read_byte(&mut self, _cpu: &mut Cpu, address: u16) -> u8 {
+    fn read_byte(&mut self, cpu: &mut Cpu, address: u16) -> u8 {
+        let result = self.perform_read(cpu, address);
+        #[cfg(feature = "debug-server")]
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, false, result);
+        }
+        result
+    }
+    fn write_byte(&mut self, cpu: &mut Cpu, address: u16, data: u8) {
+        #[cfg(feature = "debug-server")]
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, true, data);
+        }
+        self.perform_write(cpu, address, data);
+    }
+}
+
+impl Devices {
+    fn perform_read(&mut self, _cpu: &mut Cpu, address: u16) -> u8 {
         if address < 0x2000 {
             self.ram[(address & 0x7FF) as usize]
         } else if address < 0x4000 {
-            self.ppu.perform_register_read(&self.cartridge, address)
+            let data = self.ppu.perform_register_read(&self.cartridge, address);
+            #[cfg(feature = "ppu-log")]
+            self.log_ppu_access("read", address, data);
+            data
         } else if address < 0x4018 {
+            // TODO: once the DMC fetches its own samples, a fetch that lands
+            // on a read of $4016 or $4017 makes the real console read the
+            // port more than once, clocking the shift register an extra time
+            // and dropping a button (games like Super Mario Bros. 3 read
+            // until two reads agree to get around it). Modeling that needs
+            // to know which CPU cycle the fetch is on, so it goes behind an
+            // accuracy option, and never affects reads with the DMC off.
+            // Until then, there's no DMA to conflict with.
             match address {
-                0x4016 => self.controllers[0].perform_read(),
-                0x4017 => self.controllers[1].perform_read(),
-                _ => self.apu[(address - 0x4000) as usize],
+                0x4016 => {
+                    let microphone = self.is_famicom && self.controllers[1].microphone;
+                    let buttons = match self.four_score.as_mut() {
+                        Some(four_score) => four_score.perform_read(0, &self.controllers),
+                        None => self.controllers[0].perform_read(),
+                    };
+                    buttons | if microphone { MICROPHONE_BIT } else { 0 }
+                }
+                0x4017 => match (self.zapper.as_ref(), self.four_score.as_mut()) {
+                    (Some(zapper), _) => zapper.perform_read(),
+                    (None, Some(four_score)) => four_score.perform_read(1, &self.controllers),
+                    (None, None) => self.controllers[1].perform_read(),
+                },
+                0x4014 => 0, // OAM DMA is write-only
+                _ => self.apu.read_register(address),
             }
         } else {
-            // TODO: don't the hack
-            let address = (address as usize) % self.cartridge.prg_data.len();
-            self.cartridge.prg_data[address]
+            let data = self.cartridge.perform_prg_read(address);
+            self.cheats.apply(address, data)
         }
     }
-    fn write_byte(&mut self, cpu: &mut Cpu, address: u16, data: u8) {
+    #[cfg(feature = "ppu-log")]
+    fn log_ppu_access(&self, what: &str, address: u16, data: u8) {
+        let (scanline, dot) = self.render_position;
+        // The registers repeat every 8 bytes, so say which one it really is.
+        trace!(
+            "PPU {what} ${:04X} = ${data:02X} (from ${address:04X}) at scanline {scanline}, dot {dot}",
+            0x2000 | (address & 7),
+        );
+    }
+    fn perform_write(&mut self, cpu: &mut Cpu, address: u16, data: u8) {
         if address < 0x2000 {
             self.ram[(address & 0x7FF) as usize] = data;
         } else if address < 0x4000 {
+            #[cfg(feature = "ppu-log")]
+            self.log_ppu_access("write", address, data);
             self.ppu
                 .perform_register_write(cpu, &mut self.cartridge, address, data)
         } else if address < 0x4018 {
             match address {
                 0x4014 => {
-                    // OAM DMA!!!!
+                    // OAM DMA!!!! The CPU gets no say in the matter while
+                    // this is going on.
+                    cpu.stall(OAM_DMA_CYCLES + cpu.is_on_odd_cycle() as u32);
                     let page_to_read = data;
                     let start_address = u16::from_be_bytes([page_to_read, 0]);
+                    // Nobody DMAs from $4000-$40FF on purpose, but if they
+                    // did, reading $4016 and $4017 would clock the
+                    // controllers, and the game would lose its input. So we
+                    // peek instead, which says 0 for the registers.
+                    let is_io_page = page_to_read == 0x40;
+                    if is_io_page {
+                        warn!(
+                            "OAM DMA from ${start_address:04X}, where the APU and controllers \
+                             are. Copying zeroes for $4000-$4017 instead of reading them \
+                             (the rest of the page comes from the cartridge, as usual)."
+                        );
+                    }
                     for src_address in start_address..=start_address + 255 {
-                        let oam_data = self.read_byte(cpu, src_address);
+                        let oam_data = if is_io_page {
+                            self.peek_byte(src_address)
+                        } else {
+                            self.read_byte(cpu, src_address)
+                        };
                         self.write_byte(cpu, 0x2004, oam_data);
                     }
                 }
                 0x4016 => {
-                    self.controllers[0].set_latch_state(data & 1 != 0);
-                    self.controllers[1].set_latch_state(data & 1 != 0);
-                }
-                0x4017 => {
-                    // warn!("What is this rom doing, writing to 0x4017???")
+                    // Both ports share the strobe, so this latches every
+                    // controller at once. After that, each one only shifts
+                    // when its own port gets read, so reading $4016 never
+                    // clocks player 2 (or vice versa).
+                    for controller in self.controllers.iter_mut() {
+                        controller.set_latch_state(data & 1 != 0);
+                    }
+                    if let Some(four_score) = self.four_score.as_mut() {
+                        four_score.set_latch_state(data & 1 != 0, &self.controllers);
+                    }
                 }
-                _ => self.apu[(address - 0x4000) as usize] = data,
+                // (Writes to $4017 go to the APU's frame counter, not the
+                // second controller.)
+                _ => self.apu.write_register(address, data),
             }
         } else {
             warn!(
@@ -187,12 +702,45 @@ impl Memory for Devices {
             );
         }
     }
+    #[cfg(feature = "debug-server")]
+    fn check_watchpoints(&mut self, address: u16, is_write: bool, value: u8) {
+        let watchpoint = self.watchpoints.iter().find(|watchpoint| {
+            watchpoint.address == address
+                && match watchpoint.kind {
+                    WatchKind::Read => !is_write,
+                    WatchKind::Write => is_write,
+                    WatchKind::Access => true,
+                }
+        });
+        if let Some(watchpoint) = watchpoint {
+            self.watchpoint_hit = Some(WatchpointHit {
+                watchpoint: *watchpoint,
+                was_write: is_write,
+                value,
+            });
+        }
+    }
 }
 
 impl Devices {
+    /// Read a byte the way the CPU would, but without any side effects.
+    /// Registers that do something when you read them read as 0 instead.
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        if address < 0x2000 {
+            self.ram[(address & 0x7FF) as usize]
+        } else if address < 0x4018 {
+            0
+        } else {
+            let data = self.cartridge.perform_prg_read(address);
+            self.cheats.apply(address, data)
+        }
+    }
     pub fn get_ppu(&self) -> &PPU {
         &self.ppu
     }
+    pub fn get_apu(&self) -> &Apu {
+        &self.apu
+    }
     pub fn get_ram(&self) -> &[u8; WORK_RAM_SIZE] {
         &self.ram
     }
@@ -214,37 +762,15 @@ impl Sprite {
         sprite_tiles_are_in_upper_half: bool,
         oam_data: &[u8],
     ) -> Sprite {
-        debug_assert_eq!(oam_data.len(), 4);
-        let y = oam_data[0] as usize + 1;
-        let tile_address = if sprites_are_8x16 {
-            let tile_number = oam_data[1] & 0b1111_1110;
-            let tile_offset = tile_number as u16 * TILE_BYTES as u16;
-            if oam_data[1] & 1 != 0 {
-                0x1000 + tile_offset
-            } else {
-                0x0000 + tile_offset
-            }
-        } else if sprite_tiles_are_in_upper_half {
-            let tile_offset = oam_data[1] as u16 * TILE_BYTES as u16;
-            0x1000 + tile_offset
-        } else {
-            let tile_offset = oam_data[1] as u16 * TILE_BYTES as u16;
-            0x0000 + tile_offset
-        };
-        let attributes = oam_data[2];
-        let palette = (attributes as usize & 0b0000_0011) + 4;
-        let is_behind_background = (attributes & 0b0010_0000) != 0;
-        let flip_horizontal = (attributes & 0b0100_0000) != 0;
-        let flip_vertical = (attributes & 0b1000_0000) != 0;
-        let x = oam_data[3] as usize;
+        let info = SpriteInfo::from_oam_data(oam_data);
         Sprite {
-            x,
-            y,
-            tile_address,
-            palette,
-            is_behind_background,
-            flip_horizontal,
-            flip_vertical,
+            x: info.x,
+            y: info.y,
+            tile_address: info.get_tile_address(sprites_are_8x16, sprite_tiles_are_in_upper_half),
+            palette: info.palette,
+            is_behind_background: info.is_behind_background,
+            flip_horizontal: info.flip_horizontal,
+            flip_vertical: info.flip_vertical,
         }
     }
     fn is_visible_on_scanline(&self, sprites_are_8x16: bool, y: usize) -> bool {
@@ -305,19 +831,265 @@ impl System {
             devices: Devices {
                 ram: [0; 2048],
                 ppu: PPU::new(),
-                apu: [0; 24],
+                apu: Apu::new(),
                 cartridge,
                 // Any array of things that implement Default also implements
                 // Default, so we can Default our Default to Default the
                 // defaults. Nicer than [Controller::new() * n]
                 controllers: Default::default(),
+                four_score: None,
+                zapper: None,
+                is_famicom: false,
+                cheats: Cheats::default(),
+                #[cfg(feature = "ppu-log")]
+                render_position: (0, 0),
+                #[cfg(feature = "debug-server")]
+                watchpoints: vec![],
+                #[cfg(feature = "debug-server")]
+                watchpoint_hit: None,
             },
+            region: Region::default(),
+            cpu_cycles_since_reset: 0,
+            master_clocks_owed: 0,
+            render_position: (0, 0),
+            frame_count: 0,
+            sprite_0_hit_scanline: None,
+            nmi_scanline: None,
+            is_sprite_limit_lifted: false,
+            overclock: 1,
+            palette_table: build_palette_table(),
+            background_grid: vec![BackgroundGridPixel::default(); NES_PIXEL_COUNT],
+            trace_writer: None,
+            scanline_callback: None,
+            #[cfg(feature = "debug-server")]
+            breakpoints: vec![],
+            #[cfg(feature = "debug-server")]
+            breakpoint_hit: false,
         };
         result.reset();
         result
     }
+    /// Like `new`, but if `entry` is present, start running there instead
+    /// of at the reset vector. Some test ROMs want this: nestest runs all
+    /// of its tests without any input if it starts at `$C000`.
+    #[cfg(feature = "override-registers")]
+    pub fn new_with_entry(cartridge: Cartridge, entry: Option<u16>) -> System {
+        let mut result = System::new(cartridge);
+        if let Some(entry) = entry {
+            result.cpu.set_pc(entry);
+        }
+        result
+    }
+    /// Fill work RAM with `pattern`, as though the console just powered on.
+    /// (`new` fills it with zeroes.) Call this right after `new`, unless you
+    /// enjoy chaos.
+    pub fn fill_ram(&mut self, pattern: RamPattern) {
+        pattern.fill(&mut self.devices.ram);
+    }
+    /// Reset just the PPU (see `PPU::reset`), leaving RAM and the CPU alone.
+    /// Handy for seeing what a game does with a PPU it has to set up all
+    /// over again.
+    pub fn reset_ppu(&mut self) {
+        self.devices.ppu.reset();
+        // PPUCTRL is 0 now, so NMIs are off.
+        self.cpu.set_nmi_signal(false);
+    }
+    /// Replace what's in the PPU's nametables, palette, and OAM (see
+    /// `PPU::load_vram`). The next frame gets drawn from it, unless the game
+    /// writes over it first.
+    pub fn load_vram(&mut self, memory: PpuMemory) {
+        self.devices.ppu.load_vram(memory);
+    }
+    /// Write `data` to PPU register `register` (0-7, for $2000-$2007), the
+    /// same as if the CPU had. For setting the PPU up from outside, along
+    /// with `load_vram`. Right after power-on or a reset, the PPU ignores
+    /// some of these until it's warmed up, which takes most of a frame, so
+    /// call `finish_ppu_warm_up` first if the CPU's not going to run.
+    pub fn write_ppu_register(&mut self, register: u16, data: u8) {
+        self.devices.ppu.perform_register_write(
+            &mut self.cpu,
+            &mut self.devices.cartridge,
+            0x2000 | (register & 7),
+            data,
+        );
+    }
+    /// Skip straight to the end of the PPU's warm-up, as if the CPU had
+    /// already been running for `PPU_WARM_UP_CPU_CYCLES`.
+    pub fn finish_ppu_warm_up(&mut self) {
+        self.cpu_cycles_since_reset = PPU_WARM_UP_CPU_CYCLES;
+        self.devices.ppu.finish_warm_up();
+    }
     pub fn reset(&mut self) {
         self.cpu.reset(&mut self.devices);
+        // On the NES (but not the Famicom!) the reset button resets the PPU
+        // too, so it has to warm up all over again.
+        self.devices.ppu.start_warm_up();
+        self.cpu_cycles_since_reset = 0;
+    }
+    /// Run one CPU step. Returns the number of cycles it took.
+    fn step_cpu(&mut self) -> usize {
+        if self.trace_writer.is_some() {
+            self.write_trace_line();
+        }
+        let cycles = self.cpu.step(&mut self.devices) as usize;
+        if self.cpu_cycles_since_reset < PPU_WARM_UP_CPU_CYCLES {
+            self.cpu_cycles_since_reset += cycles;
+            if self.cpu_cycles_since_reset >= PPU_WARM_UP_CPU_CYCLES {
+                self.devices.ppu.finish_warm_up();
+            }
+        }
+        cycles
+    }
+    /// Write the instruction that's about to run to `trace_writer`, the same
+    /// way `nestest.log` does it.
+    fn write_trace_line(&mut self) {
+        let devices = &self.devices;
+        let peek = |address| devices.peek_byte(address);
+        let cpu = &self.cpu;
+        let instruction = Instruction::decode(cpu.get_pc(), peek);
+        let bytes: Vec<String> = instruction
+            .get_bytes()
+            .iter()
+            .map(|x| format!("{x:02X}"))
+            .collect();
+        let (scanline, dot) = self.render_position;
+        let line = format!(
+            "{:04X}  {:8}  {:32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{scanline:3},{dot:3} CYC:{}",
+            cpu.get_pc(),
+            bytes.join(" "),
+            instruction.show_with_operands(cpu, peek),
+            cpu.get_a(),
+            cpu.get_x(),
+            cpu.get_y(),
+            cpu.get_p(),
+            cpu.get_s(),
+            cpu.get_cycle_count(),
+        );
+        let trace_writer = self.trace_writer.as_mut().unwrap();
+        if let Err(x) = writeln!(trace_writer, "{line}") {
+            error!("Couldn't write to the trace, so we're not tracing anymore: {x}");
+            self.trace_writer = None;
+        }
+    }
+    /// Run the CPU for as long as it takes the PPU to output `dots` dots,
+    /// starting from the beginning of `first_scanline`.
+    fn run_cpu_for_dots(&mut self, first_scanline: u16, dots: usize) {
+        self.master_clocks_owed += (dots * self.region.master_clocks_per_dot()) as isize;
+        let mut elapsed_clocks = 0;
+        while self.master_clocks_owed > 0 {
+            let elapsed_dots = (elapsed_clocks / self.region.master_clocks_per_dot()).min(dots - 1);
+            self.render_position = (
+                first_scanline + (elapsed_dots / DOTS_PER_SCANLINE) as u16,
+                (elapsed_dots % DOTS_PER_SCANLINE) as u16,
+            );
+            #[cfg(feature = "ppu-log")]
+            {
+                self.devices.render_position = self.render_position;
+            }
+            #[cfg(feature = "debug-server")]
+            if self.breakpoint_hit || self.breakpoints.contains(&self.cpu.get_pc()) {
+                // Freeze the CPU (but not the PPU) until the debugger says
+                // otherwise.
+                self.breakpoint_hit = true;
+                self.master_clocks_owed = 0;
+                return;
+            }
+            if self.cpu.is_nmi_pending() {
+                self.nmi_scanline.get_or_insert(self.render_position.0);
+            }
+            self.devices.ppu.was_status_read = false;
+            let clocks = self.step_cpu() * self.region.master_clocks_per_cpu_cycle();
+            self.master_clocks_owed -= clocks as isize;
+            elapsed_clocks += clocks;
+            #[cfg(feature = "debug-server")]
+            if self.devices.watchpoint_hit.is_some() {
+                // Let the instruction finish, and stop right after it.
+                self.breakpoint_hit = true;
+            }
+        }
+    }
+    /// Start writing a line to `writer` for every instruction the CPU runs,
+    /// in the same format as `nestest.log`, or stop if it's `None`. Returns
+    /// whatever we were writing to before, so you can flush it. (Wrap it in a
+    /// `BufWriter`! There are a *lot* of instructions.)
+    pub fn set_trace_writer(
+        &mut self,
+        writer: Option<Box<dyn Write + Send>>,
+    ) -> Option<Box<dyn Write + Send>> {
+        std::mem::replace(&mut self.trace_writer, writer)
+    }
+    /// Call `callback` with the scanline number (0-239) once per visible
+    /// scanline, or stop if it's `None`. It fires after the scanline's
+    /// pixels have all been drawn, right before the CPU gets that
+    /// scanline's worth of cycles. On a real PPU, that's about dot 256, just
+    /// before the sprite fetches where the MMC3 clocks its scanline counter.
+    /// It doesn't fire during V-blank or on the pre-render scanline.
+    pub fn set_scanline_callback(&mut self, callback: Option<ScanlineCallback>) {
+        self.scanline_callback = callback;
+    }
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
+    /// Whether to be a Famicom, whose second controller has a microphone,
+    /// instead of an NES.
+    pub fn set_famicom(&mut self, is_famicom: bool) {
+        self.devices.is_famicom = is_famicom;
+    }
+    pub fn is_famicom(&self) -> bool {
+        self.devices.is_famicom
+    }
+    /// Whether to draw every sprite on a scanline, instead of only the first
+    /// eight like a real PPU. Less flicker, but some games hide sprites on
+    /// purpose by putting eight others in front of them. This only changes
+    /// the picture: sprite 0 is always one of the first eight anyway, so
+    /// sprite 0 hit works the same either way. (So will sprite overflow,
+    /// once we have it.)
+    pub fn set_sprite_limit_lifted(&mut self, is_lifted: bool) {
+        self.is_sprite_limit_lifted = is_lifted;
+    }
+    pub fn is_sprite_limit_lifted(&self) -> bool {
+        self.is_sprite_limit_lifted
+    }
+    /// Give the CPU `multiplier` times as many cycles per frame as it really
+    /// gets, for games that slow down when there's too much going on. This
+    /// is nothing like real hardware: the extra cycles all go at the end of
+    /// V-blank, with the PPU stopped, as if V-blank went on for that much
+    /// longer. Games that count cycles (or time their raster effects off of
+    /// the NMI) won't like it. 1 is normal speed.
+    pub fn set_overclock(&mut self, multiplier: usize) {
+        self.overclock = multiplier.max(1);
+    }
+    pub fn get_overclock(&self) -> usize {
+        self.overclock
+    }
+    /// Turn on a cheat, from a Game Genie code or a raw code (see
+    /// `cheats`). Bad codes don't change anything.
+    pub fn add_cheat(&mut self, code: &str) -> anyhow::Result<()> {
+        self.devices.cheats.add(Cheat::parse(code)?);
+        Ok(())
+    }
+    /// Turn off every cheat.
+    pub fn clear_cheats(&mut self) {
+        self.devices.cheats.clear();
+    }
+    pub fn get_cheats(&self) -> &[Cheat] {
+        self.devices.cheats.get_cheats()
+    }
+    /// Plug a Four Score into both ports, so that players 3 and 4 get read
+    /// too, or unplug it.
+    pub fn set_four_score_plugged_in(&mut self, is_plugged_in: bool) {
+        self.devices.four_score = is_plugged_in.then(FourScore::default);
+    }
+    /// Plug a Zapper into the second port (replacing the controller), or
+    /// unplug it.
+    pub fn set_zapper_plugged_in(&mut self, is_plugged_in: bool) {
+        self.devices.zapper = is_plugged_in.then(Zapper::default);
+    }
+    pub fn get_zapper_mut(&mut self) -> Option<&mut Zapper> {
+        self.devices.zapper.as_mut()
     }
     fn get_pixel_for_background(
         &mut self,
@@ -361,10 +1133,11 @@ impl System {
     }
     fn get_cursed_pixel_for_background(&mut self) -> (u8, usize) {
         let ppu = &mut self.devices.ppu;
-        let tile_address_to_read = (ppu.current_render_address & 0x0FFF) | 0x2000;
-        let attribute_address_to_read = (ppu.current_render_address & 0x0C00)
-            | ((ppu.current_render_address >> 4) & 0x38)
-            | ((ppu.current_render_address >> 2) & 0x07)
+        let render_address = ppu.scroll.get_current_render_address();
+        let tile_address_to_read = (render_address & 0x0FFF) | 0x2000;
+        let attribute_address_to_read = (render_address & 0x0C00)
+            | ((render_address >> 4) & 0x38)
+            | ((render_address >> 2) & 0x07)
             | 0x23C0;
         let tile_number = ppu.perform_bus_read(&self.devices.cartridge, tile_address_to_read);
         let tile_base_address = if ppu.are_bg_tiles_in_upper_half() {
@@ -375,131 +1148,209 @@ impl System {
         let tile_address = tile_base_address + tile_number as u16 * TILE_BYTES as u16;
         let color = self.devices.cartridge.get_tile(
             tile_address,
-            ppu.fine_scroll_x as usize,
-            (ppu.current_render_address >> 12) as usize,
+            ppu.scroll.get_fine_scroll_x() as usize,
+            (render_address >> 12) as usize,
         );
         let attribute_byte =
             ppu.perform_bus_read(&self.devices.cartridge, attribute_address_to_read as u16);
-        let index_within_attribute_byte =
-            ((ppu.current_render_address >> 1) & 1) | ((ppu.current_render_address >> 5) & 2);
+        let index_within_attribute_byte = ((render_address >> 1) & 1) | ((render_address >> 5) & 2);
         let attribute = (attribute_byte >> (index_within_attribute_byte * 2)) & 0b11;
         // scroll!
-        ppu.fine_scroll_x += 1;
-        if ppu.fine_scroll_x >= 8 {
-            ppu.fine_scroll_x = 0;
-            // we reached the end of the tile, so go to the next tile
-            if ppu.current_render_address & 0b11111 == 0b11111 {
-                // if we were at the right edge of the nametable, go to the next
-                // nametable
-                ppu.current_render_address &= 0b1111111_11100000;
-                ppu.current_render_address ^= 0b0000100_00000000;
+        ppu.scroll.advance_x();
+        (color, attribute as usize)
+    }
+    /// Draw scanline `y` (0-239) into `scanline`, from whatever the PPU's
+    /// state is right now: the background from the current render address,
+    /// then the sprites on top. This is all the PPU, no CPU, so it's the
+    /// same whether it's part of a real frame or `render_ppu_only`.
+    fn draw_scanline(&mut self, y: usize, scanline: &mut [u32]) {
+        let mut sprites_on_scanline = vec![];
+        let sprites_are_8x16 = self.devices.ppu.is_sprite_size_8x16();
+        let sprite_tiles_are_in_upper_half = self.devices.ppu.are_sprite_tiles_in_upper_half();
+        for (sprite_index, sprite_data) in self.devices.ppu.oam.chunks_exact(4).enumerate() {
+            let sprite = Sprite::from_oam_data(
+                sprites_are_8x16,
+                sprite_tiles_are_in_upper_half,
+                sprite_data,
+            );
+            if sprite.is_visible_on_scanline(sprites_are_8x16, y) {
+                if self.is_sprite_limit_lifted
+                    || sprites_on_scanline.len() < MAX_SPRITES_PER_SCANLINE
+                {
+                    sprites_on_scanline.push((sprite_index, sprite));
+                }
+            }
+        }
+        // Grayscale and emphasis can only change between scanlines (the
+        // CPU doesn't run in the middle of one), so look them up once.
+        let emphasis = self.region.fix_emphasis(self.devices.ppu.get_emphasis());
+        let palette_row = emphasis << 6;
+        let color_mask = if self.devices.ppu.is_grayscale() {
+            0x30
+        } else {
+            0x3F
+        };
+        //let mut cur_x_scroll = self.devices.ppu.register_scroll_x as usize;
+        //let mut cur_nametable = self.devices.ppu.which_nametable_is_upper_left();
+        for (x, pixel) in scanline.iter_mut().enumerate() {
+            /*
+            let tile_x = cur_x_scroll / 8;
+            let x_within_tile = cur_x_scroll % 8;
+            let tile_y = cur_y_scroll / 8;
+            let y_within_tile = cur_y_scroll % 8;
+            let (bg_color, bg_palette) = self.get_pixel_for_background(
+                cur_nametable as usize,
+                tile_x,
+                x_within_tile,
+                tile_y,
+                y_within_tile,
+            );
+            */
+            let ppu = &self.devices.ppu;
+            let (fine_scroll_x, render_address) = (
+                ppu.scroll.get_fine_scroll_x(),
+                ppu.scroll.get_current_render_address(),
+            );
+            let (bg_color, bg_palette) = self.get_cursed_pixel_for_background();
+            self.background_grid[y * NES_WIDTH + x] =
+                BackgroundGridPixel::new(fine_scroll_x, render_address, bg_palette);
+            let (sprite_index, (sprite_color, sprite_palette, sprite_is_behind_background)) =
+                sprites_on_scanline
+                    .iter()
+                    .filter_map(|(index, sprite)| {
+                        sprite
+                            .get_pixel_for_xy(&self.devices.cartridge, sprites_are_8x16, x, y)
+                            .map(|x| (*index, x))
+                    })
+                    .next()
+                    .unwrap_or((69, (0, 0, false)));
+            let background_is_blocking_sprite = bg_color != 0 && sprite_is_behind_background;
+            let (color, palette);
+            if sprite_color != 0 && !background_is_blocking_sprite {
+                (color, palette) = (sprite_color, sprite_palette);
             } else {
-                // we were not at the right edge of the nametable, go to the
-                // next tile
-                ppu.current_render_address += 1;
+                (color, palette) = (bg_color, bg_palette);
+            }
+            let color_index = if color == 0 {
+                self.devices.ppu.cram[0] // the "universal background color"
+            } else {
+                self.devices.ppu.cram[palette * 4 + color as usize]
+            };
+            if sprite_index == 0 && bg_color != 0 && sprite_color != 0 {
+                self.devices.ppu.turn_on_sprite_0_hit();
+                self.sprite_0_hit_scanline.get_or_insert(y as u16);
+            }
+            *pixel = self.palette_table[palette_row | (color_index & color_mask) as usize];
+            // 00000000 XXXXXXXX
+            // 00110000 XXXXXXXX
+            // 22222222 XXXXXXXX
+            //
+            // YYYYYYYY ZZZZZZZZ
+            // YYYYYYYY ZZZZZZZZ
+            // YYYYYYYY ZZZZZZZZ
+            /*
+            cur_x_scroll += 1;
+            if cur_x_scroll >= 256 {
+                cur_x_scroll -= 256;
+                cur_nametable ^= 1;
             }
+            */
         }
-        (color, attribute as usize)
     }
+    /// Run one frame and return the picture. See `render_into` for the pixel
+    /// format.
     pub fn render(&mut self) -> [u32; NES_PIXEL_COUNT] {
-        const CPU_STEPS_PER_SCANLINE: usize = 113;
-        const CPU_STEPS_PER_VBLANK: usize = 2273;
         let mut result = [0x0; NES_PIXEL_COUNT];
-        // Pretend to be in V-blank.
-        // vblank flag ON
-        self.devices.ppu.vblank_start(&mut self.cpu);
-        for _ in 0..CPU_STEPS_PER_VBLANK {
-            self.cpu.step(&mut self.devices);
+        self.render_into(&mut result);
+        result
+    }
+    /// Run one frame like `render`, but black out every scanline after
+    /// `up_to_scanline` (0-239), so a screenshot shows exactly how far down
+    /// the beam had gotten by then. Handy for showing where a split lands.
+    /// The whole frame still runs, so afterwards everything's just like after
+    /// `render`.
+    pub fn render_partial(&mut self, up_to_scanline: u16) -> [u32; NES_PIXEL_COUNT] {
+        let mut result = self.render();
+        let first_hidden_scanline = (up_to_scanline as usize + 1).min(NES_HEIGHT);
+        result[first_hidden_scanline * NES_WIDTH..].fill(0);
+        result
+    }
+    /// Run one frame, writing the picture into `out`, which must be exactly
+    /// `NES_PIXEL_COUNT` pixels long. Pixels go left to right, top to bottom,
+    /// `NES_WIDTH` per row, and each one is packed as `0x00RRGGBB`.
+    pub fn render_into(&mut self, out: &mut [u32]) {
+        assert_eq!(out.len(), NES_PIXEL_COUNT, "Wrong size framebuffer");
+        // Games read the controllers during V-blank, so this is the last
+        // chance to do it between frames.
+        for controller in self.devices.controllers.iter_mut() {
+            controller.advance_frame();
+        }
+        self.sprite_0_hit_scanline = None;
+        self.nmi_scanline = None;
+        // A frame goes: the post-render scanline (where we pick up from last
+        // time), V-blank (with the NMI right at the start, so the game's NMI
+        // handler runs now), the pre-render scanline, and then the picture,
+        // one scanline at a time with the CPU running in between.
+        self.devices.ppu.is_on_post_render_scanline = true;
+        self.run_cpu_for_dots(POST_RENDER_SCANLINE, DOTS_PER_SCANLINE);
+        self.devices.ppu.is_on_post_render_scanline = false;
+        // The beam's off, so whatever the Zapper saw has faded by now.
+        if let Some(zapper) = self.devices.zapper.as_mut() {
+            zapper.light_scanlines_left = 0;
+        }
+        // vblank flag ON... unless the last instruction read PPUSTATUS the
+        // dot before V-blank started. Every instruction that can read
+        // PPUSTATUS does it on its last cycle, and we know how far the
+        // instruction ran over into V-blank, so we know which dot the read
+        // was on. (A read that landed *after* V-blank started already got
+        // the flag clear, since we ran the whole instruction early, so we
+        // leave the flag up for the next read to see.)
+        let clocks_per_dot = self.region.master_clocks_per_dot() as isize;
+        let clocks_per_cycle = self.region.master_clocks_per_cpu_cycle() as isize;
+        let status_read_dot =
+            (-self.master_clocks_owed - clocks_per_cycle).div_euclid(clocks_per_dot);
+        let is_vblank_suppressed = self.devices.ppu.was_status_read && status_read_dot == -1;
+        self.devices
+            .ppu
+            .vblank_start(&mut self.cpu, is_vblank_suppressed);
+        let vblank_scanlines = self.region.vblank_scanlines();
+        self.run_cpu_for_dots(FIRST_VBLANK_SCANLINE, vblank_scanlines * DOTS_PER_SCANLINE);
+        // Overclocking: however many more frames it takes, all on the last
+        // line of V-blank. (The picture, both of the scanlines around it,
+        // and V-blank itself.)
+        let last_vblank_scanline = FIRST_VBLANK_SCANLINE + vblank_scanlines as u16 - 1;
+        let scanlines_per_frame = NES_HEIGHT + 2 + vblank_scanlines;
+        for _ in 0..(self.overclock - 1) * scanlines_per_frame {
+            self.run_cpu_for_dots(last_vblank_scanline, DOTS_PER_SCANLINE);
         }
         // vblank flag OFF
         self.devices.ppu.vblank_stop(&mut self.cpu);
-        // BEGIN CURSE!
-        self.devices.ppu.current_render_address &= 0b0000100_00011111;
-        self.devices.ppu.current_render_address |=
-            self.devices.ppu.canon_render_address & 0b1111011_11100000;
-        // END CURSE!
+        self.run_cpu_for_dots(
+            FIRST_VBLANK_SCANLINE + vblank_scanlines as u16,
+            DOTS_PER_SCANLINE,
+        );
+        // Back to the top left, from "t". (On a real PPU, this happens near
+        // the end of the pre-render scanline: X at dot 257, like every
+        // scanline, and then Y.) Any PPUADDR or PPUDATA access during
+        // V-blank left "v" pointing who knows where, so both halves matter.
+        self.devices.ppu.scroll.copy_x();
+        self.devices.ppu.scroll.copy_y();
         //let mut cur_y_scroll = self.devices.ppu.register_scroll_y as usize;
-        for (y, scanline) in result.chunks_mut(NES_WIDTH).enumerate() {
-            let mut sprites_on_scanline = vec![];
-            let sprites_are_8x16 = self.devices.ppu.is_sprite_size_8x16();
-            let sprite_tiles_are_in_upper_half = self.devices.ppu.are_sprite_tiles_in_upper_half();
-            for (sprite_index, sprite_data) in self.devices.ppu.oam.chunks_exact(4).enumerate() {
-                let sprite = Sprite::from_oam_data(
-                    sprites_are_8x16,
-                    sprite_tiles_are_in_upper_half,
-                    sprite_data,
-                );
-                if sprite.is_visible_on_scanline(sprites_are_8x16, y) {
-                    if sprites_on_scanline.len() < MAX_SPRITES_PER_SCANLINE {
-                        sprites_on_scanline.push((sprite_index, sprite));
-                    }
-                }
-            }
-            //let mut cur_x_scroll = self.devices.ppu.register_scroll_x as usize;
-            //let mut cur_nametable = self.devices.ppu.which_nametable_is_upper_left();
-            for (x, pixel) in scanline.iter_mut().enumerate() {
-                /*
-                let tile_x = cur_x_scroll / 8;
-                let x_within_tile = cur_x_scroll % 8;
-                let tile_y = cur_y_scroll / 8;
-                let y_within_tile = cur_y_scroll % 8;
-                let (bg_color, bg_palette) = self.get_pixel_for_background(
-                    cur_nametable as usize,
-                    tile_x,
-                    x_within_tile,
-                    tile_y,
-                    y_within_tile,
-                );
-                */
-                let (bg_color, bg_palette) = self.get_cursed_pixel_for_background();
-                let (sprite_index, (sprite_color, sprite_palette, sprite_is_behind_background)) =
-                    sprites_on_scanline
-                        .iter()
-                        .filter_map(|(index, sprite)| {
-                            sprite
-                                .get_pixel_for_xy(&self.devices.cartridge, sprites_are_8x16, x, y)
-                                .map(|x| (*index, x))
-                        })
-                        .next()
-                        .unwrap_or((69, (0, 0, false)));
-                let background_is_blocking_sprite = bg_color != 0 && sprite_is_behind_background;
-                let (color, palette);
-                if sprite_color != 0 && !background_is_blocking_sprite {
-                    (color, palette) = (sprite_color, sprite_palette);
-                } else {
-                    (color, palette) = (bg_color, bg_palette);
-                }
-                let color_index = if color == 0 {
-                    self.devices.ppu.cram[0] // the "universal background color"
-                } else {
-                    self.devices.ppu.cram[palette * 4 + color as usize]
-                };
-                if sprite_index == 0 && bg_color != 0 && sprite_color != 0 {
-                    self.devices.ppu.turn_on_sprite_0_hit();
-                }
-                *pixel = get_palette_color(
-                    self.devices.ppu.is_grayscale(),
-                    self.devices.ppu.get_emphasis(),
-                    color_index as usize,
-                );
-                // 00000000 XXXXXXXX
-                // 00110000 XXXXXXXX
-                // 22222222 XXXXXXXX
-                //
-                // YYYYYYYY ZZZZZZZZ
-                // YYYYYYYY ZZZZZZZZ
-                // YYYYYYYY ZZZZZZZZ
-                /*
-                cur_x_scroll += 1;
-                if cur_x_scroll >= 256 {
-                    cur_x_scroll -= 256;
-                    cur_nametable ^= 1;
-                }
-                */
+        for (y, scanline) in out.chunks_mut(NES_WIDTH).enumerate() {
+            self.draw_scanline(y, scanline);
+            if let Some(zapper) = self.devices.zapper.as_mut() {
+                zapper.observe_scanline(y, scanline);
             }
-            for _ in 0..CPU_STEPS_PER_SCANLINE {
-                self.cpu.step(&mut self.devices);
+            // Down to the next row, right after the last pixel (dot 256), like
+            // a real PPU. This has to happen before the CPU gets its turn: if
+            // the game writes a whole new address to PPUADDR during H-blank
+            // (the usual way to do a vertical split), the next scanline
+            // starts right there, not one row further down.
+            self.devices.ppu.scroll.increment_y();
+            if let Some(callback) = self.scanline_callback.as_mut() {
+                callback(y as u16);
             }
+            self.run_cpu_for_dots(y as u16, DOTS_PER_SCANLINE);
             /*
             cur_y_scroll += 1;
             if cur_y_scroll >= 240 {
@@ -507,39 +1358,121 @@ impl System {
                 self.devices.ppu.flip_which_nametable_is_upper_left_by_y();
             }
             */
-            // BEGIN CURSE!
-            let ppu = &mut self.devices.ppu;
-            // the part of the curse that is about the Y scroll
-            ppu.current_render_address += 0b0010000_00000000;
-            if ppu.current_render_address >= 0x8000 {
-                ppu.current_render_address &= 0b1111111_1111111;
-                // If the coarse Y scroll is exactly equal to 29...
-                if ppu.current_render_address & (0b11111 << 5) == (29 << 5) {
-                    // set it to 0
-                    ppu.current_render_address &= !(0b11111 << 5);
-                    // and flip to a different nametable
-                    ppu.current_render_address ^= 0b10 << 10;
-                }
-                // Otherwise...
-                else {
-                    // increment the coarse Y scroll by 1
-                    ppu.current_render_address += 0b00001 << 5;
-                    // BUG: the thing that happens if you set scroll Y to an
-                    // illegal value isn't emulated, DON'T DO THAT ANYWAY
-                }
-            }
-            // the part of the curse that is about the X scroll
-            self.devices.ppu.current_render_address &= 0b1111011_11100000;
-            self.devices.ppu.current_render_address |=
-                self.devices.ppu.canon_render_address & 0b0000100_00011111;
-            // END CURSE!
+            // Back to the left edge. A real PPU does this at dot 257, but we
+            // wait until after the CPU's turn, so that a PPUSCROLL write
+            // anywhere in the scanline (say, right after the game sees
+            // sprite 0 hit) moves the very next line over. That's close
+            // enough for status bars.
+            self.devices.ppu.scroll.copy_x();
         }
         // we have to do this again at the end of the frame
-        return result;
+        self.frame_count += 1;
+    }
+    /// Draw a frame from the PPU's state as it is right now, without running
+    /// the CPU at all. It's the same picture `render` would give, if the
+    /// game didn't touch the PPU during the frame. Set the PPU up with
+    /// `load_vram` and `write_ppu_register`, and this says exactly what it
+    /// draws, with no game code in the way. There's no V-blank and no NMI,
+    /// and `get_frame_count` stays the same, but drawing still does what it
+    /// always does: it can set the sprite 0 hit flag (and
+    /// `get_sprite_0_hit_scanline`), it fills in `get_background_grid`, and
+    /// it leaves "v" wherever the last scanline left it.
+    pub fn render_ppu_only(&mut self) -> [u32; NES_PIXEL_COUNT] {
+        let mut result = [0x0; NES_PIXEL_COUNT];
+        self.sprite_0_hit_scanline = None;
+        // The same scrolling `render_into` does around the CPU's turns.
+        self.devices.ppu.scroll.copy_x();
+        self.devices.ppu.scroll.copy_y();
+        for (y, scanline) in result.chunks_mut(NES_WIDTH).enumerate() {
+            self.draw_scanline(y, scanline);
+            self.devices.ppu.scroll.increment_y();
+            self.devices.ppu.scroll.copy_x();
+        }
+        result
+    }
+    /// Where each pixel of the last frame came from in the background, in
+    /// the same order as the pixels from `render_into`.
+    pub fn get_background_grid(&self) -> &[BackgroundGridPixel] {
+        &self.background_grid
+    }
+    /// Where the PPU is (scanline, dot) as of the CPU instruction that's
+    /// running right now. Between frames, that's near the end of the last
+    /// visible scanline.
+    pub fn get_render_position(&self) -> (u16, u16) {
+        self.render_position
+    }
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+    /// The first scanline (0-239) where sprite 0 hit during the last frame,
+    /// if it hit at all.
+    pub fn get_sprite_0_hit_scanline(&self) -> Option<u16> {
+        self.sprite_0_hit_scanline
+    }
+    /// Which scanline the CPU got an NMI on during the last frame, if it got
+    /// one. Usually `FIRST_VBLANK_SCANLINE`, unless NMIs were turned on
+    /// partway through V-blank.
+    pub fn get_nmi_scanline(&self) -> Option<u16> {
+        self.nmi_scanline
     }
     pub fn show_cpu_state(&self) -> String {
         format!("CPU: {:?}", self.cpu)
     }
+    /// A few lines about where the CPU and PPU are at, for pasting into a
+    /// bug report: the CPU, the PPU's registers and scroll, and what's on
+    /// top of the stack.
+    pub fn dump_state_string(&self) -> String {
+        let ppu = &self.devices.ppu;
+        let (scanline, dot) = self.render_position;
+        // From the top of the stack down to (at most) 8 bytes below it.
+        let stack_top = self.cpu.get_s() as u16 + 1;
+        let stack: Vec<String> = (stack_top..(stack_top + 8).min(0x100))
+            .map(|offset| format!("{:02X}", self.devices.peek_byte(0x100 + offset)))
+            .collect();
+        format!(
+            "Frame {frame}, scanline {scanline}, dot {dot}\n\
+             {cpu}\n\
+             PPUCTRL: ${control:02X} (NMI {nmi}, {size} sprites, BG tiles at ${bg:04X}, \
+             sprite tiles at ${sprites:04X})\n\
+             PPUMASK: ${mask:02X}, OAMADDR: ${oam:02X}\n\
+             V-blank flag: {vblank}, sprite 0 hit: {hit}\n\
+             Scroll: v=${v:04X} t=${t:04X} x={fine_x} w={w}\n\
+             Stack (from ${stack_address:04X} up): {stack}",
+            frame = self.frame_count,
+            cpu = self.show_cpu_state(),
+            stack_address = 0x100 + stack_top,
+            control = ppu.get_control(),
+            nmi = if ppu.is_nmi_on() { "on" } else { "off" },
+            size = if ppu.is_sprite_size_8x16() {
+                "8x16"
+            } else {
+                "8x8"
+            },
+            bg = if ppu.are_bg_tiles_in_upper_half() {
+                0x1000
+            } else {
+                0
+            },
+            sprites = if ppu.are_sprite_tiles_in_upper_half() {
+                0x1000
+            } else {
+                0
+            },
+            mask = ppu.get_mask(),
+            oam = ppu.get_oam_address(),
+            vblank = ppu.is_vblank_flag_set(),
+            hit = ppu.is_sprite_0_hit(),
+            v = ppu.get_current_render_address(),
+            t = ppu.get_canon_render_address(),
+            fine_x = ppu.get_fine_scroll_x(),
+            w = if ppu.is_next_write_first() { 0 } else { 1 },
+            stack = if stack.is_empty() {
+                "empty".to_string()
+            } else {
+                stack.join(" ")
+            },
+        )
+    }
     pub fn get_work_memory_byte(&self, address: u16) -> u8 {
         let address = address as usize;
         assert!(address < WORK_RAM_SIZE, "Invalid RAM address {address:04X}");
@@ -551,7 +1484,719 @@ impl System {
     pub fn get_controllers_mut(&mut self) -> &mut [Controller] {
         return &mut self.devices.controllers;
     }
+    /// Set all of one player's buttons at once, from a byte in shift
+    /// register order: A, B, Select, Start, Up, Down, Left, Right, starting
+    /// from bit 0. `player` is 0 to 3 (2 and 3 only get read with a Four
+    /// Score). Handy for scripts and bots.
+    pub fn set_controller_state(&mut self, player: usize, buttons: u8) {
+        assert!(
+            player < self.devices.controllers.len(),
+            "Invalid player {player}"
+        );
+        self.devices.controllers[player].set_buttons(buttons);
+    }
+    /// Which of one player's buttons are held down, packed the same way as
+    /// for `set_controller_state`.
+    pub fn get_controller_state(&self, player: usize) -> u8 {
+        assert!(
+            player < self.devices.controllers.len(),
+            "Invalid player {player}"
+        );
+        self.devices.controllers[player].get_buttons()
+    }
+    /// Which of one player's buttons went down between the last two frames
+    /// (see `Controller::get_pressed_buttons`).
+    pub fn get_pressed_buttons(&self, player: usize) -> u8 {
+        assert!(
+            player < self.devices.controllers.len(),
+            "Invalid player {player}"
+        );
+        self.devices.controllers[player].get_pressed_buttons()
+    }
+    /// Which of one player's buttons were let go of between the last two
+    /// frames.
+    pub fn get_released_buttons(&self, player: usize) -> u8 {
+        assert!(
+            player < self.devices.controllers.len(),
+            "Invalid player {player}"
+        );
+        self.devices.controllers[player].get_released_buttons()
+    }
+    pub fn get_cpu(&self) -> &Cpu {
+        &self.cpu
+    }
     pub fn get_devices(&self) -> &Devices {
         return &self.devices;
     }
+    /// Force CRAM entry `index` (0-31, same as `$3F00 + index`) to be
+    /// `color`, no matter what the game writes to it, or let the game have
+    /// it back if it's `None`. This is for trying out palettes: nothing
+    /// about the ROM changes, and the game's own color shows up again the
+    /// next time it writes one.
+    pub fn set_palette_override(&mut self, index: usize, color: Option<u8>) {
+        let ppu = &mut self.devices.ppu;
+        ppu.cram_overrides[index] = color;
+        if let Some(color) = color {
+            ppu.cram[index] = color;
+        }
+    }
+    /// Write a byte into work RAM. Writes anywhere else are ignored, since
+    /// they'd have side effects. Returns whether the write happened.
+    pub fn poke_byte(&mut self, address: u16, data: u8) -> bool {
+        if address < 0x2000 {
+            self.devices.ram[(address & 0x7FF) as usize] = data;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Things only a debugger should be doing.
+#[cfg(feature = "debug-server")]
+impl System {
+    pub fn get_cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+    /// Run exactly one instruction, even if we're stopped at a breakpoint.
+    /// The PPU doesn't move, but it'll catch up on the next frame.
+    pub fn step_instruction(&mut self) {
+        self.breakpoint_hit = false;
+        let cycles = self.step_cpu();
+        self.master_clocks_owed -= (cycles * self.region.master_clocks_per_cpu_cycle()) as isize;
+    }
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.retain(|x| *x != address);
+    }
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.devices.watchpoints.contains(&watchpoint) {
+            self.devices.watchpoints.push(watchpoint);
+        }
+    }
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.devices.watchpoints.retain(|x| *x != watchpoint);
+    }
+    /// If a watchpoint went off since the last time you asked, which one and
+    /// how.
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.devices.watchpoint_hit.take()
+    }
+    /// Let the CPU go again after it stopped at a breakpoint.
+    pub fn resume(&mut self) {
+        self.breakpoint_hit = false;
+    }
+    pub fn is_at_breakpoint(&self) -> bool {
+        self.breakpoint_hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use inaccu6502::Program;
+
+    /// A 16KiB NROM cartridge with `program` at `$8000`, where reset goes.
+    /// NMI and IRQ go to an RTI at `$BFF0`. Every byte of CHR is the low
+    /// byte of its address, so there's something to draw.
+    pub(super) fn test_cartridge(program: Program) -> Cartridge {
+        test_cartridge_with_nmi(program, 0xBFF0)
+    }
+
+    /// Like `test_cartridge`, but NMI goes to `nmi` instead.
+    fn test_cartridge_with_nmi(program: Program, nmi: u16) -> Cartridge {
+        let program = program.build();
+        let mut rom = b"NES\x1a\x01\x01\x00\x00".to_vec();
+        rom.resize(16 + 0x4000 + 0x2000, 0);
+        let prg = &mut rom[16..16 + 0x4000];
+        prg[..program.len()].copy_from_slice(&program);
+        prg[0x3FF0] = 0x40; // RTI
+        prg[0x3FFA..0x3FFC].copy_from_slice(&nmi.to_le_bytes());
+        prg[0x3FFC..].copy_from_slice(&[0x00, 0x80, 0xF0, 0xBF]);
+        for (index, byte) in rom[16 + 0x4000..].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        Cartridge::from_reader(&rom[..]).unwrap()
+    }
+
+    /// Read from `address` like the CPU would.
+    fn read(system: &mut System, address: u16) -> u8 {
+        system.devices.read_byte(&mut system.cpu, address)
+    }
+
+    /// Write to `address` like the CPU would.
+    fn write(system: &mut System, address: u16, data: u8) {
+        system.devices.write_byte(&mut system.cpu, address, data);
+    }
+
+    /// Latch `controller`, then let go so reads start shifting.
+    fn strobe(controller: &mut Controller) {
+        controller.set_latch_state(true);
+        controller.set_latch_state(false);
+    }
+
+    #[test]
+    fn controller_reads_past_the_eighth_are_1() {
+        let mut controller = Controller::default();
+        controller.set_buttons(BUTTON_A | BUTTON_START | BUTTON_DOWN | BUTTON_RIGHT);
+        strobe(&mut controller);
+        let bits: Vec<u8> = (0..8).map(|_| controller.perform_read()).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 1, 0, 1]);
+        for _ in 0..16 {
+            assert_eq!(controller.perform_read(), 1);
+        }
+    }
+    #[test]
+    fn oam_dma_takes_an_extra_cycle_on_odd_cycles() {
+        let program = Program::new(0x8000).sta_abs(0x4014).sta_abs(0x4014);
+        let mut system = System::new(test_cartridge(program));
+        // STA abs is 4 cycles, and reset leaves us on cycle 0.
+        assert_eq!(system.cpu.get_cycle_count(), 0);
+        assert_eq!(system.cpu.step(&mut system.devices), 4 + 513);
+        assert!(system.cpu.is_on_odd_cycle());
+        assert_eq!(system.cpu.step(&mut system.devices), 4 + 514);
+        assert_eq!(system.cpu.get_cycle_count(), 4 + 513 + 4 + 514);
+    }
+    #[cfg(feature = "override-registers")]
+    #[test]
+    fn new_with_entry_only_overrides_the_reset_vector_when_asked() {
+        let system = System::new_with_entry(test_cartridge(Program::new(0x8000)), Some(0xC000));
+        assert_eq!(system.cpu.get_pc(), 0xC000);
+        let system = System::new_with_entry(test_cartridge(Program::new(0x8000)), None);
+        assert_eq!(system.cpu.get_pc(), 0x8000);
+    }
+    #[test]
+    fn ascii_of_a_blank_frame_is_all_spaces() {
+        let pixels = [0; NES_PIXEL_COUNT];
+        for (columns, rows) in [(80, 30), (0, 0), (1000, 1000), (1, 1)] {
+            let text = render_ascii(&pixels, columns, rows, ASCII_RAMP);
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines.len(), rows.clamp(1, ASCII_MAX_ROWS));
+            for line in lines {
+                assert_eq!(line, " ".repeat(columns.clamp(1, ASCII_MAX_COLUMNS)));
+            }
+        }
+    }
+    #[test]
+    fn oam_dma_works_on_the_post_render_scanline() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        system.finish_ppu_warm_up();
+        system.write_ppu_register(1, 0x18);
+        for i in 0..=255u8 {
+            write(&mut system, 0x0200 + i as u16, i ^ 0x5A);
+        }
+        system.devices.ppu.is_on_post_render_scanline = true;
+        write(&mut system, 0x4014, 0x02);
+        for i in 0..=255u8 {
+            assert_eq!(system.devices.ppu.oam[i as usize], i ^ 0x5A);
+        }
+    }
+    #[test]
+    fn four_score_sends_all_four_controllers_and_then_its_signature() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        system.set_four_score_plugged_in(true);
+        for (player, buttons) in [0b0000_0001, 0b1000_0000, 0b0101_0101, 0b1100_0011]
+            .into_iter()
+            .enumerate()
+        {
+            system.set_controller_state(player, buttons);
+        }
+        write(&mut system, 0x4016, 1);
+        write(&mut system, 0x4016, 0);
+        let mut ports = [0u32; 2];
+        for bit in 0..24 {
+            ports[0] |= ((read(&mut system, 0x4016) & 1) as u32) << bit;
+            ports[1] |= ((read(&mut system, 0x4017) & 1) as u32) << bit;
+        }
+        // Players 1 and 3, and then a 1 on read 20.
+        assert_eq!(ports[0], 0b0000_1000_0101_0101_0000_0001);
+        // Players 2 and 4, and then a 1 on read 19.
+        assert_eq!(ports[1], 0b0000_0100_1100_0011_1000_0000);
+    }
+    #[test]
+    fn oam_dma_copies_a_page_and_leaves_the_controllers_alone() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        for i in 0..=255u8 {
+            write(&mut system, 0x0300 + i as u16, i.wrapping_mul(7));
+        }
+        system.set_controller_state(0, 0b1100_1010);
+        system.set_controller_state(1, 0b0101_0011);
+        write(&mut system, 0x4016, 1);
+        write(&mut system, 0x4016, 0);
+        let mut port_1: Vec<u8> = (0..3).map(|_| read(&mut system, 0x4016) & 1).collect();
+        write(&mut system, 0x4014, 0x03);
+        for i in 0..=255u8 {
+            assert_eq!(system.devices.ppu.oam[i as usize], i.wrapping_mul(7));
+        }
+        // A DMA from the I/O page reads $4016 and $4017 too, but mustn't
+        // clock either of them.
+        write(&mut system, 0x4014, 0x40);
+        port_1.extend((3..8).map(|_| read(&mut system, 0x4016) & 1));
+        let port_2: Vec<u8> = (0..8).map(|_| read(&mut system, 0x4017) & 1).collect();
+        assert_eq!(port_1, [0, 1, 0, 1, 0, 0, 1, 1]);
+        assert_eq!(port_2, [1, 1, 0, 0, 1, 0, 1, 0]);
+    }
+    #[test]
+    fn apu_registers_reach_the_apu() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        // Player 2 is holding B, and the game's already read A.
+        system.set_controller_state(1, 0b0000_0010);
+        write(&mut system, 0x4016, 1);
+        write(&mut system, 0x4016, 0);
+        assert_eq!(read(&mut system, 0x4017) & 1, 0);
+        for (address, data) in [
+            (0x4000, 0b1011_0101),
+            (0x4002, 0x34),
+            (0x4003, 0b1010_1010),
+            (0x4005, 0x88),
+            (0x4008, 0b1000_0011),
+            (0x400E, 0b1000_0110),
+            (0x4010, 0b0100_1111),
+            (0x4013, 0x21),
+            (0x4015, 0b1111_0101),
+            (0x4017, 0b1100_0001),
+        ] {
+            write(&mut system, address, data);
+        }
+        let apu = system.devices.get_apu();
+        assert_eq!(apu.pulses[0].duty, 0b10);
+        assert!(apu.pulses[0].halt_length_counter);
+        assert!(apu.pulses[0].constant_volume);
+        assert_eq!(apu.pulses[0].volume, 0b0101);
+        assert_eq!(apu.pulses[0].timer, 0x234);
+        assert_eq!(apu.pulses[0].length_counter_load, 0b10101);
+        assert_eq!(apu.pulses[1].sweep, 0x88);
+        assert!(apu.triangle.halt_length_counter);
+        assert_eq!(apu.triangle.linear_counter_load, 3);
+        assert!(apu.noise.is_short_mode);
+        assert_eq!(apu.noise.period, 6);
+        assert!(apu.dmc.is_looping);
+        assert_eq!(apu.dmc.rate, 0xF);
+        assert_eq!(apu.dmc.sample_length, 0x21);
+        assert_eq!(apu.enabled_channels, 0b1_0101);
+        assert_eq!(apu.frame_counter_control, 0b1100_0000);
+        // $4015 reads back which channels are on. And $4017 went to the
+        // frame counter, not the controller's strobe, so B is up next.
+        assert_eq!(read(&mut system, 0x4015), 0b1_0101);
+        assert_eq!(read(&mut system, 0x4017) & 1, 1);
+    }
+    #[test]
+    fn microphone_is_only_on_a_famicom() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        system.get_controllers_mut()[1].microphone = true;
+        for (is_famicom, expected) in [(false, 0), (true, MICROPHONE_BIT)] {
+            system.set_famicom(is_famicom);
+            write(&mut system, 0x4016, 1);
+            write(&mut system, 0x4016, 0);
+            // Every read, not just the first.
+            for _ in 0..3 {
+                assert_eq!(read(&mut system, 0x4016) & MICROPHONE_BIT, expected);
+            }
+        }
+        system.get_controllers_mut()[1].microphone = false;
+        assert_eq!(read(&mut system, 0x4016) & MICROPHONE_BIT, 0);
+    }
+    /// Strobe `controller` and read all eight buttons, first to last.
+    fn read_all_bits(controller: &mut Controller) -> Vec<u8> {
+        strobe(controller);
+        (0..8).map(|_| controller.perform_read()).collect()
+    }
+
+    #[test]
+    fn pressed_and_released_only_last_a_frame() {
+        let mut controller = Controller::default();
+        controller.set_buttons(BUTTON_A | BUTTON_UP);
+        assert_eq!(controller.changed_since(BUTTON_A), BUTTON_UP);
+        assert_eq!(controller.changed_since(BUTTON_UP), BUTTON_A);
+        // Nothing counts until the frame goes by.
+        assert_eq!(controller.get_pressed_buttons(), 0);
+        controller.advance_frame();
+        assert_eq!(controller.get_pressed_buttons(), BUTTON_A | BUTTON_UP);
+        assert_eq!(controller.get_released_buttons(), 0);
+        // Still holding A, letting go of up, and pressing B.
+        controller.set_buttons(BUTTON_A | BUTTON_B);
+        controller.advance_frame();
+        assert_eq!(controller.get_pressed_buttons(), BUTTON_B);
+        assert_eq!(controller.get_released_buttons(), BUTTON_UP);
+        // Holding the same thing for another frame is neither.
+        controller.advance_frame();
+        assert_eq!(controller.get_pressed_buttons(), 0);
+        assert_eq!(controller.get_released_buttons(), 0);
+        controller.set_buttons(0);
+        controller.advance_frame();
+        assert_eq!(controller.get_released_buttons(), BUTTON_A | BUTTON_B);
+        assert_eq!(controller.changed_since(BUTTON_B), BUTTON_B);
+    }
+    #[test]
+    fn turbo_only_changes_between_frames() {
+        let mut controller = Controller {
+            button_start: true,
+            turbo_a: true,
+            ..Default::default()
+        };
+        controller.advance_frame();
+        let expected = [1, 0, 0, 1, 0, 0, 0, 0];
+        assert_eq!(read_all_bits(&mut controller), expected);
+        // Letting go of turbo A, and holding turbo B, halfway through the
+        // reads doesn't change what's already latched.
+        strobe(&mut controller);
+        let mut bits: Vec<u8> = (0..4).map(|_| controller.perform_read()).collect();
+        controller.turbo_a = false;
+        controller.turbo_b = true;
+        bits.extend((0..4).map(|_| controller.perform_read()));
+        assert_eq!(bits, expected);
+        // Strobing again in the same frame sees the same turbo phase, too.
+        controller.turbo_a = true;
+        controller.turbo_b = false;
+        for _ in 0..3 {
+            assert_eq!(read_all_bits(&mut controller), expected);
+        }
+        // Only the next frame flips it.
+        controller.advance_frame();
+        assert_eq!(read_all_bits(&mut controller), [0, 0, 0, 1, 0, 0, 0, 0]);
+    }
+    #[test]
+    fn controller_state_round_trips_in_shift_register_order() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        for player in 0..4 {
+            for buttons in 0..=255 {
+                system.set_controller_state(player, buttons);
+                assert_eq!(system.get_controller_state(player), buttons);
+            }
+        }
+        // Each bit is the button it should be.
+        let is_held: [fn(&Controller) -> bool; 8] = [
+            |x| x.button_a,
+            |x| x.button_b,
+            |x| x.button_select,
+            |x| x.button_start,
+            |x| x.button_up,
+            |x| x.button_down,
+            |x| x.button_left,
+            |x| x.button_right,
+        ];
+        for (bit, is_this_held) in is_held.iter().enumerate() {
+            system.set_controller_state(0, 1 << bit);
+            let controller = &system.get_controllers()[0];
+            assert!(is_this_held(controller), "bit {bit}");
+            let held_count = is_held.iter().filter(|x| x(controller)).count();
+            assert_eq!(held_count, 1, "bit {bit}");
+            // And the game reads it in the same order.
+            write(&mut system, 0x4016, 1);
+            write(&mut system, 0x4016, 0);
+            for other_bit in 0..8 {
+                let expected = (other_bit == bit) as u8;
+                assert_eq!(read(&mut system, 0x4016) & 1, expected, "bit {bit}");
+            }
+        }
+    }
+    #[test]
+    fn one_strobe_latches_both_ports_separately() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        system.set_controller_state(0, 0b1010_0101);
+        system.set_controller_state(1, 0b0011_1100);
+        write(&mut system, 0x4016, 1);
+        write(&mut system, 0x4016, 0);
+        // Take turns, so that if reading one port clocked the other too,
+        // both would come out wrong.
+        let (mut port_1, mut port_2) = (0, 0);
+        for bit in 0..8 {
+            port_1 |= (read(&mut system, 0x4016) & 1) << bit;
+            port_2 |= (read(&mut system, 0x4017) & 1) << bit;
+        }
+        assert_eq!(port_1, 0b1010_0101);
+        assert_eq!(port_2, 0b0011_1100);
+    }
+    #[test]
+    fn cheats_only_apply_when_the_compare_byte_matches() {
+        // $91D9 is $11D9 into PRG.
+        let program = Program::new(0x8000).data(&[0; 0x11D9]).data(&[0x42]);
+        let mut system = System::new(test_cartridge(program));
+        assert_eq!(read(&mut system, 0x91D9), 0x42);
+        system.add_cheat("91D9?43:AD").unwrap();
+        assert_eq!(read(&mut system, 0x91D9), 0x42);
+        system.add_cheat("91D9?42:AD").unwrap();
+        assert_eq!(read(&mut system, 0x91D9), 0xAD);
+        // Only at that address.
+        assert_eq!(read(&mut system, 0x91DA), 0x00);
+        system.clear_cheats();
+        system.add_cheat("SXIOPO").unwrap();
+        assert_eq!(read(&mut system, 0x91D9), 0xAD);
+        // The mirror at $D1D9 is a different address, as far as the Game
+        // Genie's concerned.
+        assert_eq!(read(&mut system, 0xD1D9), 0x42);
+    }
+    /// PPU memory full of noise (the same noise every time), so that
+    /// drawing any of it wrong shows.
+    fn noisy_vram() -> PpuMemory {
+        let bytes: Vec<u8> = (0..PpuMemory::SIZE as u32)
+            .map(|x| (x.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        PpuMemory::from_bytes(&bytes).unwrap()
+    }
+
+    /// A system that hasn't run at all, with `noisy_vram` and the
+    /// background and sprites on.
+    fn system_with_noisy_vram() -> System {
+        let mut system = System::new(test_cartridge(Program::new(0x8000)));
+        system.load_vram(noisy_vram());
+        system.finish_ppu_warm_up();
+        system.write_ppu_register(1, 0x1E);
+        system
+    }
+
+    #[test]
+    fn loaded_vram_round_trips_and_draws_the_same_every_time() {
+        let mut system = system_with_noisy_vram();
+        assert_eq!(system.devices.ppu.dump_vram(), noisy_vram());
+        let bytes = noisy_vram().to_bytes();
+        assert_eq!(PpuMemory::from_bytes(&bytes).unwrap(), noisy_vram());
+        let expected = system.render_ppu_only();
+        assert!(system.render_ppu_only() == expected);
+        assert!(system_with_noisy_vram().render_ppu_only() == expected);
+        // Tile 0's second row is $01 and $09, which is color 0 all the way
+        // across except for the last pixel, which is color 3.
+        let mut memory = PpuMemory {
+            nametables: [0; 4096],
+            cram: [0x0F; 32],
+            oam: [0xFF; 256],
+        };
+        memory.cram[3] = 0x30;
+        system.load_vram(memory);
+        let frame = system.render_ppu_only();
+        let black = get_palette_color(false, 0, 0x0F);
+        let white = get_palette_color(false, 0, 0x30);
+        assert_eq!(frame[NES_WIDTH..NES_WIDTH + 7], [black; 7]);
+        assert_eq!(frame[NES_WIDTH + 7], white);
+        assert_eq!(frame[NES_WIDTH + 15], white);
+        assert!(frame[..NES_WIDTH].iter().all(|x| *x == black));
+    }
+    #[test]
+    fn render_ppu_only_draws_what_render_draws() {
+        // A game that never touches the PPU.
+        let mut system = System::new(test_cartridge(Program::new(0x8000).jmp(0x8000)));
+        system.load_vram(noisy_vram());
+        system.finish_ppu_warm_up();
+        system.write_ppu_register(1, 0x1E);
+        let rendered = system.render().to_vec();
+        let sprite_0_hit_scanline = system.get_sprite_0_hit_scanline();
+        let background_grid = system.get_background_grid().to_vec();
+        assert!(system.render_ppu_only()[..] == rendered[..]);
+        assert_eq!(system.get_sprite_0_hit_scanline(), sprite_0_hit_scanline);
+        assert!(system.get_background_grid() == background_grid);
+    }
+    #[test]
+    fn palette_table_matches_get_palette_color() {
+        // Looked up the same way `draw_scanline` does.
+        let table = build_palette_table();
+        for grayscale in [false, true] {
+            let color_mask = if grayscale { 0x30 } else { 0x3F };
+            for emphasis in 0..8 {
+                for index in 0..0x40 {
+                    assert_eq!(
+                        table[emphasis << 6 | (index & color_mask)],
+                        get_palette_color(grayscale, emphasis, index),
+                        "grayscale {grayscale}, emphasis {emphasis}, color ${index:02X}",
+                    );
+                }
+            }
+        }
+    }
+    #[test]
+    fn overclocking_runs_more_cpu_cycles_per_frame() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000).jmp(0x8000)));
+        let cycles_per_frame = |system: &mut System| {
+            let start = system.cpu.get_cycle_count();
+            system.render();
+            system.cpu.get_cycle_count() - start
+        };
+        let normal = cycles_per_frame(&mut system);
+        // A frame is 29780.5 CPU cycles, give or take where the last
+        // instruction ends.
+        assert!(normal.abs_diff(29781) <= 3, "{normal} cycles");
+        system.set_overclock(2);
+        let doubled = cycles_per_frame(&mut system);
+        assert!(doubled.abs_diff(2 * 29781) <= 3, "{doubled} cycles");
+        system.set_overclock(0);
+        assert_eq!(system.get_overclock(), 1);
+    }
+    #[test]
+    fn pal_runs_more_scanlines_and_cpu_cycles_per_frame() {
+        let cycles_per_frame = |region| {
+            let mut system = System::new(test_cartridge(Program::new(0x8000).jmp(0x8000)));
+            system.set_region(region);
+            system.render();
+            let start = system.cpu.get_cycle_count();
+            system.render();
+            system.cpu.get_cycle_count() - start
+        };
+        // How many scanlines' worth of time that many cycles took.
+        let scanlines = |region: Region, cycles: u64| {
+            let clocks = cycles as usize * region.master_clocks_per_cpu_cycle();
+            (clocks as f64 / (region.master_clocks_per_dot() * DOTS_PER_SCANLINE) as f64).round()
+        };
+        let ntsc = cycles_per_frame(Region::Ntsc);
+        let pal = cycles_per_frame(Region::Pal);
+        // 262 scanlines of 341 dots, at 3 dots per CPU cycle for NTSC and
+        // 3.2 for PAL (with 50 more scanlines of V-blank).
+        assert!(ntsc.abs_diff(29781) <= 3, "{ntsc} NTSC cycles");
+        assert!(pal.abs_diff(33248) <= 3, "{pal} PAL cycles");
+        assert_eq!(scanlines(Region::Ntsc, ntsc), 262.0);
+        assert_eq!(scanlines(Region::Pal, pal), 312.0);
+    }
+    #[test]
+    fn scroll_split_moves_everything_below_it() {
+        // Every V-blank: scroll back to the top left, and then wait until
+        // about scanline 120 to jump to Y = 64 with the usual four writes.
+        // Only the last one touches "v", so the split lands on one row.
+        let wait = 0x8000;
+        let program = Program::new(wait).bit_abs(0x2002).bpl(wait);
+        let program = program
+            .lda_imm(0x00)
+            .sta_abs(0x2000)
+            .sta_abs(0x2005)
+            .sta_abs(0x2005)
+            .lda_imm(0x0A)
+            .sta_abs(0x2001)
+            .ldy_imm(12);
+        let outer = program.here();
+        let program = program.ldx_imm(0);
+        let inner = program.here();
+        let program = program
+            .dex()
+            .bne(inner)
+            .dey()
+            .bne(outer)
+            .lda_imm(0x00)
+            .sta_abs(0x2006)
+            .lda_imm(64)
+            .sta_abs(0x2005)
+            .lda_imm(0x00)
+            .sta_abs(0x2005)
+            .sta_abs(0x2006)
+            .jmp(wait);
+        let mut system = System::new(test_cartridge(program));
+        // Sprites stay put when the background scrolls, so keep them all
+        // below the bottom of the screen.
+        system.load_vram(PpuMemory {
+            oam: [0xFF; 256],
+            ..noisy_vram()
+        });
+        // The PPU ignores the scroll writes until it's warmed up.
+        system.render();
+        system.render();
+        let frame = system.render().to_vec();
+        // The same picture, with no split, scrolled to 0 and to 64.
+        let mut scrolled_to = |y| {
+            read(&mut system, 0x2002);
+            system.write_ppu_register(5, 0);
+            system.write_ppu_register(5, y);
+            system.render_ppu_only().to_vec()
+        };
+        let (top, bottom) = (scrolled_to(0), scrolled_to(64));
+        fn row(pixels: &[u32], y: usize) -> &[u32] {
+            &pixels[y * NES_WIDTH..(y + 1) * NES_WIDTH]
+        }
+        let split = (0..NES_HEIGHT)
+            .find(|y| row(&frame, *y) != row(&top, *y))
+            .unwrap();
+        assert!((100..=140).contains(&split), "split at {split}");
+        // Below the split, it picks up from the top of the scrolled picture.
+        for y in split..NES_HEIGHT {
+            assert!(row(&frame, y) == row(&bottom, y - split), "row {y}");
+        }
+    }
+    #[test]
+    fn ppuctrl_writes_only_count_after_the_ppu_warms_up() {
+        let program = Program::new(0x8000)
+            .lda_imm(0x04)
+            .sta_abs(0x2000)
+            .jmp(0x8000);
+        let mut system = System::new(test_cartridge(program));
+        // The loop writes PPUCTRL every 9 cycles, and every one of those
+        // writes gets dropped until the PPU has warmed up...
+        while system.cpu_cycles_since_reset < PPU_WARM_UP_CPU_CYCLES {
+            assert_eq!(system.devices.ppu.get_control(), 0x00);
+            system.step_cpu();
+        }
+        // ...and then the next one goes through.
+        for _ in 0..3 {
+            system.step_cpu();
+        }
+        assert_eq!(system.devices.ppu.get_control(), 0x04);
+    }
+    #[test]
+    fn nmi_handler_runs_once_a_frame() {
+        // Keep turning NMIs on (the first few writes land during warm-up,
+        // and don't count), and count frames in the NMI handler.
+        let program = Program::new(0x8000)
+            .lda_imm(0x80)
+            .sta_abs(0x2000)
+            .jmp(0x8000);
+        let nmi = program.here();
+        let program = program.inc_zp(0x00).rti();
+        let mut system = System::new(test_cartridge_with_nmi(program, nmi));
+        for _ in 0..3 {
+            system.render();
+        }
+        write(&mut system, 0x0000, 0);
+        for _ in 0..10 {
+            system.render();
+        }
+        assert_eq!(read(&mut system, 0x0000), 10);
+    }
+    #[test]
+    fn polling_ppustatus_only_misses_v_blank_a_dot_early() {
+        // Wait for V-blank, count it, and do it again, with NMIs off.
+        let program = Program::new(0x8000)
+            .bit_abs(0x2002)
+            .bpl(0x8000)
+            .inc_zp(0x10)
+            .jmp(0x8000);
+        let mut system = System::new(test_cartridge(program));
+        for _ in 0..60 {
+            system.render();
+        }
+        // It only misses a frame when the read lands on the dot right
+        // before V-blank starts, the same as on hardware. That's one read
+        // in 21 dots for this loop, so a frame or two out of 60, and not
+        // every time the read runs over into V-blank.
+        assert!(read(&mut system, 0x0010) >= 57);
+    }
+    #[test]
+    fn render_partial_blacks_out_everything_below_the_cutoff() {
+        let mut system = System::new(test_cartridge(Program::new(0x8000).jmp(0x8000)));
+        system.load_vram(noisy_vram());
+        system.finish_ppu_warm_up();
+        system.write_ppu_register(1, 0x1E);
+        let full = system.render();
+        let cutoff = 100 * NES_WIDTH + NES_WIDTH;
+        assert!(full[cutoff..].iter().any(|x| *x != 0));
+        let partial = system.render_partial(100);
+        assert!(partial[..cutoff] == full[..cutoff]);
+        assert!(partial[cutoff..].iter().all(|x| *x == 0));
+        // The last scanline, or past it, hides nothing.
+        assert!(system.render_partial(239) == full);
+        assert!(system.render_partial(1000) == full);
+    }
+    #[test]
+    fn frames_start_from_t_not_wherever_v_was_left() {
+        let mut system = system_with_noisy_vram();
+        let expected = system.render_ppu_only();
+        assert!(system.render_ppu_only() == expected);
+        // Leave "v" in the middle of the second nametable, the way a game
+        // reading VRAM in V-blank would, and then set the scroll back to
+        // the top left the usual way.
+        system.write_ppu_register(6, 0x24);
+        system.write_ppu_register(6, 0x53);
+        read(&mut system, 0x2007);
+        system.write_ppu_register(0, 0x00);
+        system.write_ppu_register(5, 0x00);
+        system.write_ppu_register(5, 0x00);
+        assert!(system.render_ppu_only() == expected);
+    }
 }
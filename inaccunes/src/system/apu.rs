@@ -0,0 +1,188 @@
+use log::*;
+
+/// One of the two square wave channels. ($4000-$4003 and $4004-$4007)
+#[derive(Debug, Default)]
+pub struct Pulse {
+    /// Which of the four duty cycles (12.5%, 25%, 50%, 75%) to use.
+    pub duty: u8,
+    /// Also the envelope's loop flag.
+    pub halt_length_counter: bool,
+    pub constant_volume: bool,
+    /// Either the volume, or the envelope's period, depending on
+    /// `constant_volume`.
+    pub volume: u8,
+    /// The whole sweep unit register, undecoded (for now).
+    pub sweep: u8,
+    /// 11 bits.
+    pub timer: u16,
+    /// Index into the length counter table.
+    pub length_counter_load: u8,
+}
+
+impl Pulse {
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.halt_length_counter = data & 0b0010_0000 != 0;
+                self.constant_volume = data & 0b0001_0000 != 0;
+                self.volume = data & 0b1111;
+            }
+            1 => self.sweep = data,
+            2 => self.timer = (self.timer & 0x700) | data as u16,
+            3 => {
+                self.timer = (self.timer & 0xFF) | ((data as u16 & 0b111) << 8);
+                self.length_counter_load = data >> 3;
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The triangle wave channel. ($4008-$400B)
+#[derive(Debug, Default)]
+pub struct Triangle {
+    /// Also the linear counter's control flag.
+    pub halt_length_counter: bool,
+    pub linear_counter_load: u8,
+    /// 11 bits.
+    pub timer: u16,
+    /// Index into the length counter table.
+    pub length_counter_load: u8,
+}
+
+impl Triangle {
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.halt_length_counter = data & 0b1000_0000 != 0;
+                self.linear_counter_load = data & 0b0111_1111;
+            }
+            1 => (), // nothing here!
+            2 => self.timer = (self.timer & 0x700) | data as u16,
+            3 => {
+                self.timer = (self.timer & 0xFF) | ((data as u16 & 0b111) << 8);
+                self.length_counter_load = data >> 3;
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The noise channel. ($400C-$400F)
+#[derive(Debug, Default)]
+pub struct Noise {
+    /// Also the envelope's loop flag.
+    pub halt_length_counter: bool,
+    pub constant_volume: bool,
+    /// Either the volume, or the envelope's period, depending on
+    /// `constant_volume`.
+    pub volume: u8,
+    /// The "short" mode, that makes metallic sounding noise.
+    pub is_short_mode: bool,
+    /// Index into the noise period table.
+    pub period: u8,
+    /// Index into the length counter table.
+    pub length_counter_load: u8,
+}
+
+impl Noise {
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.halt_length_counter = data & 0b0010_0000 != 0;
+                self.constant_volume = data & 0b0001_0000 != 0;
+                self.volume = data & 0b1111;
+            }
+            1 => (), // nothing here either!
+            2 => {
+                self.is_short_mode = data & 0b1000_0000 != 0;
+                self.period = data & 0b1111;
+            }
+            3 => self.length_counter_load = data >> 3,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The delta modulation channel, which plays samples. ($4010-$4013)
+#[derive(Debug, Default)]
+pub struct Dmc {
+    pub irq_enabled: bool,
+    pub is_looping: bool,
+    /// Index into the DMC rate table.
+    pub rate: u8,
+    /// 7 bits, written straight into the output level.
+    pub direct_load: u8,
+    /// Sample address is `$C000 + sample_address * 64`.
+    pub sample_address: u8,
+    /// Sample length is `sample_length * 16 + 1` bytes.
+    pub sample_length: u8,
+}
+
+impl Dmc {
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = data & 0b1000_0000 != 0;
+                self.is_looping = data & 0b0100_0000 != 0;
+                self.rate = data & 0b1111;
+            }
+            1 => self.direct_load = data & 0b0111_1111,
+            2 => self.sample_address = data,
+            3 => self.sample_length = data,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Audio Processing Unit. For now, this only keeps track of what the game
+/// has told each channel. It doesn't make any sound (yet).
+// TODO: once there's a mixer, let the frontend mute each channel on its own
+// (separately from the game's own enables in $4015), so you can pick a song
+// apart. Hotkeys in main.rs to go with it. Nothing to mute until then.
+// TODO: same goes for `--record-audio out.wav`: with a mixer, that's a small
+// WAV writer in main.rs (finished off on exit, like `--trace`). Without one,
+// it'd only ever record silence.
+#[derive(Debug, Default)]
+pub struct Apu {
+    pub pulses: [Pulse; 2],
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    /// The low five bits of $4015: which channels are turned on. (Pulse 1 is
+    /// bit 0, DMC is bit 4.)
+    pub enabled_channels: u8,
+    /// The frame counter's mode and IRQ inhibit bits, from $4017.
+    pub frame_counter_control: u8,
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu::default()
+    }
+    /// Handle a CPU read from `$4000-$4015`. Only `$4015` is actually
+    /// readable. Everything else is open bus, which we call 0.
+    pub fn read_register(&mut self, address: u16) -> u8 {
+        match address {
+            // TODO: this is supposed to say which length counters are
+            // nonzero (and whether an IRQ happened), but we don't have any
+            // length counters yet. Enabled is the next best thing.
+            0x4015 => self.enabled_channels,
+            _ => 0,
+        }
+    }
+    /// Handle a CPU write to `$4000-$4013`, `$4015`, or `$4017`.
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulses[0].write_register(address & 0b11, data),
+            0x4004..=0x4007 => self.pulses[1].write_register(address & 0b11, data),
+            0x4008..=0x400B => self.triangle.write_register(address & 0b11, data),
+            0x400C..=0x400F => self.noise.write_register(address & 0b11, data),
+            0x4010..=0x4013 => self.dmc.write_register(address & 0b11, data),
+            0x4015 => self.enabled_channels = data & 0b1_1111,
+            0x4017 => self.frame_counter_control = data & 0b1100_0000,
+            _ => warn!("Write to a register the APU doesn't have: {address:04X} <-- {data:02X}"),
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use super::*;
 use log::*;
 
-use crate::cartridge::{Cartridge, MirroringType};
+use crate::cartridge::Cartridge;
+
+mod scroll;
+pub use scroll::ScrollRegisters;
 
 /*
 
@@ -21,25 +24,138 @@ ______________ || |||| ||||
 
 */
 
-pub struct PPU {
-    pub register_control: u8,
-    pub register_mask: u8,
-    pub register_oam_address: u8,
-    pub register_scroll_x: u8,
-    pub register_scroll_y: u8,
-    //pub register_ppudata_address: u16,
+/// Where the palette (CRAM) starts on the PPU bus. It goes all the way to the
+/// end of the (14-bit) bus from here.
+const PALETTE_START: u16 = 0x3F00;
+
+/// One sprite's four bytes of OAM, picked apart. See `PPU::decoded_sprites`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpriteInfo {
+    /// The scanline its top row is on. Sprites show up one line lower than
+    /// OAM says, so this is one more than byte 0.
+    pub y: usize,
+    pub x: usize,
+    /// Byte 1, as is. For 8x16 sprites, bit 0 is which pattern table, and
+    /// the rest is the top tile (the bottom one is the one after it).
+    pub tile_index: u8,
+    /// Which palette, 4-7. (Sprites get the second four.)
+    pub palette: usize,
+    /// Whether the background is drawn on top of it (where the background
+    /// isn't color 0).
+    pub is_behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl SpriteInfo {
+    pub fn from_oam_data(oam_data: &[u8]) -> SpriteInfo {
+        debug_assert_eq!(oam_data.len(), 4);
+        let attributes = oam_data[2];
+        SpriteInfo {
+            y: oam_data[0] as usize + 1,
+            x: oam_data[3] as usize,
+            tile_index: oam_data[1],
+            palette: (attributes as usize & 0b0000_0011) + 4,
+            is_behind_background: (attributes & 0b0010_0000) != 0,
+            flip_horizontal: (attributes & 0b0100_0000) != 0,
+            flip_vertical: (attributes & 0b1000_0000) != 0,
+        }
+    }
+    /// Where its (top) tile is in CHR, given PPUCTRL's sprite size and
+    /// sprite pattern table bits. 8x16 sprites ignore the pattern table bit
+    /// and pick for themselves.
+    pub fn get_tile_address(
+        &self,
+        sprites_are_8x16: bool,
+        sprite_tiles_are_in_upper_half: bool,
+    ) -> u16 {
+        let (tile_number, is_upper_half) = if sprites_are_8x16 {
+            (self.tile_index & 0b1111_1110, self.tile_index & 1 != 0)
+        } else {
+            (self.tile_index, sprite_tiles_are_in_upper_half)
+        };
+        let tile_offset = tile_number as u16 * TILE_BYTES as u16;
+        if is_upper_half {
+            0x1000 + tile_offset
+        } else {
+            tile_offset
+        }
+    }
+}
+
+/// Everything the PPU remembers that a game puts there: nametable RAM, the
+/// palette, and sprites. Enough to draw a frame without running the game
+/// that set it up. See `PPU::dump_vram` and `PPU::load_vram`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PpuMemory {
+    pub nametables: [u8; 4096],
     pub cram: [u8; 32],
     pub oam: [u8; 256],
-    pub nametables: [u8; 4096],
+}
+
+impl PpuMemory {
+    /// How long `to_bytes` is: nametables, then CRAM, then OAM.
+    pub const SIZE: usize = 4096 + 32 + 256;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.nametables[..], &self.cram, &self.oam].concat()
+    }
+    /// The opposite of `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<PpuMemory> {
+        if bytes.len() != PpuMemory::SIZE {
+            return Err(anyhow::anyhow!(
+                "PPU memory should be {} bytes, not {}",
+                PpuMemory::SIZE,
+                bytes.len()
+            ));
+        }
+        let (nametables, rest) = bytes.split_at(4096);
+        let (cram, oam) = rest.split_at(32);
+        Ok(PpuMemory {
+            nametables: nametables.try_into().unwrap(),
+            cram: cram.try_into().unwrap(),
+            oam: oam.try_into().unwrap(),
+        })
+    }
+}
+
+/// The PPU's insides are only for `System` to mess with. Everyone else (say,
+/// a debugger) gets read-only getters, so that how they're stored can change.
+pub struct PPU {
+    pub(crate) register_control: u8,
+    pub(crate) register_mask: u8,
+    pub(crate) register_oam_address: u8,
+    pub(crate) register_scroll_x: u8,
+    pub(crate) register_scroll_y: u8,
+    //pub register_ppudata_address: u16,
+    pub(crate) cram: [u8; 32],
+    /// CRAM entries that stay put, no matter what the game writes. See
+    /// `System::set_palette_override`.
+    pub(crate) cram_overrides: [Option<u8>; 32],
+    pub(crate) oam: [u8; 256],
+    pub(crate) nametables: [u8; 4096],
     vblank_status_flag: bool,
     vblank_in_progress: bool,
-    pub cursed_multi_register_flag: bool,
+    /// Whether the beam's on the post-render scanline, where the PPU sits
+    /// idle even with rendering on. `System` keeps this up to date.
+    pub(crate) is_on_post_render_scanline: bool,
+    /// The "w" latch: true if the next write to PPUSCROLL or PPUADDR is the
+    /// first of a pair. There's only one of these, shared between both
+    /// registers, so writing PPUSCROLL once and then PPUADDR once counts as a
+    /// complete pair! Reading PPUSTATUS resets it to true, which is how games
+    /// get it into a known state before they start writing.
+    pub(crate) cursed_multi_register_flag: bool,
     sprite_0_hit_flag: bool,
+    /// Whether anyone's read PPUSTATUS since `System` last cleared this.
+    /// See `vblank_start`.
+    pub(crate) was_status_read: bool,
     ppudata_latch: u8,
-    // reference: https://forums.nesdev.org/viewtopic.php?t=664
-    pub current_render_address: u16, // LoopyV
-    pub canon_render_address: u16,   // LoopyT
-    pub fine_scroll_x: u8,
+    /// Loopy's "v", "t", and "x".
+    pub(crate) scroll: ScrollRegisters,
+    /// Right after power-on or reset, the PPU ignores writes to PPUCTRL,
+    /// PPUMASK, PPUSCROLL, and PPUADDR for a while. `System` decides when
+    /// that's over.
+    is_warming_up: bool,
 }
 
 impl PPU {
@@ -54,26 +170,66 @@ impl PPU {
             oam: [0; 256],
             vblank_status_flag: false,
             vblank_in_progress: false,
+            is_on_post_render_scanline: false,
             cursed_multi_register_flag: true,
             nametables: [0; 4096],
             cram: [0; 32],
+            cram_overrides: [None; 32],
             sprite_0_hit_flag: false,
+            was_status_read: false,
             ppudata_latch: 0,
-            current_render_address: 0,
-            canon_render_address: 0,
-            fine_scroll_x: 0,
+            scroll: ScrollRegisters::default(),
+            is_warming_up: true,
+        }
+    }
+    /// Put the registers, OAM, the scroll registers, the w latch, and the
+    /// flags back the way `new` has them. Nametables and the palette stay
+    /// put, and so does the warm-up (this isn't the reset line, so nothing
+    /// starts over) and where we are in the frame (that's up to the beam).
+    pub fn reset(&mut self) {
+        *self = PPU {
+            nametables: self.nametables,
+            cram: self.cram,
+            cram_overrides: self.cram_overrides,
+            vblank_in_progress: self.vblank_in_progress,
+            is_on_post_render_scanline: self.is_on_post_render_scanline,
+            is_warming_up: self.is_warming_up,
+            ..PPU::new()
+        };
+    }
+    /// A copy of nametable RAM, the palette, and OAM.
+    pub fn dump_vram(&self) -> PpuMemory {
+        PpuMemory {
+            nametables: self.nametables,
+            cram: self.cram,
+            oam: self.oam,
+        }
+    }
+    /// Put back what `dump_vram` took. Palette overrides (see
+    /// `System::set_palette_override`) still win.
+    pub fn load_vram(&mut self, memory: PpuMemory) {
+        self.nametables = memory.nametables;
+        self.oam = memory.oam;
+        for (index, color) in memory.cram.into_iter().enumerate() {
+            self.cram[index] = self.cram_overrides[index].unwrap_or(color);
         }
     }
+    pub fn start_warm_up(&mut self) {
+        self.is_warming_up = true;
+    }
+    pub fn finish_warm_up(&mut self) {
+        self.is_warming_up = false;
+    }
     pub fn perform_bus_read(&mut self, cartridge: &Cartridge, address: u16) -> u8 {
         // only 14 bits of address exist on the bus
         let address = address & 0b11_1111_1111_1111;
         if address < 0x2000 {
             cartridge.perform_chr_read(address)
-        } else if address > 0x3F00 {
+        } else if address >= PALETTE_START {
             let cram_address = address & 0x1F;
             self.cram[cram_address as usize]
         } else {
-            self.nametables[(address & 0b1111_1111_1111) as usize]
+            self.nametables[cartridge.mirroring_type.map_nametable_address(address)]
         }
     }
     pub fn perform_bus_write(&mut self, cartridge: &mut Cartridge, address: u16, data: u8) {
@@ -81,18 +237,14 @@ impl PPU {
         let address = address & 0b11_1111_1111_1111;
         if address < 0x2000 {
             cartridge.perform_chr_write(address, data)
-        } else if address > 0x3F00 {
-            let cram_address = address & 0x1F;
-            self.cram[cram_address as usize] = data;
+        } else if address >= PALETTE_START {
+            let cram_address = (address & 0x1F) as usize;
+            self.cram[cram_address] = self.cram_overrides[cram_address].unwrap_or(data);
         } else {
-            let bit_to_flip = match cartridge.mirroring_type {
-                MirroringType::Horizontal => 0b0100_0000_0000,
-                MirroringType::Vertical => 0b1000_0000_0000,
-                MirroringType::FourScreen => 0b0000_0000_0000,
-            };
-            let nametable_address = address & 0xFFF;
-            self.nametables[nametable_address as usize] = data;
-            self.nametables[(nametable_address ^ bit_to_flip) as usize] = data;
+            // The mirroring type is checked every time (instead of, say,
+            // writing to every mirror at once) because some mappers can
+            // change it on the fly.
+            self.nametables[cartridge.mirroring_type.map_nametable_address(address)] = data;
         }
     }
     fn increment_ppudata_address(&mut self) {
@@ -101,7 +253,7 @@ impl PPU {
         } else {
             32
         };
-        self.current_render_address = self.current_render_address.wrapping_add(inc);
+        self.scroll.increment_address(inc);
     }
     pub fn perform_register_read(&mut self, cartridge: &Cartridge, address: u16) -> u8 {
         let address = address & 0b111;
@@ -111,8 +263,12 @@ impl PPU {
                 return 0;
             }
             2 => {
-                // Reading PPUSTATUS sets the latch to a known state:
+                // Reading PPUSTATUS sets the latch to a known state, even in
+                // the middle of a pair of writes. The next write to $2005 or
+                // $2006 will be a first write (X scroll, or PPUADDR's high
+                // byte).
                 self.cursed_multi_register_flag = true;
+                self.was_status_read = true;
                 let mut result = 0;
                 // Sprite Overflow flag. The real hardware is buggy as
                 // hell. For now, we won't try to implement it.
@@ -130,13 +286,19 @@ impl PPU {
                 }
                 result
             }
-            4 => {
-                todo!("read OAMDATA")
-            }
+            4 => inaccu6502::unimplemented_feature!(0, "read OAMDATA"),
             7 => {
-                let real_result = self.perform_bus_read(cartridge, self.current_render_address);
-                let output_result = self.ppudata_latch;
-                self.ppudata_latch = real_result;
+                let address = self.scroll.get_current_render_address() & 0b11_1111_1111_1111;
+                let output_result = if address >= PALETTE_START {
+                    // Palette reads aren't buffered, they come right out.
+                    // The buffer still gets filled, though, with the
+                    // nametable byte that's "underneath" the palette.
+                    self.ppudata_latch = self.perform_bus_read(cartridge, address - 0x1000);
+                    self.perform_bus_read(cartridge, address)
+                } else {
+                    let real_result = self.perform_bus_read(cartridge, address);
+                    std::mem::replace(&mut self.ppudata_latch, real_result)
+                };
                 self.increment_ppudata_address();
                 output_result
             }
@@ -151,19 +313,27 @@ impl PPU {
         data: u8,
     ) {
         let address = address & 0b111;
+        if self.is_warming_up && matches!(address, 0 | 1 | 5 | 6) {
+            debug!("PPU is still warming up, ignoring write of {data:02X} to register {address:X}");
+            return;
+        }
         match address {
             0 => {
-                // BEGIN CURSE!
-                let loopy_bits = data & 0b11;
-                self.canon_render_address &= 0b1110011_11111111;
-                self.canon_render_address |= (loopy_bits as u16) << 10;
-                // END CURSE!
+                self.scroll.write_ppuctrl_nametable(data);
                 self.register_control = data;
                 cpu.set_nmi_signal(self.is_nmi_supposed_to_be_active());
             }
             1 => self.register_mask = data,
             2 => warn!("ROM wrote {data:02X} to PPUSTATUS register"),
             3 => self.register_oam_address = data,
+            4 if self.is_rendering() => {
+                // The PPU is busy reading OAM for sprite evaluation, so the
+                // write goes nowhere. What it *does* do is bump OAMADDR to
+                // the next sprite, which is what the few games that do this
+                // on purpose are after.
+                debug!("ROM wrote {data:02X} to OAMDATA while rendering, OAM is unchanged");
+                self.register_oam_address = self.register_oam_address.wrapping_add(4);
+            }
             4 => {
                 self.oam[self.register_oam_address as usize] = data;
                 self.register_oam_address = self.register_oam_address.wrapping_add(1);
@@ -171,22 +341,10 @@ impl PPU {
             5 => {
                 if self.cursed_multi_register_flag {
                     self.register_scroll_x = data;
-                    // BEGIN CURSE!
-                    let loopy_data = data >> 3;
-                    self.canon_render_address &= 0b1111111_11100000;
-                    self.canon_render_address |= (loopy_data as u16) << 0;
-                    let loopy_data = data & 0b111;
-                    self.fine_scroll_x = loopy_data;
-                    // END CURSE!
+                    self.scroll.write_scroll_x(data);
                 } else {
                     self.register_scroll_y = data;
-                    // BEGIN CURSE!
-                    self.canon_render_address &= 0b0001100_00011111;
-                    let loopy_data = data >> 3;
-                    self.canon_render_address |= (loopy_data as u16) << 5;
-                    let loopy_data = data & 0b111;
-                    self.canon_render_address |= (loopy_data as u16) << 12;
-                    // END CURSE!
+                    self.scroll.write_scroll_y(data);
                 }
                 self.cursed_multi_register_flag = !self.cursed_multi_register_flag;
             }
@@ -196,35 +354,29 @@ impl PPU {
                     // log::trace!("PPUADDR high write: {data:02X}");
                     //self.register_ppudata_address =
                     //    (self.register_ppudata_address & !0xFF00) | ((data as u16) << 8);
-                    // BEGIN CURSE!
-                    self.canon_render_address &= 0b1000000_11111111;
-                    let loopy_data = data & 0b111111;
-                    self.canon_render_address |= (loopy_data as u16) << 8;
-                    // END CURSE!
+                    self.scroll.write_addr_high(data);
                 } else {
                     // Write the low byte
                     // log::trace!("PPUADDR low write:  {data:02X}");
                     //self.register_ppudata_address =
                     //    (self.register_ppudata_address & !0x00FF) | (data as u16);
-                    // BEGIN CURSE!
-                    self.canon_render_address &= 0b1111111_00000000;
-                    let loopy_data = data;
-                    self.canon_render_address |= (loopy_data as u16) << 0;
-                    // AND!
-                    self.current_render_address = self.canon_render_address;
-                    // END CURSE!
+                    self.scroll.write_addr_low(data);
                 }
                 self.cursed_multi_register_flag = !self.cursed_multi_register_flag;
             }
             7 => {
-                self.perform_bus_write(cartridge, self.current_render_address, data);
+                self.perform_bus_write(cartridge, self.scroll.get_current_render_address(), data);
                 self.increment_ppudata_address();
             }
             _ => unreachable!(),
         }
     }
-    pub fn vblank_start(&mut self, cpu: &mut Cpu) {
-        self.vblank_status_flag = true;
+    /// If `is_suppressed`, the game read PPUSTATUS the dot before V-blank
+    /// started. On hardware, that read sees the flag clear, *and* keeps it
+    /// from getting set, so there's no NMI this frame. Some games lose that
+    /// race every so often, and you can tell.
+    pub fn vblank_start(&mut self, cpu: &mut Cpu, is_suppressed: bool) {
+        self.vblank_status_flag = !is_suppressed;
         self.vblank_in_progress = true;
         cpu.set_nmi_signal(self.is_nmi_supposed_to_be_active());
         self.sprite_0_hit_flag = true;
@@ -235,6 +387,14 @@ impl PPU {
         cpu.set_nmi_signal(self.is_nmi_supposed_to_be_active());
         self.sprite_0_hit_flag = false;
     }
+    /// Whether the PPU is busy fetching from memory right now: the beam's on
+    /// the picture (scanlines 0-239) or the pre-render scanline, and the
+    /// background or sprites are on.
+    pub fn is_rendering(&self) -> bool {
+        !self.vblank_in_progress
+            && !self.is_on_post_render_scanline
+            && self.register_mask & 0b11000 != 0
+    }
     fn is_nmi_supposed_to_be_active(&self) -> bool {
         self.is_nmi_on() && self.vblank_status_flag
     }
@@ -274,7 +434,222 @@ impl PPU {
         let data = self.register_mask;
         (data >> 5) as usize
     }
+    pub fn is_sprite_0_hit(&self) -> bool {
+        self.sprite_0_hit_flag
+    }
+    /// Bit 7 of PPUSTATUS: set at the start of V-blank, cleared at the end
+    /// of it, or as soon as anyone reads PPUSTATUS.
+    pub fn is_vblank_flag_set(&self) -> bool {
+        self.vblank_status_flag
+    }
+    /// PPUCTRL, as last written.
+    pub fn get_control(&self) -> u8 {
+        self.register_control
+    }
+    /// PPUMASK, as last written.
+    pub fn get_mask(&self) -> u8 {
+        self.register_mask
+    }
+    pub fn get_oam_address(&self) -> u8 {
+        self.register_oam_address
+    }
+    /// The scroll position, as last written to PPUSCROLL. (What's actually
+    /// used for rendering is in the render addresses.)
+    pub fn get_scroll_x(&self) -> u8 {
+        self.register_scroll_x
+    }
+    pub fn get_scroll_y(&self) -> u8 {
+        self.register_scroll_y
+    }
+    /// Whether the next write to PPUSCROLL or PPUADDR is the first of a
+    /// pair. (See `cursed_multi_register_flag`.)
+    pub fn is_next_write_first(&self) -> bool {
+        self.cursed_multi_register_flag
+    }
+    /// Where the PPU is reading (or the CPU is accessing) right now. Loopy
+    /// calls this "v".
+    pub fn get_current_render_address(&self) -> u16 {
+        self.scroll.get_current_render_address()
+    }
+    /// Where rendering starts over at the top of the frame. Loopy calls this
+    /// "t".
+    pub fn get_canon_render_address(&self) -> u16 {
+        self.scroll.get_canon_render_address()
+    }
+    pub fn get_fine_scroll_x(&self) -> u8 {
+        self.scroll.get_fine_scroll_x()
+    }
+    /// The palette, 32 entries: four background palettes, then four sprite
+    /// palettes.
+    pub fn get_cram(&self) -> &[u8; 32] {
+        &self.cram
+    }
+    /// Sprite memory: 64 sprites, four bytes each (Y, tile, attributes, X).
+    pub fn get_oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+    /// Every sprite in OAM, picked apart, in OAM order.
+    pub fn decoded_sprites(&self) -> [SpriteInfo; 64] {
+        std::array::from_fn(|index| SpriteInfo::from_oam_data(&self.oam[index * 4..index * 4 + 4]))
+    }
+    /// All of nametable RAM, room for four nametables. (Unless the cartridge
+    /// has four-screen VRAM, only half of it is real. See
+    /// `MirroringType::map_nametable_address`.)
+    pub fn get_nametables(&self) -> &[u8; 4096] {
+        &self.nametables
+    }
     pub fn turn_on_sprite_0_hit(&mut self) {
         self.sprite_0_hit_flag = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::tests::test_cartridge;
+    use inaccu6502::Program;
+
+    /// A PPU that's done warming up, and a cartridge to go with it.
+    fn warm_ppu() -> (PPU, Cartridge) {
+        let mut ppu = PPU::new();
+        ppu.finish_warm_up();
+        (ppu, test_cartridge(Program::new(0x8000)))
+    }
+
+    fn write_register(ppu: &mut PPU, cartridge: &mut Cartridge, address: u16, data: u8) {
+        ppu.perform_register_write(&mut Cpu::new(), cartridge, address, data);
+    }
+
+    #[test]
+    fn palette_reads_are_not_buffered() {
+        let (mut ppu, mut cartridge) = warm_ppu();
+        ppu.perform_bus_write(&mut cartridge, 0x3F05, 0x17);
+        // The nametable byte "underneath" $3F05.
+        ppu.perform_bus_write(&mut cartridge, 0x2F05, 0xAB);
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x3F);
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x05);
+        assert_eq!(ppu.perform_register_read(&cartridge, 0x2007), 0x17);
+        // A buffered read anywhere else gets what the palette read left in
+        // the buffer.
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x20);
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x00);
+        assert_eq!(ppu.perform_register_read(&cartridge, 0x2007), 0xAB);
+    }
+    #[test]
+    fn reading_ppustatus_resets_the_write_latch() {
+        let (mut ppu, mut cartridge) = warm_ppu();
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x21);
+        ppu.perform_register_read(&cartridge, 0x2002);
+        // Another high byte, not the low byte of $21xx.
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x23);
+        assert_eq!(ppu.scroll.get_canon_render_address(), 0x2300);
+        assert_eq!(ppu.scroll.get_current_render_address(), 0x0000);
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x45);
+        assert_eq!(ppu.scroll.get_current_render_address(), 0x2345);
+    }
+    #[test]
+    fn decoded_sprites_pick_apart_every_byte() {
+        let mut ppu = PPU::new();
+        ppu.oam[..4].copy_from_slice(&[0x20, 0x42, 0b1110_0010, 0x80]);
+        ppu.oam[4..8].copy_from_slice(&[0xEF, 0x01, 0b0001_1101, 0xF8]);
+        let sprites = ppu.decoded_sprites();
+        assert_eq!(
+            sprites[0],
+            SpriteInfo {
+                y: 0x21,
+                x: 0x80,
+                tile_index: 0x42,
+                palette: 6,
+                is_behind_background: true,
+                flip_horizontal: true,
+                flip_vertical: true,
+            }
+        );
+        // The unused attribute bits don't count for anything.
+        assert_eq!(
+            sprites[1],
+            SpriteInfo {
+                y: 0xF0,
+                x: 0xF8,
+                tile_index: 0x01,
+                palette: 5,
+                is_behind_background: false,
+                flip_horizontal: false,
+                flip_vertical: false,
+            }
+        );
+        assert_eq!(sprites[63], SpriteInfo::from_oam_data(&[0; 4]));
+        assert_eq!(sprites[63].palette, 4);
+    }
+    #[test]
+    fn reset_matches_new_except_memory_and_the_beam() {
+        let (mut ppu, mut cartridge) = warm_ppu();
+        ppu.vblank_start(&mut Cpu::new(), false);
+        for (address, data) in [
+            (0x2000, 0x93),
+            (0x2001, 0x1E),
+            (0x2003, 0x40),
+            (0x2004, 0x77),
+        ] {
+            write_register(&mut ppu, &mut cartridge, address, data);
+        }
+        // Scroll, then leave the w latch halfway through a PPUADDR pair.
+        write_register(&mut ppu, &mut cartridge, 0x2005, 0x2D);
+        write_register(&mut ppu, &mut cartridge, 0x2005, 0x5A);
+        write_register(&mut ppu, &mut cartridge, 0x2006, 0x23);
+        ppu.perform_bus_write(&mut cartridge, 0x2123, 0x45);
+        ppu.perform_bus_write(&mut cartridge, 0x3F01, 0x16);
+        ppu.sprite_0_hit_flag = true;
+        ppu.was_status_read = true;
+        ppu.ppudata_latch = 0x99;
+        let (nametables, cram) = (ppu.nametables, ppu.cram);
+
+        ppu.reset();
+        let new = PPU::new();
+        assert_eq!(ppu.register_control, new.register_control);
+        assert_eq!(ppu.register_mask, new.register_mask);
+        assert_eq!(ppu.register_oam_address, new.register_oam_address);
+        assert_eq!(ppu.register_scroll_x, new.register_scroll_x);
+        assert_eq!(ppu.register_scroll_y, new.register_scroll_y);
+        assert_eq!(ppu.oam, new.oam);
+        assert_eq!(ppu.vblank_status_flag, new.vblank_status_flag);
+        assert_eq!(
+            ppu.cursed_multi_register_flag,
+            new.cursed_multi_register_flag
+        );
+        assert_eq!(ppu.sprite_0_hit_flag, new.sprite_0_hit_flag);
+        assert_eq!(ppu.was_status_read, new.was_status_read);
+        assert_eq!(ppu.ppudata_latch, new.ppudata_latch);
+        assert_eq!(ppu.scroll, new.scroll);
+        // What stays put.
+        assert_eq!(ppu.nametables, nametables);
+        assert_eq!(ppu.cram, cram);
+        assert!(ppu.vblank_in_progress);
+        assert!(!ppu.is_warming_up);
+    }
+    #[test]
+    fn oamdata_writes_only_go_nowhere_on_render_scanlines() {
+        let (mut ppu, mut cartridge) = warm_ppu();
+        write_register(&mut ppu, &mut cartridge, 0x2001, 0x18);
+        write_register(&mut ppu, &mut cartridge, 0x2003, 0x10);
+        // Mid-picture: dropped, and OAMADDR moves on to the next sprite.
+        write_register(&mut ppu, &mut cartridge, 0x2004, 0xAB);
+        assert_eq!(ppu.oam[0x10], 0);
+        assert_eq!(ppu.register_oam_address, 0x14);
+        // The post-render scanline is idle, so the write lands.
+        ppu.is_on_post_render_scanline = true;
+        write_register(&mut ppu, &mut cartridge, 0x2004, 0xCD);
+        assert_eq!(ppu.oam[0x14], 0xCD);
+        assert_eq!(ppu.register_oam_address, 0x15);
+        // So does V-blank.
+        ppu.is_on_post_render_scanline = false;
+        ppu.vblank_start(&mut Cpu::new(), false);
+        write_register(&mut ppu, &mut cartridge, 0x2004, 0xEF);
+        assert_eq!(ppu.oam[0x15], 0xEF);
+        // And with rendering off, mid-picture.
+        ppu.vblank_stop(&mut Cpu::new());
+        write_register(&mut ppu, &mut cartridge, 0x2001, 0x00);
+        write_register(&mut ppu, &mut cartridge, 0x2004, 0x12);
+        assert_eq!(ppu.oam[0x16], 0x12);
+    }
+}
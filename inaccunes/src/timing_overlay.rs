@@ -0,0 +1,53 @@
+//! When things happened during the last frame, drawn right on the TV: a
+//! marker at each edge of the scanline where sprite 0 hit, and a line of
+//! text in the corner with that scanline, the one the NMI came on, and
+//! whether the V-blank flag is up right now. (It's only ever up between
+//! frames if the debugger stopped us in the middle of V-blank.)
+
+use inaccunes::system::System;
+use sdl2::{pixels::Color, rect::Rect, render::WindowCanvas};
+
+use crate::font::FontInstance;
+
+const SPRITE_0_HIT_COLOR: Color = Color {
+    r: 255,
+    g: 64,
+    b: 64,
+    a: 255,
+};
+/// How far the sprite 0 hit marker sticks in from each side of the window.
+const MARKER_WIDTH: u32 = 16;
+const TEXT_MARGIN: i32 = 4;
+
+/// Draw the overlay over whatever's on `canvas`, which is showing
+/// `visible_area` of the picture.
+pub fn draw(canvas: &mut WindowCanvas, font: &FontInstance, system: &System, visible_area: Rect) {
+    let (output_width, output_height) = canvas.output_size().unwrap_or((1, 1));
+    if let Some(scanline) = system.get_sprite_0_hit_scanline() {
+        // Overscan might be hiding it.
+        let row = scanline as i32 - visible_area.y();
+        if (0..visible_area.height() as i32).contains(&row) {
+            let top = row * output_height as i32 / visible_area.height() as i32;
+            let height = (output_height / visible_area.height()).max(1);
+            canvas.set_draw_color(SPRITE_0_HIT_COLOR);
+            for left in [0, output_width as i32 - MARKER_WIDTH as i32] {
+                canvas
+                    .fill_rect(Rect::new(left, top, MARKER_WIDTH, height))
+                    .expect("could not draw the sprite 0 hit marker");
+            }
+        }
+    }
+    let show = |scanline: Option<u16>| scanline.map_or("-".to_string(), |x| x.to_string());
+    let is_vblank = system.get_devices().get_ppu().is_vblank_flag_set();
+    font.render_to_canvas(
+        canvas,
+        TEXT_MARGIN,
+        output_height as i32 - font.get_glyph_height() as i32 - TEXT_MARGIN,
+        &format!(
+            "S0 hit: {}  NMI: {}  V-blank: {}",
+            show(system.get_sprite_0_hit_scanline()),
+            show(system.get_nmi_scanline()),
+            if is_vblank { "on" } else { "off" },
+        ),
+    );
+}